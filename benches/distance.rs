@@ -0,0 +1,102 @@
+// Distance computation: per point vs. batched (`cargo +nightly bench`,
+// compare with `--features simd`)
+
+#![feature(test)]
+
+extern crate test;
+extern crate sdf_text;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use test::{Bencher, black_box};
+use sdf_text::*;
+
+// Allocator counting the allocations, to check the distance queries make none
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// Polygon approximating a circle, with a scanline of query points across it
+fn polygon_and_row() -> (OutlineDistance, Vec<Vec2>) {
+    let mut outline = OutlineDistance::new();
+    let n = 64;
+    let point = |i: usize| {
+        let a = i as f32 / n as f32 * 2.0 * std::f32::consts::PI;
+        Vec2::new(50.0 + 40.0 * a.cos(), 50.0 + 40.0 * a.sin())
+    };
+    for i in 0 .. n {
+        outline.push_line(point(i), point(i + 1));
+    }
+    let row = (0 .. 100).map(|x| Vec2::new(x as f32 + 0.5, 30.5)).collect();
+    (outline, row)
+}
+
+#[bench]
+fn bench_distance(b: &mut Bencher) {
+    let (outline, row) = polygon_and_row();
+    let mut out = vec![0.0; row.len()];
+    b.iter(|| {
+        for (p, dist) in row.iter().zip(out.iter_mut()) {
+            *dist = outline.distance(*p);
+        }
+        black_box(&out);
+    });
+}
+
+// Same with the spatial index (see `OutlineDistance::finalize`)
+#[bench]
+fn bench_distance_indexed(b: &mut Bencher) {
+    let (mut outline, row) = polygon_and_row();
+    outline.finalize();
+    let mut out = vec![0.0; row.len()];
+    b.iter(|| {
+        for (p, dist) in row.iter().zip(out.iter_mut()) {
+            *dist = outline.distance(*p);
+        }
+        black_box(&out);
+    });
+}
+
+#[bench]
+fn bench_distances(b: &mut Bencher) {
+    let (outline, row) = polygon_and_row();
+    let mut out = vec![0.0; row.len()];
+    b.iter(|| {
+        outline.distances(&row, &mut out);
+        black_box(&out);
+    });
+}
+
+// Distance to the curves (allocation-free nearest point search)
+#[bench]
+fn bench_curve_distance(b: &mut Bencher) {
+    let mut outline = OutlineDistance::new();
+    outline.push_bezier2(Vec2::new(10.0, 10.0), Vec2::new(50.0, 90.0), Vec2::new(90.0, 10.0));
+    outline.push_bezier3(Vec2::new(10.0, 10.0), Vec2::new(30.0, 90.0),
+                         Vec2::new(70.0, -50.0), Vec2::new(90.0, 10.0));
+    let (_, row) = polygon_and_row();
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    for p in row.iter() {
+        black_box(outline.distance(*p));
+    }
+    assert_eq!(ALLOCATIONS.load(Ordering::SeqCst), before, "distance() allocated");
+    b.iter(|| {
+        for p in row.iter() {
+            black_box(outline.distance(*p));
+        }
+    });
+}
@@ -1,4 +1,7 @@
 /* Render font texture
+ *
+ * Usage: font [FONT_FILE] [CHARS] [--png]
+ *   --png              save the texture to font.png ("png" feature)
  *
  * Controls:
  *   Escape             quit
@@ -58,8 +61,9 @@ fn main() {
     let printable_ascii: Vec<u8> = (0x20u8 .. 0x7Eu8).collect();
     let printable_ascii = std::str::from_utf8(&printable_ascii).unwrap();
 
-    // Parse args
-    let mut args = env::args();
+    // Parse args (`--png` saves the texture as font.png)
+    let save_png = env::args().any(|arg| arg == "--png");
+    let mut args = env::args().filter(|arg| arg != "--png");
     let font_name = args.nth(1).unwrap_or("assets/FreeSans.ttf".to_string());
     let char_list = args.next().unwrap_or(printable_ascii.to_string());
 
@@ -67,11 +71,14 @@ fn main() {
     let face_size = 128;
     let mut font = Font::new(1024);
     let t_start = time::Instant::now();
-    font.build_from_file(font_name, 0, face_size, 3, char_list.as_str());
+    font.build_from_file(font_name, 0, face_size, 3, char_list.as_str()).unwrap();
     let t_end = time::Instant::now();
     let d = t_end.duration_since(t_start);
     println!("Render font texture: face size {} in {}s",
              face_size, d.as_secs() as f32 + d.subsec_nanos() as f32 / 1e9);
+    if save_png {
+        save_texture(&font, "font.png");
+    }
 
     // Create OpenGL window
     let mut events_loop = glium::glutin::EventsLoop::new();
@@ -92,7 +99,7 @@ fn main() {
     let program = match glium::Program::from_source(&display, VERTEX_SHADER, FRAGMENT_SHADER, None) {
         Ok(res) => res,
         Err(glium::program::ProgramCreationError::CompilationError(err)) => {
-            println!("Shader compile error:\n{}", err);
+            eprintln!("Shader compile error:\n{}", err);
             return;
         },
         Err(other) => panic!(other),
@@ -176,3 +183,16 @@ fn main() {
         );
     }
 }
+
+#[cfg(feature = "png")]
+fn save_texture(font: &Font, path: &str) {
+    match font.save_png(path) {
+        Ok(()) => println!("Saved font texture to {}", path),
+        Err(err) => eprintln!("Failed to save {}: {}", path, err),
+    }
+}
+
+#[cfg(not(feature = "png"))]
+fn save_texture(_font: &Font, _path: &str) {
+    eprintln!("Saving PNG needs the \"png\" feature");
+}
@@ -99,9 +99,13 @@ const FRAGMENT_SHADER_OUTLINED: &'static str = r#"
 "#;
 
 const PADDING: u32 = 3;
-const SHIFT: f32 = 0.5;
 const FACE_SIZE: u32 = 128;
 
+// Glyph box rounded to nearest pixel, shared by the SDF and monochrome renderers
+fn glyph_config() -> FontConfig {
+    FontConfig { box_rounding: BoxRounding::Nearest, .. Default::default() }
+}
+
 enum Renderer {
     Sdf,
     Monochrome,
@@ -111,173 +115,51 @@ enum Renderer {
 fn glyph_to_sdf<'a>(c: char, face: &'a ft::Face) -> glium::texture::RawImage2d<'a, u8> {
     // Make SDF texture from the glyph
     let t_start = time::Instant::now();
-    face.set_pixel_sizes(face.em_size() as u32, 0).unwrap();
-    face.load_char(c as usize, ft::face::NO_HINTING).unwrap();
-    let outline = face.glyph().outline().unwrap();
-    let bbox = face.glyph().get_glyph().unwrap().get_cbox(0);
-    let pxsize = face.em_size() as f32 * 64. / FACE_SIZE as f32;
-    let xmin = (bbox.xMin as f32 / pxsize + SHIFT).floor();
-    let ymin = (bbox.yMin as f32 / pxsize + SHIFT).floor();
-    let xmax = (bbox.xMax as f32 / pxsize + SHIFT).floor();
-    let ymax = (bbox.yMax as f32 / pxsize + SHIFT).floor();
-    let w = ((xmax - xmin) + 2.0 * PADDING as f32) as u32;
-    let h = ((ymax - ymin) + 2.0 * PADDING as f32) as u32;
-    let origin = Vec2::new((xmin - PADDING as f32 + SHIFT),
-                           (ymin - PADDING as f32 + SHIFT));
-    let mut buffer = Vec::<u8>::with_capacity((w * h) as usize);
-    // Reversed contour orientation (counter-clockwise filled)
-    let outline_flags = face.glyph().raw().outline.flags;
-    let reverse_fill = (outline_flags & 0x4) == 0x4; // FT_OUTLINE_REVERSE_FILL;
-
-    // Feed the outline segments into rasterizer. These are later queried
-    // for scanline crossings and minimum distance from a point to the outline.
-    let mut rasterizer = Rasterizer::new();
-    let mut mindist = OutlineDistance::new();
-    for contour in outline.contours_iter() {
-        let mut p0 = vec2_from_ft(contour.start(), pxsize);
-        for curve in contour {
-            match curve {
-                ft::outline::Curve::Line(a) => {
-                    let p1 = vec2_from_ft(a, pxsize);
-                    rasterizer.push_line(p0, p1);
-                    mindist.push_line(p0, p1);
-                    p0 = p1;
-                }
-                ft::outline::Curve::Bezier2(a, b) => {
-                    let p1 = vec2_from_ft(a, pxsize);
-                    let p2 = vec2_from_ft(b, pxsize);
-                    rasterizer.push_bezier2(p0, p1, p2);
-                    mindist.push_bezier2(p0, p1, p2);
-                    p0 = p2;
-                }
-                ft::outline::Curve::Bezier3(a, b, c) => {
-                    let p1 = vec2_from_ft(a, pxsize);
-                    let p2 = vec2_from_ft(b, pxsize);
-                    let p3 = vec2_from_ft(c, pxsize);
-                    rasterizer.push_bezier3(p0, p1, p2, p3);
-                    mindist.push_bezier3(p0, p1, p2, p3);
-                    p0 = p3;
-                }
-            }
-        }
-    }
-
-    for yr in (0..h).rev() {
-        let y = origin.y + yr as f32;
-
-        let ref mut crossings = rasterizer.scanline_crossings(y);
-
-        // Find point distance
-        let mut crossings_idx = 0;
-        let mut wn = 0i32;
-        for xr in 0 .. w {
-            let x = origin.x + xr as f32;
-            let mp = Vec2::new(x, y);
-
-            // Compute the distance
-            let mut dist_min = mindist.distance(mp);
-
-            // Is the point inside curve?
-            while crossings.len() > crossings_idx && crossings[crossings_idx].x <= x {
-                wn += crossings[crossings_idx].dir as i32;
-                crossings_idx += 1;
-            }
-            let inside = if reverse_fill { wn < 0 } else { wn > 0 };
-            if inside {
-                dist_min = -dist_min;
-            }
-
-            // Convert float distance to discrete space (u8):
-            // 0 << 127 = outside
-            // 127 = zero distance (the outline)
-            // 128 >> 255 = inside
-            let shift = 127.0;
-            let scale = 1920. / FACE_SIZE as f32;
-            dist_min = shift - dist_min * scale;
-            if dist_min < 0. { dist_min = 0.; }
-            if dist_min > 255. { dist_min = 255.; }
-            buffer.push(dist_min as u8);
-        }
-    }
+    let (buffer, glyph) = render_glyph_sdf(face, c, FACE_SIZE as usize, PADDING as usize,
+                                           &glyph_config(), &SdfParams::default()).unwrap();
     face.set_pixel_sizes(FACE_SIZE, 0).unwrap();
     let t_end = time::Instant::now();
     let d = t_end.duration_since(t_start);
     println!("Render: size {}x{} in {}s (SDF)",
-             w, h, d.as_secs() as f32 + d.subsec_nanos() as f32 / 1e9);
+             glyph.width, glyph.height, d.as_secs() as f32 + d.subsec_nanos() as f32 / 1e9);
     glium::texture::RawImage2d {
         data: buffer.into(),
-        width: w as u32,
-        height: h as u32,
+        width: glyph.width as u32,
+        height: glyph.height as u32,
         format: glium::texture::ClientFormat::U8,
     }
 }
 
 fn glyph_to_image<'a>(c: char, face: &'a ft::Face) -> glium::texture::RawImage2d<'a, u8> {
-    // Make SDF texture from the glyph
+    // Make monochrome texture from the glyph, sampled at the same pixel
+    // centers as the SDF (the box and outline come from the library)
     let t_start = time::Instant::now();
     face.set_pixel_sizes(face.em_size() as u32, 0).unwrap();
-    face.load_char(c as usize, ft::face::NO_HINTING).unwrap();
-    let outline = face.glyph().outline().unwrap();
-    let bbox = face.glyph().get_glyph().unwrap().get_cbox(0);
-    let pxsize = face.em_size() as f32 * 64. / FACE_SIZE as f32;
-    let xmin = (bbox.xMin as f32 / pxsize + SHIFT).floor();
-    let ymin = (bbox.yMin as f32 / pxsize + SHIFT).floor();
-    let xmax = (bbox.xMax as f32 / pxsize + SHIFT).floor();
-    let ymax = (bbox.yMax as f32 / pxsize + SHIFT).floor();
-    let w = ((xmax - xmin) + 2.0 * PADDING as f32) as u32;
-    let h = ((ymax - ymin) + 2.0 * PADDING as f32) as u32;
-    let origin = Vec2::new((xmin - PADDING as f32 + SHIFT ),
-                           (ymin - PADDING as f32 + SHIFT));
+    let (glyph, outline) = Glyph::load_char(face, c, FACE_SIZE as usize, PADDING as usize,
+                                            &glyph_config());
+    let (w, h) = (glyph.width as u32, glyph.height as u32);
     let mut buffer = Vec::<u8>::with_capacity((w * h) as usize);
-    // Reversed contour orientation (counter-clockwise filled)
-    let outline_flags = face.glyph().raw().outline.flags;
-    let reverse_fill = (outline_flags & 0x4) == 0x4; // FT_OUTLINE_REVERSE_FILL;
-
-    // Feed the outline segments into rasterizer. These are later queried
-    // for scanline crossings and minimum distance from a point to the outline.
-    let mut rasterizer = Rasterizer::new();
-    for contour in outline.contours_iter() {
-        let mut p0 = vec2_from_ft(contour.start(), pxsize);
-        for curve in contour {
-            match curve {
-                ft::outline::Curve::Line(a) => {
-                    let p1 = vec2_from_ft(a, pxsize);
-                    rasterizer.push_line(p0, p1);
-                    p0 = p1;
-                }
-                ft::outline::Curve::Bezier2(a, b) => {
-                    let p1 = vec2_from_ft(a, pxsize);
-                    let p2 = vec2_from_ft(b, pxsize);
-                    rasterizer.push_bezier2(p0, p1, p2);
-                    p0 = p2;
-                }
-                ft::outline::Curve::Bezier3(a, b, c) => {
-                    let p1 = vec2_from_ft(a, pxsize);
-                    let p2 = vec2_from_ft(b, pxsize);
-                    let p3 = vec2_from_ft(c, pxsize);
-                    rasterizer.push_bezier3(p0, p1, p2, p3);
-                    p0 = p3;
-                }
-            };
-        }
-    }
 
+    // The outline segments are later queried for scanline crossings
+    let rasterizer = outline.rasterizer();
+
+    let mut crossings = Vec::new();
     for yr in (0..h).rev() {
-        let y = origin.y + yr as f32;
+        let y = (glyph.ymin + yr as isize) as f32 + 0.5;
 
-        let ref mut crossings = rasterizer.scanline_crossings(y);
+        rasterizer.scanline_crossings_into(y, &mut crossings);
 
         let mut crossings_idx = 0;
         let mut wn = 0i32;
         for xr in 0 .. w {
-            let x = origin.x + xr as f32;
+            let x = (glyph.xmin + xr as isize) as f32 + 0.5;
 
             // Is the point inside curve?
             while crossings.len() > crossings_idx && crossings[crossings_idx].x <= x {
                 wn += crossings[crossings_idx].dir as i32;
                 crossings_idx += 1;
             }
-            let inside = if reverse_fill { wn < 0 } else { wn > 0 };
+            let inside = FillRule::NonZero.is_inside(wn);
 
             buffer.push(inside as u8 * 255u8);
         }
@@ -354,7 +236,7 @@ fn main() {
     let program_direct = match glium::Program::from_source(&display, VERTEX_SHADER, FRAGMENT_SHADER, None) {
         Ok(res) => res,
         Err(glium::program::ProgramCreationError::CompilationError(err)) => {
-            println!("Shader compile error:\n{}", err);
+            eprintln!("Shader compile error:\n{}", err);
             return;
         },
         Err(other) => panic!(other),
@@ -362,7 +244,7 @@ fn main() {
     let program_sdf = match glium::Program::from_source(&display, VERTEX_SHADER, FRAGMENT_SHADER_SDF, None) {
         Ok(res) => res,
         Err(glium::program::ProgramCreationError::CompilationError(err)) => {
-            println!("Shader compile error:\n{}", err);
+            eprintln!("Shader compile error:\n{}", err);
             return;
         },
         Err(other) => panic!(other),
@@ -370,7 +252,7 @@ fn main() {
     let program_outlined = match glium::Program::from_source(&display, VERTEX_SHADER, FRAGMENT_SHADER_OUTLINED, None) {
         Ok(res) => res,
         Err(glium::program::ProgramCreationError::CompilationError(err)) => {
-            println!("Shader compile error:\n{}", err);
+            eprintln!("Shader compile error:\n{}", err);
             return;
         },
         Err(other) => panic!(other),
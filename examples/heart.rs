@@ -0,0 +1,188 @@
+/* Render SDF of a shape built from drawing commands (no font needed)
+ *
+ * Controls:
+ *   Escape             quit
+ *   mouse wheel        zoom in/out
+ */
+
+#[macro_use] extern crate glium;
+extern crate sdf_text;
+
+use std::time;
+
+use glium::{glutin, Surface};
+use glium::glutin::{Event, WindowEvent, ElementState, VirtualKeyCode, MouseScrollDelta, TouchPhase};
+
+use sdf_text::*;
+
+#[derive(Copy, Clone)]
+struct Vertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+}
+implement_vertex!(Vertex, position, tex_coords);
+
+const VERTEX_SHADER: &'static str = r#"
+    #version 140
+
+    in vec2 position;
+    in vec2 tex_coords;
+    out vec2 v_tex_coords;
+
+    uniform mat4 projection;
+    uniform mat4 model;
+
+    void main() {
+        v_tex_coords = tex_coords;
+        gl_Position = projection * model * vec4(position, 0.0, 1.0);
+    }
+"#;
+
+const FRAGMENT_SHADER: &'static str = r#"
+    #version 140
+
+    in vec2 v_tex_coords;
+    out vec4 color;
+
+    uniform sampler2D tex;
+
+    const vec3 c_inside = vec3(0.8, 0.0, 0.1);
+    const vec3 c_outside = vec3(0.0, 0.0, 0.0);
+
+    void main() {
+        float w = texture(tex, v_tex_coords).r;
+        float aaw = 0.5 * fwidth(w);
+        float alpha = smoothstep(0.50 - aaw, 0.50 + aaw, w);
+        color = vec4(mix(c_outside, c_inside, alpha), 1.0);
+    }
+"#;
+
+// Heart shape, about 100 x 85 pixels, clockwise contour
+fn heart() -> Outline {
+    let mut builder = OutlineBuilder::new();
+    builder.move_to(Vec2::new(50.0, 0.0));
+    builder.curve_to(Vec2::new(35.0, 15.0), Vec2::new(0.0, 35.0), Vec2::new(0.0, 60.0));
+    builder.curve_to(Vec2::new(0.0, 80.0), Vec2::new(25.0, 100.0), Vec2::new(50.0, 75.0));
+    builder.curve_to(Vec2::new(75.0, 100.0), Vec2::new(100.0, 80.0), Vec2::new(100.0, 60.0));
+    builder.curve_to(Vec2::new(100.0, 35.0), Vec2::new(65.0, 15.0), Vec2::new(50.0, 0.0));
+    builder.finish()
+}
+
+fn main() {
+    // Render the SDF (OpenGL not needed yet)
+    let face_size = 100;
+    let outline = heart();
+    let t_start = time::Instant::now();
+    let (xmin, ymin, xmax, ymax) = outline.control_box();
+    let glyph = Glyph::from_bbox(xmin, ymin, xmax, ymax, 3, BoxRounding::Expand);
+    let mut buffer = vec![0u8; glyph.width * glyph.height];
+    glyph.render_outline_sdf(&outline, face_size, &SdfParams::default(), &mut buffer, glyph.width);
+    let t_end = time::Instant::now();
+    let d = t_end.duration_since(t_start);
+    println!("Render: size {}x{} in {}s (SDF)",
+             glyph.width, glyph.height, d.as_secs() as f32 + d.subsec_nanos() as f32 / 1e9);
+
+    // Create OpenGL window
+    let mut events_loop = glium::glutin::EventsLoop::new();
+    let window = glutin::WindowBuilder::new();
+    let context = glium::glutin::ContextBuilder::new();
+    let display = glium::Display::new(window, context, &events_loop).unwrap();
+
+    // Prepare quad
+    let vertex1 = Vertex { position: [ -0.5, -0.5], tex_coords: [0.0, 1.0] };
+    let vertex2 = Vertex { position: [  0.5, -0.5], tex_coords: [1.0, 1.0] };
+    let vertex3 = Vertex { position: [ -0.5,  0.5], tex_coords: [0.0, 0.0] };
+    let vertex4 = Vertex { position: [  0.5,  0.5], tex_coords: [1.0, 0.0] };
+    let quad = vec![vertex1, vertex2, vertex3, vertex4];
+    let quad_buffer = glium::VertexBuffer::new(&display, &quad).unwrap();
+    let quad_indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+
+    // Prepare shaders and draw params
+    let program = match glium::Program::from_source(&display, VERTEX_SHADER, FRAGMENT_SHADER, None) {
+        Ok(res) => res,
+        Err(glium::program::ProgramCreationError::CompilationError(err)) => {
+            eprintln!("Shader compile error:\n{}", err);
+            return;
+        },
+        Err(other) => panic!(other),
+    };
+    let params = glium::DrawParameters {
+        backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockwise,
+        .. Default::default()
+    };
+
+    // Transform SDF texture to GL
+    let image = glium::texture::RawImage2d {
+        data: buffer.into(),
+        width: glyph.width as u32,
+        height: glyph.height as u32,
+        format: glium::texture::ClientFormat::U8,
+    };
+    let texture = glium::texture::Texture2d::new(&display, image).unwrap();
+
+    let mut zoom = 2.0;
+    let mut quit = false;
+    while !quit {
+        // Draw frame
+        {
+            let mut target = display.draw();
+
+            // Prepare projection matrix
+            let (width, height) = target.get_dimensions();
+            let aspect_ratio = width as f32 / height as f32;
+            let image_aspect = glyph.width as f32 / glyph.height as f32;
+            let projection = [
+                [zoom * image_aspect / aspect_ratio, 0.0, 0.0, 0.0],
+                [0.0, zoom, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0f32],
+            ];
+            let model = [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0f32],
+            ];
+
+            let texture_sampler = glium::uniforms::Sampler::new(&texture)
+                        .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear)
+                        .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp);
+
+            target.clear_color(0.0, 0.0, 0.1, 1.0);
+            target.draw(&quad_buffer, &quad_indices, &program,
+                        &uniform! { projection: projection, model: model, tex: texture_sampler, },
+                        &params).unwrap();
+            target.finish().unwrap();
+        }
+        // Handle events
+        events_loop.poll_events(|event|
+            match event {
+                Event::WindowEvent { event, .. } => match event {
+                    WindowEvent::Closed => quit = true,
+                    WindowEvent::KeyboardInput { input, .. } => {
+                        if input.state == ElementState::Pressed {
+                            match input.virtual_keycode {
+                                Some(VirtualKeyCode::Escape) => quit = true,
+                                _ => ()
+                            }
+                        }
+                    }
+                    WindowEvent::MouseWheel { delta, phase: TouchPhase::Moved, .. } => {
+                        match delta {
+                            MouseScrollDelta::LineDelta(_, y) => {
+                                zoom += y * zoom / 4.0;
+                                if zoom < 0.01 { zoom = 0.01; }
+                            }
+                            MouseScrollDelta::PixelDelta(_, y) => {
+                                zoom += y * zoom / 40.0;
+                                if zoom < 0.01 { zoom = 0.01; }
+                            }
+                        }
+                    },
+                    _ => ()
+                },
+                _ => ()
+            }
+        );
+    }
+}
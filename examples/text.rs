@@ -66,15 +66,12 @@ fn main() {
     let input_text = args.next().unwrap_or("Hello world!".to_string());
 
     // Extract set of characters from input text
-    let mut char_list: Vec<char> = input_text.chars().collect();
-    char_list.sort();
-    char_list.dedup();
-    let char_list: String = char_list.into_iter().collect();
+    let char_list = chars_from_corpus(&[&input_text]);
 
     // Build font texture (OpenGL not needed yet)
     let face_size = 256;
     let mut font = Font::new(1024);
-    font.build_from_file(font_name, 0, face_size, 3, char_list.as_str());
+    font.build_from_file(font_name, 0, face_size, 3, char_list.as_str()).unwrap();
 
     // Create OpenGL window
     let mut events_loop = glium::glutin::EventsLoop::new();
@@ -86,25 +83,29 @@ fn main() {
     let num_chars = input_text.chars().count();
     let mut vertices = Vec::with_capacity(num_chars * 4);
     let mut indices = Vec::with_capacity(num_chars * 6);
-    let mut xpos = 0f32;
+    // Em to window coordinates, the pen starts at the left edge
+    let scale = 1.5 / num_chars as f32;
+    let mut pen = Vec2::new(-0.9 / scale, 0.0);
+    let mut prev = None;
     for ch in input_text.chars() {
-        // Font texture coords
-        let glyph_coords = font.glyphs.get(&ch).unwrap();
-        let x1 = glyph_coords.x as f32 / font.width as f32 ;
-        let y1 = glyph_coords.y as f32 / font.height as f32;
-        let x2 = (glyph_coords.x + glyph_coords.width) as f32 / font.width as f32;
-        let y2 = (glyph_coords.y + glyph_coords.height) as f32 / font.height as f32;
-
-        // Vertex coords, indices
-        // TODO: position
-        let vertex1 = Vertex { position: [ -0.6 + xpos, -0.5], tex_coords: [x1, y2] };
-        let vertex2 = Vertex { position: [ -0.5 + xpos, -0.5], tex_coords: [x2, y2] };
-        let vertex3 = Vertex { position: [ -0.6 + xpos,  0.5], tex_coords: [x1, y1] };
-        let vertex4 = Vertex { position: [ -0.5 + xpos,  0.5], tex_coords: [x2, y1] };
+        if let Some(prev) = prev {
+            pen.x += font.kerning(prev, ch).x / face_size as f32;
+        }
+        let quad = match font.glyph_quad(ch, pen) {
+            Some(quad) => quad,
+            None => continue,
+        };
+        prev = Some(ch);
+
+        // Vertex coords (the glyph box relative to the pen on the baseline), indices
+        let (left, bottom, right, top) = quad.rect;
+        let corners = [(left, bottom), (right, bottom), (left, top), (right, top)];
         let n = vertices.len() as u16;
-        vertices.append(&mut vec![vertex1, vertex2, vertex3, vertex4]);
+        for (&(x, y), uv) in corners.iter().zip(quad.uvs.iter()) {
+            vertices.push(Vertex { position: [x * scale, y * scale], tex_coords: [uv.x, uv.y] });
+        }
         indices.append(&mut vec![n, n+1, n+2, n+2, n+1, n+3]);
-        xpos += 0.1;
+        pen.x += quad.advance;
     }
 
     let vertex_buffer = glium::VertexBuffer::new(&display, &vertices).unwrap();
@@ -114,7 +115,7 @@ fn main() {
     let program = match glium::Program::from_source(&display, VERTEX_SHADER, FRAGMENT_SHADER, None) {
         Ok(res) => res,
         Err(glium::program::ProgramCreationError::CompilationError(err)) => {
-            println!("Shader compile error:\n{}", err);
+            eprintln!("Shader compile error:\n{}", err);
             return;
         },
         Err(other) => panic!(other),
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use freetype as ft;
+
+use font::*;
+use error::FontError;
+
+/// Identifier of a face in `SdfCache` (assigned by the user)
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FaceId(pub usize);
+
+/// Key of a glyph in `SdfCache`
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SdfCacheKey {
+    pub face: FaceId,
+    pub face_size: usize,
+    pub padding: usize,
+    // bits of `SdfParams::em_spread` (f32 can't be hashed), 0 = the default scale
+    pub spread_bits: u32,
+    pub ch: char,
+}
+
+/// Rendered glyph SDF (standalone buffer, pitch = glyph.width)
+
+#[derive(Clone, Debug)]
+pub struct CachedGlyph {
+    pub buffer: Vec<u8>,
+    pub glyph: Glyph,
+}
+
+/// Memoized single-glyph SDFs, keyed by (face, size, spread, char)
+///
+/// The glyphs are rendered with `render_glyph_sdf` on first use.
+/// With non-zero `capacity`, the least recently used glyph is evicted
+/// when the cache is full, the eviction hook is called with it
+/// (e.g. to free its place in user's atlas). Setting new config or params
+/// evicts all glyphs, as they were rendered with the old ones.
+
+pub struct SdfCache {
+    config: FontConfig,
+    params: SdfParams,
+    // maximum number of glyphs (0 = unlimited)
+    pub capacity: usize,
+    entries: HashMap<SdfCacheKey, (CachedGlyph, u64)>,
+    clock: u64,
+    on_evict: Option<Box<FnMut(SdfCacheKey, CachedGlyph)>>,
+}
+
+impl SdfCache {
+    pub fn new(capacity: usize) -> Self {
+        SdfCache {
+            config: FontConfig::default(),
+            params: SdfParams::default(),
+            capacity: capacity,
+            entries: HashMap::new(),
+            clock: 0,
+            on_evict: None,
+        }
+    }
+
+    // Set the eviction hook
+    pub fn on_evict<F>(&mut self, hook: F)
+        where F: FnMut(SdfCacheKey, CachedGlyph) + 'static
+    {
+        self.on_evict = Some(Box::new(hook));
+    }
+
+    pub fn config(&self) -> &FontConfig {
+        &self.config
+    }
+
+    pub fn params(&self) -> &SdfParams {
+        &self.params
+    }
+
+    // Set the config for new glyphs, evicting all cached glyphs
+    pub fn set_config(&mut self, config: FontConfig) {
+        self.evict_all();
+        self.config = config;
+    }
+
+    // Set the SDF params for new glyphs, evicting all cached glyphs
+    pub fn set_params(&mut self, params: SdfParams) {
+        self.evict_all();
+        self.params = params;
+    }
+
+    pub fn key(&self, face: FaceId, face_size: usize, padding: usize, ch: char) -> SdfCacheKey {
+        SdfCacheKey {
+            face: face,
+            face_size: face_size,
+            padding: padding,
+            spread_bits: self.params.em_spread.map_or(0, |s| s.to_bits()),
+            ch: ch,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &SdfCacheKey) -> Option<&CachedGlyph> {
+        self.entries.get(key).map(|entry| &entry.0)
+    }
+
+    // Get the glyph from the cache, or render it from the face
+    pub fn get_or_render(&mut self, face_id: FaceId, face: &ft::Face, ch: char,
+                         face_size: usize, padding: usize) -> Result<&CachedGlyph, FontError> {
+        let key = self.key(face_id, face_size, padding, ch);
+        let rendered = if self.entries.contains_key(&key) {
+            None
+        } else {
+            let (buffer, glyph) = render_glyph_sdf(face, ch, face_size, padding,
+                                                   &self.config, &self.params)?;
+            Some(CachedGlyph { buffer: buffer, glyph: glyph })
+        };
+        Ok(self.get_or_insert_with(key, || rendered.unwrap()))
+    }
+
+    // Get the glyph from the cache, or insert one made by `render`
+    pub fn get_or_insert_with<F>(&mut self, key: SdfCacheKey, render: F) -> &CachedGlyph
+        where F: FnOnce() -> CachedGlyph
+    {
+        self.clock += 1;
+        let clock = self.clock;
+        if !self.entries.contains_key(&key) {
+            if self.capacity > 0 && self.entries.len() >= self.capacity {
+                self.evict_lru();
+            }
+            self.entries.insert(key, (render(), clock));
+        }
+        let entry = self.entries.get_mut(&key).unwrap();
+        entry.1 = clock;
+        &entry.0
+    }
+
+    // Remove all glyphs (without calling the eviction hook)
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn evict_all(&mut self) {
+        for (key, (glyph, _)) in self.entries.drain() {
+            if let Some(ref mut hook) = self.on_evict {
+                hook(key, glyph);
+            }
+        }
+    }
+
+    fn evict_lru(&mut self) {
+        let lru = self.entries.iter()
+            .min_by_key(|&(_, entry)| entry.1)
+            .map(|(key, _)| *key);
+        if let Some(key) = lru {
+            let (glyph, _) = self.entries.remove(&key).unwrap();
+            if let Some(ref mut hook) = self.on_evict {
+                hook(key, glyph);
+            }
+        }
+    }
+}
+
+
+// Tests
+// -----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    fn tile(value: u8) -> CachedGlyph {
+        CachedGlyph {
+            buffer: vec![value; 4],
+            glyph: Glyph { width: 2, height: 2, .. Default::default() },
+        }
+    }
+
+    #[test]
+    fn test_get_or_insert() {
+        let mut cache = SdfCache::new(2);
+        let evicted = Rc::new(RefCell::new(Vec::new()));
+        {
+            let evicted = evicted.clone();
+            cache.on_evict(move |key, _| evicted.borrow_mut().push(key.ch));
+        }
+        let key_a = cache.key(FaceId(0), 32, 2, 'a');
+        let key_b = cache.key(FaceId(0), 32, 2, 'b');
+        let key_c = cache.key(FaceId(1), 32, 2, 'a');
+        assert_eq!(cache.get_or_insert_with(key_a, || tile(1)).buffer[0], 1);
+        // Cached, not rendered again
+        assert_eq!(cache.get_or_insert_with(key_a, || tile(2)).buffer[0], 1);
+        cache.get_or_insert_with(key_b, || tile(3));
+        cache.get_or_insert_with(key_a, || tile(4));
+        // Full, evicts least recently used 'b'
+        assert_eq!(cache.get_or_insert_with(key_c, || tile(5)).buffer[0], 5);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&key_b).is_none());
+        assert_eq!(*evicted.borrow(), vec!['b']);
+        // Different spread is a different key
+        let params = SdfParams { em_spread: Some(0.125), .. SdfParams::default() };
+        cache.set_params(params);
+        assert!(cache.key(FaceId(0), 32, 2, 'a') != key_a);
+        // The glyphs rendered with the old params are gone
+        assert!(cache.is_empty());
+        evicted.borrow_mut().sort();
+        assert_eq!(*evicted.borrow(), vec!['a', 'a', 'b']);
+        cache.get_or_insert_with(key_a, || tile(6));
+        cache.set_config(FontConfig::default());
+        assert!(cache.is_empty());
+        assert_eq!(cache.params().em_spread, Some(0.125));
+    }
+}
@@ -1,10 +1,11 @@
 use std;
 use std::f32;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use roots;
 
 /// 2D vector / point
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Vec2 {
     pub x: f32,
     pub y: f32,
@@ -27,6 +28,25 @@ impl Vec2 {
     pub fn lerp(self, other: Vec2, t: f32) -> Vec2 {
         (1.0 - t) * self + t * other
     }
+    // rotation by 90 degrees (counter-clockwise with Y axis going up)
+    pub fn perp(self) -> Vec2 {
+        Vec2 { x: -self.y, y: self.x }
+    }
+    // unit vector of same direction (zero vector stays zero, as does a vector
+    // too short for its magnitude to be represented)
+    pub fn normalize(self) -> Vec2 {
+        let m = self.magnitude();
+        if m > 0.0 { Vec2::new(self.x / m, self.y / m) } else { Vec2::new(0.0, 0.0) }
+    }
+    // 2D cross product (z of the 3D one), positive when `other`
+    // is counter-clockwise from `self`
+    pub fn cross(self, other: Vec2) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
+    // distance between two points
+    pub fn distance(self, other: Vec2) -> f32 {
+        (other - self).magnitude()
+    }
 }
 
 impl std::ops::Add for Vec2 {
@@ -53,31 +73,79 @@ impl std::ops::Mul<Vec2> for f32 {
 // Equation solvers
 // ----------------
 
-const EPS: f32 = 5e-5;
+// Precision of the internal distance and intersection math.
+// Feature "f64" computes in double precision (converting the inputs up
+// and the results down), trading speed for accuracy near degenerate curves.
+#[cfg(not(feature = "f64"))]
+type Real = f32;
+#[cfg(feature = "f64")]
+type Real = f64;
+
+const EPS: Real = 5e-5;
+const INFINITY: Real = 1.0 / 0.0;
+
+// Convergence of the root finding for nearest point on cubic curve
+#[cfg(not(feature = "f64"))]
+const BRENT_EPS: Real = 2e-5;
+#[cfg(feature = "f64")]
+const BRENT_EPS: Real = 1e-12;
+
+// Number of times the single-root solvers found no root in range
+// and fell back to the nearest end point (see `root_fallback_count`)
+static ROOT_FALLBACKS: AtomicUsize = AtomicUsize::new(0);
+
+// How many times the intersection solvers didn't find the root in range 0..1
+// and used the end point of the curve instead (a debugging aid, the count
+// is global for the process)
+pub fn root_fallback_count() -> usize {
+    ROOT_FALLBACKS.load(Ordering::Relaxed)
+}
 
 // These solvers are used when we know in advance that the equation
 // has exactly one root in range 0..1. There might be other roots out
-// of this range - these are ignored.
-
-fn solve_quadratic_for_single_t(a2: f32, a1: f32, a0: f32) -> f32 {
-    for &t in roots::find_roots_quadratic(a2, a1, a0).as_ref() {
-        if t.is_finite() && t >= 0.0 && t <= 1.0 {
-            return t;
+// of this range - these are ignored. The range is widened by `EPS`
+// (the root is clamped back to 0..1), numerical error may push the root
+// at an end point slightly outside.
+
+// Root of the polynomial in the widened range, or the end point
+// where the polynomial is closer to zero
+fn single_t_in_range(found: &[Real], value_at_0: Real, value_at_1: Real) -> Real {
+    for &t in found {
+        if t.is_finite() && t >= -EPS && t <= 1.0 + EPS {
+            return t.max(0.0).min(1.0);
         }
     }
-    panic!("quadratic root not found");
+    ROOT_FALLBACKS.fetch_add(1, Ordering::Relaxed);
+    if value_at_0.abs() <= value_at_1.abs() { 0.0 } else { 1.0 }
+}
+
+fn solve_quadratic_for_single_t(a2: Real, a1: Real, a0: Real) -> Real {
+    single_t_in_range(roots::find_roots_quadratic(a2, a1, a0).as_ref(),
+                      a0, a2 + a1 + a0)
 }
 
-fn solve_cubic_for_single_t(a3: f32, a2: f32, a1: f32, a0: f32) -> f32 {
+fn solve_cubic_for_single_t(a3: Real, a2: Real, a1: Real, a0: Real) -> Real {
     if a3.abs() < EPS {
         return solve_quadratic_for_single_t(a2, a1, a0);
     }
-    for &t in roots::find_roots_cubic(a3, a2, a1, a0).as_ref() {
-        if t.is_finite() && t >= 0.0 && t <= 1.0 {
-            return t;
-        }
-    }
-    panic!("cubic root not found");
+    single_t_in_range(roots::find_roots_cubic(a3, a2, a1, a0).as_ref(),
+                      a0, a3 + a2 + a1 + a0)
+}
+
+// Coordinate of bézier curves at `t`, in `Real` precision
+fn bezier2(t: Real, a: f32, b: f32, c: f32) -> Real {
+    let tc = 1.0 - t;
+    tc*tc * a as Real + 2.0*tc*t * b as Real + t*t * c as Real
+}
+
+fn bezier3(t: Real, a: f32, b: f32, c: f32, d: f32) -> Real {
+    let tc = 1.0 - t;
+    tc*tc*tc * a as Real + 3.0*tc*tc*t * b as Real + 3.0*tc*t*t * c as Real + t*t*t * d as Real
+}
+
+fn bezier3_derivative(t: Real, a: f32, b: f32, c: f32, d: f32) -> Real {
+    let tc = 1.0 - t;
+    3.0*tc*tc * (b - a) as Real + 6.0*tc*t * (c - b) as Real + 3.0*t*t * (d - c) as Real
 }
 
 /// Linear segment
@@ -98,14 +166,36 @@ impl LinearSegment {
         }
     }
 
-    // Minimal distance from a point to the line segment
-    pub fn distance(&self, p: Vec2) -> f32 {
+    // Evaluate point on the segment at `t`
+    pub fn eval_point(&self, t: f32) -> Vec2 {
+        self.p0.lerp(self.p1, t)
+    }
+
+    // Evaluate tangent vector at `t` (first derivative)
+    pub fn eval_tangent(&self, _t: f32) -> Vec2 {
+        self.p1 - self.p0
+    }
+
+    // Nearest point on the line segment: distance from `p` and the parameter `t`
+    pub fn nearest(&self, p: Vec2) -> (f32, f32) {
         let m = p - self.p0;
         let a = self.p1 - self.p0;
         let t = (m.dot(a) / a.dot(a))
                 .max(0.0).min(1.0);
         let x = self.p0 + t * a;
-        (x - p).magnitude()
+        ((x - p).magnitude(), t)
+    }
+
+    // Nearest point on the line segment: distance from `p`, the parameter `t`
+    // and the point itself
+    pub fn distance_detailed(&self, p: Vec2) -> (f32, f32, Vec2) {
+        let (dist, t) = self.nearest(p);
+        (dist, t, self.eval_point(t))
+    }
+
+    // Minimal distance from a point to the line segment
+    pub fn distance(&self, p: Vec2) -> f32 {
+        self.distance_detailed(p).0
     }
 }
 
@@ -147,53 +237,84 @@ impl QuadraticSegment {
         2.0*tc*(self.p1 - self.p0) + 2.0*t*(self.p2 - self.p1)
     }
 
-    // Minimal distance from a point to the quadratic bézier segment
-    pub fn distance(&self, p: Vec2) -> f32 {
-        let m = self.p0 - p;
-        let a = self.p1 - self.p0;
-        let b = self.p2 - self.p1 - a;
+    // Split the curve at `t` (De Casteljau): the parts for 0..t and t..1
+    pub fn split(&self, t: f32) -> (QuadraticSegment, QuadraticSegment) {
+        let m0 = self.p0.lerp(self.p1, t);
+        let m1 = self.p1.lerp(self.p2, t);
+        let n0 = m0.lerp(m1, t);
+        (QuadraticSegment::new(self.p0, m0, n0), QuadraticSegment::new(n0, m1, self.p2))
+    }
+
+    // Nearest point on the quadratic bézier segment: distance from `p` and the parameter `t`
+    pub fn nearest(&self, p: Vec2) -> (f32, f32) {
+        let (p0, p1, p2) = (self.p0, self.p1, self.p2);
+        let (mx, my) = (p0.x as Real - p.x as Real, p0.y as Real - p.y as Real);
+        let (ax, ay) = (p1.x as Real - p0.x as Real, p1.y as Real - p0.y as Real);
+        let (bx, by) = (p2.x as Real - p1.x as Real - ax, p2.y as Real - p1.y as Real - ay);
         // Cubic equation coefficients
-        let a3 = b.dot(b);
-        let a2 = 3.0*a.dot(b);
-        let a1 = 2.0*a.dot(a) + m.dot(b);
-        let a0 = m.dot(a);
+        let a3 = bx*bx + by*by;
+        let a2 = 3.0*(ax*bx + ay*by);
+        let a1 = 2.0*(ax*ax + ay*ay) + mx*bx + my*by;
+        let a0 = mx*ax + my*ay;
         // Find roots of the equation (1 or 3 real roots)
-        let mut candidates = Vec::<Vec2>::with_capacity(5);
+        // (fixed array, no allocation in this hot path)
+        let mut candidates = [0.0 as Real; 5];
+        let mut n = 0;
         for &t in roots::find_roots_cubic(a3, a2, a1, a0).as_ref() {
             // Drop roots outside of curve interval
             if t >= 0.0 && t <= 1.0 {
-                // Compute point on the curve for each t
-                candidates.push(self.eval_point(t));
+                candidates[n] = t;
+                n += 1;
             }
         }
         // Add end points
-        candidates.push(self.p0);
-        candidates.push(self.p2);
+        candidates[n] = 0.0;
+        candidates[n + 1] = 1.0;
+        n += 2;
         // Find least distance point from candidates
-        let mut dist_min = f32::INFINITY;
-        for x in candidates.into_iter() {
+        let mut dist_min = INFINITY;
+        let mut t_min = 0.0;
+        for &t in candidates[.. n].iter() {
             // Actually, it's distance squared, but that's okay for comparison
-            let dist = (x - p).magnitude2();
+            let dx = bezier2(t, p0.x, p1.x, p2.x) - p.x as Real;
+            let dy = bezier2(t, p0.y, p1.y, p2.y) - p.y as Real;
+            let dist = dx*dx + dy*dy;
             if dist < dist_min {
                 dist_min = dist;
+                t_min = t;
             }
         }
-        dist_min.sqrt()
+        (dist_min.sqrt() as f32, t_min as f32)
+    }
+
+    // Nearest point on the quadratic bézier segment: distance from `p`, the parameter `t`
+    // and the point itself
+    pub fn distance_detailed(&self, p: Vec2) -> (f32, f32, Vec2) {
+        let (dist, t) = self.nearest(p);
+        (dist, t, self.eval_point(t))
+    }
+
+    // Minimal distance from a point to the quadratic bézier segment
+    pub fn distance(&self, p: Vec2) -> f32 {
+        self.distance_detailed(p).0
     }
 }
 
 // Find intersection between monotonic (growing) quadratic bezier and Y scanline
 pub fn quadratic_intersection(y: f32, p0: Vec2, p1: Vec2, p2: Vec2) -> f32 {
     debug_assert!(p0.y <= p1.y && p1.y <= p2.y);
-    let a2 = p0.y - 2.0*p1.y + p2.y;
-    let a1 = -2.0*p0.y + 2.0*p1.y;
-    let a0 = p0.y - y;
+    let (y0, y1, y2) = (p0.y as Real, p1.y as Real, p2.y as Real);
+    let a2 = y0 - 2.0*y1 + y2;
+    let a1 = -2.0*y0 + 2.0*y1;
+    let a0 = y0 - y as Real;
     let t = solve_quadratic_for_single_t(a2, a1, a0);
-    let tc = 1.0 - t;
-    tc*tc * p0.x + 2.0*tc*t * p1.x + t*t * p2.x
+    bezier2(t, p0.x, p1.x, p2.x) as f32
 }
 
 
+// Number of intervals searched for roots in `CubicSegment::nearest`
+const CUBIC_NEAREST_STEPS: usize = 15;
+
 /// Cubic bézier segment
 ///
 /// B(t) = (1-t)^3*p0 + 3*(1-t)^2*t*p1 + 3*(1-t)*t^2*p2 + t^3*p3; t = 0..1
@@ -228,51 +349,128 @@ impl CubicSegment {
         3.0*tc*tc*(self.p1 - self.p0) + 6.0*tc*t*(self.p2 - self.p1) + 3.0*t*t*(self.p3 - self.p2)
     }
 
-    // Minimal distance from a point to the cubic bézier segment
-    pub fn distance(&self, p: Vec2) -> f32 {
+    // Split the curve at `t` (De Casteljau): the parts for 0..t and t..1
+    pub fn split(&self, t: f32) -> (CubicSegment, CubicSegment) {
+        let m0 = self.p0.lerp(self.p1, t);
+        let m1 = self.p1.lerp(self.p2, t);
+        let m2 = self.p2.lerp(self.p3, t);
+        let n0 = m0.lerp(m1, t);
+        let n1 = m1.lerp(m2, t);
+        let o0 = n0.lerp(n1, t);
+        (CubicSegment::new(self.p0, m0, n0, o0), CubicSegment::new(o0, n1, m2, self.p3))
+    }
+
+    // Nearest point on the cubic bézier segment: distance from `p` and the parameter `t`
+    pub fn nearest(&self, p: Vec2) -> (f32, f32) {
+        self.nearest_with_steps(p, CUBIC_NEAREST_STEPS)
+    }
+
+    // Nearest point, with the range of `t` searched in `steps` intervals.
+    // The nearest point is a root of the dot product f(t) = (B(t) - p) . B'(t)
+    // (a polynomial of degree 5, up to 5 real roots) or an end point.
+    // The intervals are further split at the extrema of `f`, the roots
+    // of its derivative (quartic, solved analytically), so each part contains
+    // at most one root, even where the roots are close to each other.
+    pub fn nearest_with_steps(&self, p: Vec2, steps: usize) -> (f32, f32) {
+        assert!(steps > 0, "no steps");
+        let (p0, p1, p2, p3) = (self.p0, self.p1, self.p2, self.p3);
+        let (px, py) = (p.x as Real, p.y as Real);
         let f = |t| {
-            (self.eval_point(t) - p).dot(self.eval_tangent(t))
+            (bezier3(t, p0.x, p1.x, p2.x, p3.x) - px) * bezier3_derivative(t, p0.x, p1.x, p2.x, p3.x) +
+            (bezier3(t, p0.y, p1.y, p2.y, p3.y) - py) * bezier3_derivative(t, p0.y, p1.y, p2.y, p3.y)
         };
-        // Find roots of the equation (up to 5 real roots)
-        let mut candidates = Vec::<Vec2>::with_capacity(7);
-        let convergency = roots::SimpleConvergency { eps:2e-5f32, max_iter:100 };
-        let steps = 15;
-        let mut a = 0.0;
-        for t in 1 .. steps + 1 {
-            let b = t as f32 / steps as f32;
-            match roots::find_root_brent(a, b, &f, &convergency) {
-                // Compute point on the curve for each t
-                Ok(t) => candidates.push(self.eval_point(t)),
-                Err(_) => (),
+        // Actually, it's distance squared, but that's okay for the comparison
+        let dist = |t| {
+            let dx = bezier3(t, p0.x, p1.x, p2.x, p3.x) - px;
+            let dy = bezier3(t, p0.y, p1.y, p2.y, p3.y) - py;
+            dx*dx + dy*dy
+        };
+        // Start with the end points
+        let (mut dist_min, mut t_min) = (dist(0.0), 0.0);
+        let dist_end = dist(1.0);
+        if dist_end < dist_min {
+            dist_min = dist_end;
+            t_min = 1.0;
+        }
+        // Extrema of `f` in 0..1, sorted
+        let mut extrema = [0.0 as Real; 4];
+        let mut n = 0;
+        for &t in self.dot_derivative_roots(p).as_ref() {
+            if t.is_finite() && 0.0 < t && t < 1.0 {
+                extrema[n] = t;
+                n += 1;
             }
-            a = b;
         }
-        // Add end points
-        candidates.push(self.p0);
-        candidates.push(self.p3);
-        // Find least distance point from candidates
-        let mut dist_min = f32::INFINITY;
-        for x in candidates.into_iter() {
-            // Actually, it's distance squared, but that's okay for the comparison
-            let dist = (x - p).magnitude2();
-            if dist < dist_min {
-                dist_min = dist;
+        let extrema = &mut extrema[.. n];
+        extrema.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        // Find roots of the equation, at most one per interval
+        let convergency = roots::SimpleConvergency { eps: BRENT_EPS, max_iter: 100 };
+        let mut a = 0.0;
+        let mut next_extremum = 0;
+        for step in 1 .. steps + 1 {
+            let end = step as Real / steps as Real;
+            while a < end {
+                let b = match extrema.get(next_extremum) {
+                    Some(&t) if t < end => {
+                        next_extremum += 1;
+                        t
+                    }
+                    _ => end,
+                };
+                if let Ok(t) = roots::find_root_brent(a, b, &f, &convergency) {
+                    let d = dist(t);
+                    if d < dist_min {
+                        dist_min = d;
+                        t_min = t;
+                    }
+                }
+                a = b;
             }
         }
-        dist_min.sqrt()
+        (dist_min.sqrt() as f32, t_min as f32)
+    }
+
+    // Roots of the derivative of f(t) = (B(t) - p) . B'(t), see `nearest_with_steps`
+    fn dot_derivative_roots(&self, p: Vec2) -> roots::Roots<Real> {
+        // Power basis of the curve shifted by `p`: B(t) - p = a t^3 + b t^2 + c t + d
+        let coefs = |p0: f32, p1: f32, p2: f32, p3: f32, p: f32| {
+            let (p0, p1, p2, p3) = (p0 as Real, p1 as Real, p2 as Real, p3 as Real);
+            (-p0 + 3.0*p1 - 3.0*p2 + p3, 3.0*p0 - 6.0*p1 + 3.0*p2, -3.0*p0 + 3.0*p1, p0 - p as Real)
+        };
+        let (ax, bx, cx, dx) = coefs(self.p0.x, self.p1.x, self.p2.x, self.p3.x, p.x);
+        let (ay, by, cy, dy) = coefs(self.p0.y, self.p1.y, self.p2.y, self.p3.y, p.y);
+        // f'(t) = B'(t) . B'(t) + (B(t) - p) . B''(t)
+        let a4 = 15.0 * (ax*ax + ay*ay);
+        let a3 = 20.0 * (ax*bx + ay*by);
+        let a2 = 12.0 * (ax*cx + ay*cy) + 6.0 * (bx*bx + by*by);
+        let a1 = 6.0 * (bx*cx + by*cy) + 6.0 * (ax*dx + ay*dy);
+        let a0 = cx*cx + cy*cy + 2.0 * (bx*dx + by*dy);
+        roots::find_roots_quartic(a4, a3, a2, a1, a0)
+    }
+
+    // Nearest point on the cubic bézier segment: distance from `p`, the parameter `t`
+    // and the point itself
+    pub fn distance_detailed(&self, p: Vec2) -> (f32, f32, Vec2) {
+        let (dist, t) = self.nearest(p);
+        (dist, t, self.eval_point(t))
+    }
+
+    // Minimal distance from a point to the cubic bézier segment
+    pub fn distance(&self, p: Vec2) -> f32 {
+        self.distance_detailed(p).0
     }
 }
 
 // Find intersection between monotonic (growing) cubic bezier and Y scanline
 pub fn cubic_intersection(y: f32, p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2) -> f32 {
-    debug_assert!(p0.y < p1.y + EPS && p1.y < p2.y + EPS && p2.y < p3.y + EPS);
-    let a3 = -p0.y + 3.0*p1.y - 3.0*p2.y + p3.y;
-    let a2 = 3.0*p0.y - 6.0*p1.y + 3.0*p2.y;
-    let a1 = -3.0*p0.y + 3.0*p1.y;
-    let a0 = p0.y - y;
+    let (y0, y1, y2, y3) = (p0.y as Real, p1.y as Real, p2.y as Real, p3.y as Real);
+    debug_assert!(y0 < y1 + EPS && y1 < y2 + EPS && y2 < y3 + EPS);
+    let a3 = -y0 + 3.0*y1 - 3.0*y2 + y3;
+    let a2 = 3.0*y0 - 6.0*y1 + 3.0*y2;
+    let a1 = -3.0*y0 + 3.0*y1;
+    let a0 = y0 - y as Real;
     let t = solve_cubic_for_single_t(a3, a2, a1, a0);
-    let tc = 1.0 - t;
-    tc*tc*tc * p0.x + 3.0*tc*tc*t * p1.x + 3.0*tc*t*t * p2.x + t*t*t * p3.x
+    bezier3(t, p0.x, p1.x, p2.x, p3.x) as f32
 }
 
 
@@ -303,19 +501,149 @@ mod tests {
     Query point: (419.0, 291.0)
     Result: dist=47.04632869336913, X=[ 382.2548382   320.37941673], t=0.7942392383680202
     */
+    #[test]
+    fn test_vec2() {
+        let v = Vec2::new(3.0, 4.0);
+        assert_eq!(v.normalize(), Vec2::new(0.6, 0.8));
+        assert_eq!(v.perp(), Vec2::new(-4.0, 3.0));
+        assert_eq!(v.cross(v.perp()), 25.0);
+        assert_eq!(v.perp().cross(v), -25.0);
+        assert_eq!(v.cross(2.0 * v), 0.0);
+        assert_eq!(v.distance(Vec2::new(0.0, 0.0)), 5.0);
+        assert_eq!(Vec2::new(1.0, 1.0).distance(Vec2::new(1.0, -2.0)), 3.0);
+        // Zero and near-zero vectors
+        assert_eq!(Vec2::new(0.0, 0.0).normalize(), Vec2::new(0.0, 0.0));
+        for &tiny in [1e-20f32, 1e-30, 1e-40, f32::MIN_POSITIVE].iter() {
+            let n = Vec2::new(tiny, -tiny).normalize();
+            assert!(n.x.is_finite() && n.y.is_finite(), "{:?} for {}", n, tiny);
+            assert!(n.magnitude() <= 1.0 + 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        let v = |x, y| Vec2::new(x, y);
+        let close = |a: Vec2, b: Vec2| a.distance(b) < 1e-4;
+        let quadratic = QuadraticSegment::new(v(0.0, 0.0), v(2.0, 4.0), v(5.0, -1.0));
+        let cubic = CubicSegment::new(v(100.0, 200.0), v(250.0, 400.0), v(400.0, 200.0), v(400.0, 400.0));
+        for &t in [0.0, 0.25, 0.5, 0.8, 1.0].iter() {
+            let (first, second) = quadratic.split(t);
+            let (cubic_first, cubic_second) = cubic.split(t);
+            assert_eq!(first.p2, second.p0);
+            assert_eq!(cubic_first.p3, cubic_second.p0);
+            for &s in [0.0, 0.3, 0.5, 1.0].iter() {
+                // The first part maps s to t * s, the second to t + (1 - t) * s
+                let (t1, t2) = (t * s, t + (1.0 - t) * s);
+                assert!(close(quadratic.eval_point(t1), first.eval_point(s)));
+                assert!(close(quadratic.eval_point(t2), second.eval_point(s)));
+                assert!(cubic.eval_point(t1).distance(cubic_first.eval_point(s)) < 1e-2);
+                assert!(cubic.eval_point(t2).distance(cubic_second.eval_point(s)) < 1e-2);
+            }
+        }
+        // The tangent at the split point keeps its direction (scaled by the parameter)
+        let (first, second) = quadratic.split(0.5);
+        assert!(close(first.eval_tangent(1.0), 0.5 * quadratic.eval_tangent(0.5)));
+        assert!(close(second.eval_tangent(0.0), 0.5 * quadratic.eval_tangent(0.5)));
+    }
+
+    #[test]
+    fn test_distance_detailed() {
+        let v = |x, y| Vec2::new(x, y);
+        let line = LinearSegment::new(v(0.0, 0.0), v(4.0, 2.0));
+        let quadratic = QuadraticSegment::new(v(0.0, 0.0), v(2.0, 4.0), v(4.0, 0.0));
+        let cubic = CubicSegment::new(v(100.0, 200.0), v(250.0, 400.0), v(400.0, 200.0), v(400.0, 400.0));
+        // The line and the quadratic elevated to cubics (the same curves),
+        // for the reference distance from `brute_force_distance`
+        let line_cubic = CubicSegment::new(v(0.0, 0.0), v(4.0 / 3.0, 2.0 / 3.0),
+                                           v(8.0 / 3.0, 4.0 / 3.0), v(4.0, 2.0));
+        let quadratic_cubic = CubicSegment::new(v(0.0, 0.0), v(4.0 / 3.0, 8.0 / 3.0),
+                                                v(8.0 / 3.0, 8.0 / 3.0), v(4.0, 0.0));
+        let check = |(dist, t, point): (f32, f32, Vec2), reference: f64, p: Vec2| {
+            assert!(t >= 0.0 && t <= 1.0);
+            assert!((dist as f64 - reference).abs() <= 1e-4 * reference.max(1.0),
+                    "{:?}: {} vs {}", p, dist, reference);
+            assert!((point.distance(p) - dist).abs() <= 1e-4 * dist.max(1.0), "{:?} {}", point, dist);
+        };
+        for &p in [v(1.0, 3.0), v(-1.0, -1.0), v(5.0, 1.0), v(2.0, 1.0), v(3.0, 2.5)].iter() {
+            let detailed = line.distance_detailed(p);
+            check(detailed, brute_force_distance(&line_cubic, p), p);
+            assert_eq!(line.distance(p), detailed.0);
+            let detailed = quadratic.distance_detailed(p);
+            check(detailed, brute_force_distance(&quadratic_cubic, p), p);
+            assert_eq!(quadratic.distance(p), detailed.0);
+        }
+        for &p in [v(98.0, 314.0), v(419.0, 291.0), v(250.0, 300.0)].iter() {
+            let detailed = cubic.distance_detailed(p);
+            check(detailed, brute_force_distance(&cubic, p), p);
+            assert_eq!(cubic.distance(p), detailed.0);
+        }
+        // Known nearest points: the projection on the line and its end point
+        let (dist, t, point) = line.distance_detailed(v(1.0, 3.0));
+        assert!(float_eq(dist, 5f32.sqrt()) && float_eq(t, 0.5));
+        assert!(float_eq(point.x, 2.0) && float_eq(point.y, 1.0));
+        let (dist, t, point) = line.distance_detailed(v(5.0, 1.0));
+        assert!(float_eq(dist, 2f32.sqrt()) && t == 1.0);
+        assert!(float_eq(point.x, 4.0) && float_eq(point.y, 2.0));
+        // The apex of the quadratic
+        let (dist, t, point) = quadratic.distance_detailed(v(2.0, 3.0));
+        assert!(float_eq(dist, 1.0) && float_eq(t, 0.5));
+        assert!(float_eq(point.x, 2.0) && float_eq(point.y, 2.0));
+        // The samples from distance.py (see above)
+        let references = [(v(98.0, 314.0), 80.05094469021948, 0.1091577060749022,
+                           v(148.925869, 252.23666449)),
+                          (v(419.0, 291.0), 47.04632869336913, 0.7942392383680202,
+                           v(382.2548382, 320.37941673))];
+        for &(p, ref_dist, ref_t, ref_point) in references.iter() {
+            let (dist, t, point) = cubic.distance_detailed(p);
+            assert!(float_eq(dist, ref_dist as f32));
+            assert!((t as f64 - ref_t).abs() < 1e-4, "{} vs {}", t, ref_t);
+            assert!(point.distance(ref_point) < 1e-2, "{:?} vs {:?}", point, ref_point);
+        }
+    }
+
+    #[test]
+    fn test_single_root_out_of_range() {
+        // Root at t = 1 + 1e-6 (the other root at -5)
+        let r: Real = 1.0 + 1e-6;
+        let t = solve_quadratic_for_single_t(1.0, 5.0 - r, -5.0 * r);
+        assert!(t <= 1.0 && 1.0 - t < 1e-5);
+        // (t - r) * (t + 5) * (t + 7)
+        let t = solve_cubic_for_single_t(1.0, 12.0 - r, 35.0 - 12.0 * r, -35.0 * r);
+        assert!(t <= 1.0 && 1.0 - t < 1e-5);
+        // Quadratic curve grazing the scanline at its end point
+        let p = |x, y| Vec2::new(x, y);
+        let x = quadratic_intersection(1.0 + 1e-6, p(0.0, 0.0), p(1.0, 0.5), p(2.0, 1.0));
+        assert!(float_eq(x, 2.0));
+        // No root in the widened range, the nearer end point is used
+        let before = root_fallback_count();
+        assert_eq!(solve_quadratic_for_single_t(1.0, 3.5, -7.5), 1.0);
+        assert_eq!(solve_quadratic_for_single_t(1.0, 5.5, 2.5), 0.0);
+        assert!(root_fallback_count() >= before + 2);
+    }
+
     #[test]
     fn test_cubic_distance() {
-        let p = Point::new(98.0, 314.0);
-        let p0 = Point::new(100.0, 200.0);
-        let p1 = Point::new(250.0, 400.0);
-        let p2 = Point::new(400.0, 200.0);
-        let p3 = Point::new(400.0, 400.0);
-        let dist = cubic_distance(p, p0, p1, p2, p3);
-        assert!(float_eq(dist, -80.05094469021948));
-
-        let p = Point::new(419.0, 291.0);
-        let dist = cubic_distance(p, p0, p1, p2, p3);
+        // `distance` is unsigned, the reference values are signed
+        let p = Vec2::new(98.0, 314.0);
+        let p0 = Vec2::new(100.0, 200.0);
+        let p1 = Vec2::new(250.0, 400.0);
+        let p2 = Vec2::new(400.0, 200.0);
+        let p3 = Vec2::new(400.0, 400.0);
+        let curve = CubicSegment::new(p0, p1, p2, p3);
+        let dist = curve.distance(p);
+        assert!(float_eq(dist, 80.05094469021948));
+
+        let p = Vec2::new(419.0, 291.0);
+        let dist = curve.distance(p);
         assert!(float_eq(dist, 47.04632869336913));
+        // With the double precision math, the error is within f32 rounding
+        if cfg!(feature = "f64") {
+            let eps = 8e-6;
+            assert!((curve.distance(Vec2::new(98.0, 314.0)) as f64 - 80.05094469021948).abs() < eps);
+            assert!((dist as f64 - 47.04632869336913).abs() < eps);
+            let (_, t) = curve.nearest(p);
+            assert!((t as f64 - 0.7942392383680202).abs() < 1e-6);
+        }
         /*
         let (a, b) = (0.73333335, 0.8);
         let f = |t| { (cubic_bezier(t, p0, p1, p2, p3) - p).dot(cubic_derivate(t, p0, p1, p2, p3)) };
@@ -329,4 +657,93 @@ mod tests {
         println!("t={}, f(t)={}", t, f(t));
         */
     }
+    // Deterministic pseudo-random numbers in 0..1 (LCG, upper bits)
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> f32 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 40) as f32 / (1u64 << 24) as f32
+        }
+    }
+
+    // Ground truth for the cubic distance: dense sampling of the curve
+    // (in f64), refined by ternary search around the best sample
+    fn brute_force_distance(curve: &CubicSegment, p: Vec2) -> f64 {
+        let dist = |t: f64| {
+            let tc = 1.0 - t;
+            let (w0, w1, w2, w3) = (tc*tc*tc, 3.0*tc*tc*t, 3.0*tc*t*t, t*t*t);
+            let x = w0 * curve.p0.x as f64 + w1 * curve.p1.x as f64 +
+                    w2 * curve.p2.x as f64 + w3 * curve.p3.x as f64;
+            let y = w0 * curve.p0.y as f64 + w1 * curve.p1.y as f64 +
+                    w2 * curve.p2.y as f64 + w3 * curve.p3.y as f64;
+            ((x - p.x as f64).powi(2) + (y - p.y as f64).powi(2)).sqrt()
+        };
+        let n = 2000;
+        let best = (0 .. n + 1).min_by(|&a, &b| {
+            dist(a as f64 / n as f64).partial_cmp(&dist(b as f64 / n as f64)).unwrap()
+        }).unwrap();
+        let (mut lo, mut hi) = ((best as f64 - 1.0).max(0.0) / n as f64,
+                                (best as f64 + 1.0).min(n as f64) / n as f64);
+        for _ in 0 .. 60 {
+            let (m1, m2) = (lo + (hi - lo) / 3.0, hi - (hi - lo) / 3.0);
+            if dist(m1) < dist(m2) { hi = m2; } else { lo = m1; }
+        }
+        dist((lo + hi) / 2.0).min(dist(best as f64 / n as f64))
+    }
+
+    // Accuracy of the cubic solver (bracketed Brent's method) on random curves
+    #[test]
+    fn test_cubic_distance_accuracy() {
+        let mut rng = Lcg(1);
+        let mut max_error = 0.0f64;
+        for _ in 0 .. 100 {
+            let mut point = |scale: f32, offset: f32| Vec2::new(rng.next() * scale + offset,
+                                                                rng.next() * scale + offset);
+            let curve = CubicSegment::new(point(100.0, 0.0), point(100.0, 0.0),
+                                          point(100.0, 0.0), point(100.0, 0.0));
+            for _ in 0 .. 5 {
+                let p = point(140.0, -20.0);
+                let error = (curve.distance(p) as f64 - brute_force_distance(&curve, p)).abs();
+                max_error = max_error.max(error);
+            }
+        }
+        assert!(max_error < 1e-2, "max error {:e}", max_error);
+    }
+
+    // The extrema split the intervals, so the roots are found even in one step
+    #[test]
+    fn test_cubic_nearest_steps() {
+        let curve = CubicSegment::new(Vec2::new(100.0, 200.0), Vec2::new(250.0, 400.0),
+                                      Vec2::new(400.0, 200.0), Vec2::new(400.0, 400.0));
+        let references = [(Vec2::new(98.0, 314.0), 80.05094469021948, 0.1091577060749022),
+                          (Vec2::new(419.0, 291.0), 47.04632869336913, 0.7942392383680202)];
+        for &steps in [1, 2, CUBIC_NEAREST_STEPS].iter() {
+            for &(p, ref_dist, ref_t) in references.iter() {
+                let (dist, t) = curve.nearest_with_steps(p, steps);
+                assert!(float_eq(dist, ref_dist as f32));
+                if cfg!(feature = "f64") {
+                    assert!((dist as f64 - ref_dist).abs() < 8e-6);
+                    assert!((t as f64 - ref_t).abs() < 1e-7);
+                } else {
+                    assert!((t as f64 - ref_t).abs() < 1e-4);
+                }
+            }
+        }
+
+        let mut rng = Lcg(2);
+        let mut max_error = 0.0f64;
+        for _ in 0 .. 100 {
+            let mut point = |scale: f32, offset: f32| Vec2::new(rng.next() * scale + offset,
+                                                                rng.next() * scale + offset);
+            let curve = CubicSegment::new(point(100.0, 0.0), point(100.0, 0.0),
+                                          point(100.0, 0.0), point(100.0, 0.0));
+            for _ in 0 .. 5 {
+                let p = point(140.0, -20.0);
+                let (dist, _) = curve.nearest_with_steps(p, 1);
+                max_error = max_error.max((dist as f64 - brute_force_distance(&curve, p)).abs());
+            }
+        }
+        assert!(max_error < 1e-2);
+    }
 }
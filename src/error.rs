@@ -0,0 +1,69 @@
+use std::error;
+use std::fmt;
+
+use rect::Rect;
+
+/// Error of font building
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FontError {
+    // the build was cancelled by the caller (the glyphs built so far are valid)
+    Cancelled,
+    // the face has no charmap of requested encoding (see `FontConfig::charmap`)
+    CharmapNotFound,
+    // the face has no outlines (bitmap-only font)
+    NotScalable,
+    // glyph rectangle doesn't fit in the texture (width, height)
+    GlyphOutOfBounds { ch: char, rect: Rect, width: usize, height: usize },
+    // no space left in the texture for the glyph
+    TextureFull(char),
+    // no space left in the texture for the glyph given by index
+    TextureFullForIndex(u32),
+    // texture data doesn't match the font metadata (details)
+    CorruptAtlas(String),
+    // the glyph is not in the font
+    GlyphNotFound(char),
+    // the glyph index is not in the face
+    GlyphIndexNotFound(u32),
+    // re-rendered glyph doesn't fit its existing box in the texture
+    GlyphBoxChanged(char),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FontError::GlyphOutOfBounds { ch, rect, width, height } =>
+                write!(f, "glyph {:?} at {:?} is out of {}x{} texture", ch, rect, width, height),
+            FontError::TextureFull(ch) =>
+                write!(f, "font texture not large enough for glyph {:?}", ch),
+            FontError::TextureFullForIndex(index) =>
+                write!(f, "font texture not large enough for glyph #{}", index),
+            FontError::CorruptAtlas(ref details) =>
+                write!(f, "corrupt font atlas: {}", details),
+            FontError::GlyphNotFound(ch) =>
+                write!(f, "glyph {:?} not found in the font", ch),
+            FontError::GlyphIndexNotFound(index) =>
+                write!(f, "glyph #{} not found in the face", index),
+            FontError::GlyphBoxChanged(ch) =>
+                write!(f, "glyph {:?} doesn't match its box in the texture", ch),
+            _ => write!(f, "{}", error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for FontError {
+    fn description(&self) -> &str {
+        match *self {
+            FontError::Cancelled => "font build cancelled",
+            FontError::CharmapNotFound => "charmap not found in the face",
+            FontError::NotScalable => "the face is not scalable (bitmap-only)",
+            FontError::GlyphOutOfBounds { .. } => "glyph out of texture bounds",
+            FontError::TextureFull(_) => "font texture not large enough",
+            FontError::TextureFullForIndex(_) => "font texture not large enough",
+            FontError::CorruptAtlas(_) => "corrupt font atlas",
+            FontError::GlyphNotFound(_) => "glyph not found in the font",
+            FontError::GlyphIndexNotFound(_) => "glyph index not found in the face",
+            FontError::GlyphBoxChanged(_) => "glyph box changed",
+        }
+    }
+}
@@ -1,17 +1,640 @@
+use std::f32;
 use std::path;
-use std::collections::HashMap;
+#[cfg(feature = "png")]
+use std::{fs, io};
+use std::collections::{HashMap, HashSet};
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use freetype as ft;
 use rect_packer;
 
 use rasterizer::*;
 use mindist::*;
 use curve::*;
+use layout::*;
+use rect::*;
+use outline::*;
+use msdf::*;
+use error::*;
 
 pub fn vec2_from_ft(p: ft::Vector, unit: f32) -> Vec2 {
     Vec2 { x: p.x as f32 / unit, y: p.y as f32 / unit }
 }
 
-#[derive(Debug)]
+// Convert FreeType outline to `Outline` in face pixels
+// (`unit` is size of the face pixel in 26.6 font units)
+pub fn outline_from_ft(outline: &ft::outline::Outline, unit: f32) -> Outline {
+    let mut builder = OutlineBuilder::new();
+    for contour in outline.contours_iter() {
+        builder.move_to(vec2_from_ft(contour.start(), unit));
+        for curve in contour {
+            match curve {
+                ft::outline::Curve::Line(a) => {
+                    builder.line_to(vec2_from_ft(a, unit));
+                }
+                ft::outline::Curve::Bezier2(a, b) => {
+                    builder.quad_to(vec2_from_ft(a, unit), vec2_from_ft(b, unit));
+                }
+                ft::outline::Curve::Bezier3(a, b, c) => {
+                    builder.curve_to(vec2_from_ft(a, unit), vec2_from_ft(b, unit),
+                                     vec2_from_ft(c, unit));
+                }
+            }
+        }
+    }
+    builder.finish()
+}
+
+// Coverage of a pixel whose center lies at `distance` (in pixels) from the outline
+pub fn sdf_coverage(distance: f32) -> f32 {
+    (0.5 - distance).max(0.0).min(1.0)
+}
+
+// Hard bitmap reconstructed from SDF values: 255 where the value is
+// at least `threshold` (inside), 0 elsewhere. With the default params,
+// the threshold is the midpoint (`SdfParams::encode(0.0, ..)` in general).
+// Meant for validating the SDF against a direct monochrome render.
+pub fn sdf_to_monochrome(sdf: &[u8], threshold: u8) -> Vec<u8> {
+    sdf.iter().map(|&value| if value >= threshold { 255 } else { 0 }).collect()
+}
+
+// SDF values mirrored around `midpoint` (the outline stays), i.e. inside
+// and outside swapped, saturated to 0 ..= 255. Converts between the normal
+// and `SdfParams::invert` encoding (of linear values, not sRGB). Inverting
+// twice gives the original values, except those above 2 * midpoint.
+pub fn invert_sdf(sdf: &[u8], midpoint: u8) -> Vec<u8> {
+    let mirror = 2 * midpoint as i32;
+    sdf.iter().map(|&value| (mirror - value as i32).max(0).min(255) as u8).collect()
+}
+
+// Printable ASCII characters, to be included in `chars_from_corpus` as the base set
+pub const ASCII_PRINTABLE: &'static str = " !\"#$%&'()*+,-./0123456789:;<=>?@\
+    ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+// Collect unique characters from the strings, sorted (usable as `chars`
+// for building the font). The font is indexed by chars (Unicode scalar values),
+// so grapheme clusters contribute each of their chars (base letter and
+// combining marks). Control characters (like line breaks) are left out.
+pub fn chars_from_corpus(texts: &[&str]) -> String {
+    let mut chars: Vec<char> = texts.iter()
+        .flat_map(|text| text.chars())
+        .filter(|ch| !ch.is_control())
+        .collect();
+    chars.sort();
+    chars.dedup();
+    chars.into_iter().collect()
+}
+
+/// Representation of alpha in RGBA images
+///
+/// The blend state of the drawing pipeline has to match:
+///
+/// - `Straight` - pixels are `(color, alpha)`. Blend with
+///   `glium::Blend::alpha_blending()` (wgpu: `BlendState::ALPHA_BLENDING`),
+///   i.e. `src * src_alpha + dst * (1 - src_alpha)`.
+/// - `Premultiplied` - pixels are `(color * alpha, alpha)`. Blend with source
+///   factor `One` and destination factor `OneMinusSourceAlpha` (wgpu:
+///   `BlendState::PREMULTIPLIED_ALPHA_BLENDING`). Premultiplied images also
+///   filter (scale, mipmap) without dark fringes.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AlphaMode {
+    Straight,
+    Premultiplied,
+}
+
+// Smallest face size (in pixels) at which strokes of `stroke_em` thickness (in em)
+// keep their interior in the SDF. A stroke at least 2 texels wide contains
+// a texel center lying 0.5 texel or more inside, at any sub-texel position.
+// Thinner strokes may survive only as the outline (value near 127, see
+// `SdfParams::preserve_thin_strokes`) and break up when magnified.
+// Typical regular stems are about 0.08 em, hairlines of light weights 0.02 em.
+pub fn min_face_size(stroke_em: f32) -> usize {
+    (2.0 / stroke_em).ceil() as usize
+}
+
+/// Line decoration (underline, strikeout)
+///
+/// Position of the line center relative to the baseline (positive = above)
+/// and its thickness, both in face pixels.
+
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Decoration {
+    pub position: f32,
+    pub thickness: f32,
+}
+
+impl Decoration {
+    // Underline metrics of the face, scaled to `face_size`
+    pub fn underline_from_face(face: &ft::Face, face_size: usize) -> Self {
+        let scale = face_size as f32 / face.em_size() as f32;
+        Decoration {
+            position: face.raw().underline_position as f32 * scale,
+            thickness: face.raw().underline_thickness as f32 * scale,
+        }
+    }
+
+    // Strikeout metrics of the face (from OS/2 table), scaled to `face_size`
+    pub fn strikeout_from_face(face: &ft::Face, face_size: usize) -> Self {
+        let scale = face_size as f32 / face.em_size() as f32;
+        match os2_strikeout(face) {
+            // OS/2 gives position of the top of the stroke
+            Some((size, position)) => Decoration {
+                position: (position as f32 - size as f32 / 2.) * scale,
+                thickness: size as f32 * scale,
+            },
+            // No OS/2 table: a quarter of em above baseline, same thickness as underline
+            None => Decoration {
+                position: face_size as f32 / 4.,
+                thickness: face.raw().underline_thickness as f32 * scale,
+            },
+        }
+    }
+}
+
+/// Vertical metrics of the font
+///
+/// Ascent and descent are distances from the baseline (both positive),
+/// line height is the baseline-to-baseline distance and baseline is
+/// the position of the baseline from the top of the line (half of the line gap
+/// above the ascent). In face pixels, or target pixels from `Font::line_metrics`.
+
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+pub struct LineMetrics {
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_height: f32,
+    pub baseline: f32,
+}
+
+impl LineMetrics {
+    // Line metrics of the face (ascender, descender and height), scaled to `face_size`
+    pub fn from_face(face: &ft::Face, face_size: usize) -> Self {
+        let scale = face_size as f32 / face.em_size() as f32;
+        let ascent = face.raw().ascender as f32 * scale;
+        let descent = -face.raw().descender as f32 * scale;
+        let line_height = face.raw().height as f32 * scale;
+        LineMetrics {
+            ascent: ascent,
+            descent: descent,
+            line_height: line_height,
+            baseline: (line_height - ascent - descent) / 2. + ascent,
+        }
+    }
+
+    // All metrics multiplied by `scale`
+    pub fn scaled(&self, scale: f32) -> Self {
+        LineMetrics {
+            ascent: self.ascent * scale,
+            descent: self.descent * scale,
+            line_height: self.line_height * scale,
+            baseline: self.baseline * scale,
+        }
+    }
+}
+
+// Leading part of FreeType's TT_OS2 structure, up to the strikeout fields
+#[allow(dead_code)]
+#[repr(C)]
+struct TtOs2Head {
+    version: u16,
+    x_avg_char_width: i16,
+    us_weight_class: u16,
+    us_width_class: u16,
+    fs_type: u16,
+    y_subscript: [i16; 4],
+    y_superscript: [i16; 4],
+    y_strikeout_size: i16,
+    y_strikeout_position: i16,
+}
+
+const FT_SFNT_OS2: c_int = 2;
+const FT_FACE_FLAG_SCALABLE: c_long = 1;
+
+// FreeType's FT_Var_Axis
+#[allow(dead_code)]
+#[repr(C)]
+struct FtVarAxis {
+    name: *mut c_char,
+    minimum: c_long,
+    def: c_long,
+    maximum: c_long,
+    tag: c_ulong,
+    strid: c_uint,
+}
+
+// FreeType's FT_MM_Var (the named styles are not used)
+#[allow(dead_code)]
+#[repr(C)]
+struct FtMmVar {
+    num_axis: c_uint,
+    num_designs: c_uint,
+    num_namedstyles: c_uint,
+    axis: *mut FtVarAxis,
+    namedstyle: *mut c_void,
+}
+
+// FreeType's FT_MemoryRec (the allocator of a face)
+#[allow(dead_code)]
+#[repr(C)]
+struct FtMemoryRec {
+    user: *mut c_void,
+    alloc: extern "C" fn(memory: *mut FtMemoryRec, size: c_long) -> *mut c_void,
+    free: extern "C" fn(memory: *mut FtMemoryRec, block: *mut c_void),
+    realloc: extern "C" fn(memory: *mut FtMemoryRec, cur_size: c_long, new_size: c_long,
+                           block: *mut c_void) -> *mut c_void,
+}
+
+extern "C" {
+    fn FT_Get_Sfnt_Table(face: ft::ffi::FT_Face, tag: c_int) -> *mut c_void;
+    fn FT_Set_Transform(face: ft::ffi::FT_Face, matrix: *mut c_void, delta: *mut c_void);
+    fn FT_Select_Charmap(face: ft::ffi::FT_Face, encoding: u32) -> c_int;
+    fn FT_Get_MM_Var(face: ft::ffi::FT_Face, amaster: *mut *mut FtMmVar) -> c_int;
+    fn FT_Set_Var_Design_Coordinates(face: ft::ffi::FT_Face, num_coords: c_uint,
+                                     coords: *mut c_long) -> c_int;
+}
+
+// Reset the transformation set on the face with `set_transform`.
+// FreeType applies it when loading glyphs, we apply `FontConfig::transform`
+// to the outline instead, so the box, the outline and the advance agree.
+fn reset_transform(face: &ft::Face) {
+    unsafe {
+        let raw = face.raw() as *const ft::ffi::FT_FaceRec as ft::ffi::FT_Face;
+        FT_Set_Transform(raw, ptr::null_mut(), ptr::null_mut());
+    }
+}
+
+// Does the face have outlines? Bitmap-only faces (strikes in BDF, PCF, …)
+// have no em size to scale to, nor outlines to compute the distance from.
+fn check_scalable(face: &ft::Face) -> Result<(), FontError> {
+    if face.raw().face_flags & FT_FACE_FLAG_SCALABLE == 0 || face.em_size() == 0 {
+        return Err(FontError::NotScalable);
+    }
+    Ok(())
+}
+
+// Read (yStrikeoutSize, yStrikeoutPosition) from OS/2 table, if the face has one
+fn os2_strikeout(face: &ft::Face) -> Option<(i16, i16)> {
+    unsafe {
+        let raw = face.raw() as *const ft::ffi::FT_FaceRec as ft::ffi::FT_Face;
+        let os2 = FT_Get_Sfnt_Table(raw, FT_SFNT_OS2) as *const TtOs2Head;
+        if os2.is_null() || (*os2).version == 0xFFFF {
+            None
+        } else {
+            Some(((*os2).y_strikeout_size, (*os2).y_strikeout_position))
+        }
+    }
+}
+
+/// Charmap (encoding) used to map chars to glyphs
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Charmap {
+    Unicode,
+    // Microsoft Symbol encoding, used by symbol and icon fonts
+    // (the chars are usually mapped into U+F000 - U+F0FF)
+    Symbol,
+    AppleRoman,
+}
+
+impl Charmap {
+    // FreeType's FT_Encoding tag
+    fn ft_encoding(self) -> u32 {
+        make_tag(match self {
+            Charmap::Unicode => b"unic",
+            Charmap::Symbol => b"symb",
+            Charmap::AppleRoman => b"armn",
+        })
+    }
+}
+
+// Select charmap of the face (see `FontConfig::charmap`)
+pub fn select_charmap(face: &ft::Face, charmap: Charmap) -> Result<(), FontError> {
+    let err = unsafe {
+        let raw = face.raw() as *const ft::ffi::FT_FaceRec as ft::ffi::FT_Face;
+        FT_Select_Charmap(raw, charmap.ft_encoding())
+    };
+    if err == 0 { Ok(()) } else { Err(FontError::CharmapNotFound) }
+}
+
+// Big-endian tag from four ASCII chars, like FreeType's FT_MAKE_TAG
+fn make_tag(tag: &[u8; 4]) -> u32 {
+    (tag[0] as u32) << 24 | (tag[1] as u32) << 16 | (tag[2] as u32) << 8 | tag[3] as u32
+}
+
+// Set the variation axes of a variable font (e.g. `(*b"wght", 600.0)`),
+// in the axes' design units. The axes not given get their default values,
+// the values are clamped to the axis range, unknown axes are skipped.
+// Faces without variations are left as they are. With no settings,
+// the face is not touched (so a named instance opened by the face index stays).
+pub fn set_variations(face: &ft::Face, settings: &[([u8; 4], f32)]) {
+    if settings.is_empty() {
+        return;
+    }
+    unsafe {
+        let raw = face.raw() as *const ft::ffi::FT_FaceRec as ft::ffi::FT_Face;
+        let mut mm: *mut FtMmVar = ptr::null_mut();
+        if FT_Get_MM_Var(raw, &mut mm) != 0 || mm.is_null() {
+            warn!("the face has no variation axes, the settings are ignored");
+            return;
+        }
+        let axes = ::std::slice::from_raw_parts((*mm).axis, (*mm).num_axis as usize);
+        let mut coords: Vec<c_long> = axes.iter().map(|axis| axis.def).collect();
+        for &(ref tag, value) in settings {
+            match axes.iter().position(|axis| axis.tag as u32 == make_tag(tag)) {
+                Some(i) => {
+                    let fixed = (value * 65536.0) as c_long;
+                    coords[i] = fixed.max(axes[i].minimum).min(axes[i].maximum);
+                    if coords[i] != fixed {
+                        warn!("variation {:?} = {} clamped to the axis range",
+                              String::from_utf8_lossy(tag), value);
+                    }
+                }
+                None => warn!("variation axis {:?} not found in the face",
+                              String::from_utf8_lossy(tag)),
+            }
+        }
+        if FT_Set_Var_Design_Coordinates(raw, coords.len() as c_uint, coords.as_mut_ptr()) != 0 {
+            warn!("failed to set the variation coordinates");
+        }
+        // FT_Get_MM_Var allocates the structure with the face's memory.
+        // Free it the same way (FT_Done_MM_Var does this, but it's only
+        // in FreeType 2.9+, the older versions would fail to link).
+        let memory = (*raw).memory as *mut FtMemoryRec;
+        ((*memory).free)(memory, mm as *mut c_void);
+    }
+}
+
+/// Rounding of the glyph box to whole pixels
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BoxRounding {
+    // Round mins down and maxes up, the ink is never clipped
+    Expand,
+    // Round all edges to nearest (reproduces the older glyph sizes)
+    Nearest,
+}
+
+/// Configuration of the font building and layout
+
+#[derive(Clone, Debug)]
+pub struct FontConfig {
+    // Rounding of glyph boxes (before adding the padding)
+    pub box_rounding: BoxRounding,
+    // Snap glyph origins (pen positions) to whole target pixels in the layout,
+    // so every glyph starts at the same sub-pixel phase. This only affects
+    // `Layout`, the glyph boxes in the atlas are already at whole texels.
+    pub snap_to_pixel: bool,
+    // Synthetic bold: embolden the glyph outlines by this strength (in face pixels)
+    // before rendering. The strokes get wider by `embolden`, and so does the advance.
+    pub embolden: f32,
+    // Transformation applied to the glyph outlines (in face pixels), e.g. for
+    // synthetic oblique. Transforms set directly on the face are reset before building.
+    pub transform: Option<Transform>,
+    // Charmap to select before the build (default: keep the face's active charmap,
+    // which is Unicode when the face has one)
+    pub charmap: Option<Charmap>,
+    // Horizontal face size (in pixels) for anamorphic (condensed / expanded) glyphs,
+    // the face size given to the build is then the vertical size. The outlines
+    // are stretched before rendering, so the distances are measured in the
+    // stretched glyph's uniform space (correct when drawn with square texels).
+    pub face_width: Option<usize>,
+    // Keep the glyph outlines in the font (see `Font::render_glyph_exact`)
+    pub retain_outlines: bool,
+    // Empty pixels between the packed glyphs. With linear filtering, a glyph's
+    // spread bleeds into its neighbor unless the gap is at least the spread
+    // (in texels) or the glyphs have `padding`. It applies to the free space
+    // of the texture from the first build on (see `Font::repack`).
+    pub gap: usize,
+    // Variation settings of a variable font (axis tag, value in design units),
+    // e.g. `vec![(*b"wght", 600.0)]` for SemiBold. Applied to the face before
+    // the build (see `set_variations`), ignored for non-variable faces.
+    pub variations: Vec<([u8; 4], f32)>,
+}
+
+impl Default for FontConfig {
+    fn default() -> Self {
+        FontConfig {
+            box_rounding: BoxRounding::Expand,
+            snap_to_pixel: false,
+            embolden: 0.0,
+            transform: None,
+            charmap: None,
+            face_width: None,
+            retain_outlines: false,
+            gap: 0,
+            variations: Vec::new(),
+        }
+    }
+}
+
+impl FontConfig {
+    // Transformation of the outlines in face pixels (the anamorphic stretch
+    // followed by `transform`), None when there is none
+    pub fn outline_transform(&self, face_size: usize) -> Option<Transform> {
+        let stretch = self.face_width.map(|w| Transform::scale(w as f32 / face_size as f32, 1.0));
+        match (stretch, self.transform) {
+            (Some(a), Some(b)) => Some(a.then(&b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Parameters of the SDF rendering
+
+#[derive(Clone, Debug)]
+pub struct SdfParams {
+    // Preserve strokes thinner than a texel. Pixels whose center is outside,
+    // but the outline passes within half a texel, are rendered as lying
+    // on the outline, so the stroke doesn't drop out.
+    pub preserve_thin_strokes: bool,
+    // Encode distances normalized to em: the distance (in em) which maps
+    // to the whole outside range (from the outline to saturated 0).
+    // The same texture then reconstructs at any size with this one constant.
+    // Default (None) is the pixel scale `1920 / face_size`, i.e. 127/1920 em.
+    pub em_spread: Option<f32>,
+    // The same in texels (face pixels): the distance which maps to the whole
+    // outside range. Takes precedence over `em_spread`. The outside field
+    // reaches only as far as the glyph padding, so a spread larger than
+    // the padding is cut at the tile border (padding >= spread avoids that).
+    pub spread: Option<f32>,
+    // SDF value of the outline (zero distance). Smaller values are outside,
+    // larger inside. The default 127 gives about the same range to both.
+    // Valid values are 1 to 254, both sides need some range.
+    pub midpoint: u8,
+    // The texture is going to be uploaded as sRGB (e.g. SRGB8 / SRGB8_ALPHA8),
+    // so the GPU converts the sampled values from sRGB to linear. The values are
+    // pre-distorted by the inverse transfer function, so the distances come back
+    // linear after sampling. Without this flag (default), the texture must be
+    // UNORM (R8, RGBA8, …): an sRGB sampler would shift the edge, e.g. the outline
+    // value 127 would be read as 54.
+    pub srgb: bool,
+    // Compute the distance only to the boundary of the union of the contours.
+    // Overlapping contours (e.g. merged icon shapes) fill correctly with
+    // the non-zero rule, but the parts of the contours inside the other ones
+    // would show as false edges (seams) in the SDF. See `Outline::union_distance`.
+    pub union_overlaps: bool,
+    // Use the pseudo-distance: beyond the ends of the nearest segment,
+    // the distance is measured to the segment's tangent line, so the corners
+    // stay sharp when the SDF is rendered bold (outline, glow). The default
+    // true distance rounds them off. See `OutlineDistance::pseudo_distance`.
+    pub pseudo_distance: bool,
+    // Rule deciding which areas of the outline are inside. The fonts
+    // use non-zero (default), even-odd suits arbitrary paths, e.g. a star
+    // drawn by one self-intersecting contour is hollow in the middle.
+    // The distance doesn't depend on the rule (nor `union_overlaps` does).
+    pub fill_rule: FillRule,
+    // Decide inside / outside of each pixel by a grid of N x N samples
+    // instead of the pixel center alone: the pixel is inside when at least
+    // half of the samples are. Thin features which miss the pixel centers
+    // (hairlines in small glyphs) then don't drop out. 0 or 1 = center only.
+    pub coverage_samples: u8,
+    // Render the contours as a stroke of this width (in face pixels) instead
+    // of the filled shape. The encoded distance is `|distance| - stroke_width / 2`,
+    // so the outline level bounds a band centered on the contours and the glyph's
+    // interior is outside. The padding has to hold the half stroke as well.
+    // Only the SDF encoding is affected (not the f32 or coverage rendering).
+    pub stroke_width: Option<f32>,
+    // Invert the encoding: the values grow outward, inside is below `midpoint`
+    // (for shaders expecting the distance increasing outside). The outline
+    // stays at `midpoint`, the values are mirrored around it (see `invert_sdf`).
+    pub invert: bool,
+}
+
+impl Default for SdfParams {
+    fn default() -> Self {
+        SdfParams {
+            preserve_thin_strokes: false,
+            em_spread: None,
+            spread: None,
+            midpoint: 127,
+            srgb: false,
+            union_overlaps: false,
+            pseudo_distance: false,
+            fill_rule: FillRule::NonZero,
+            coverage_samples: 1,
+            stroke_width: None,
+            invert: false,
+        }
+    }
+}
+
+// Minimal number of SDF value steps per screen pixel across the edge
+const SPREAD_AA_STEPS: f32 = 4.0;
+
+// Recommend `SdfParams::em_spread` (in em) for text displayed between `min_px`
+// and `max_px` pixels per em, from a texture rendered at `face_size`.
+//
+// The spread is bounded from both sides:
+// * At the smallest size, the antialiasing ramp in the shader spans about
+//   one screen pixel to each side of the outline (1 / min_px em), and the
+//   bilinear filter blends in another texel (1 / face_size em). The values
+//   within this range must not be saturated, otherwise the edge gets cut off.
+// * At the largest size, one step of the 8bit value (spread / 127 em)
+//   must stay smaller than a fraction of a screen pixel (1 / max_px em),
+//   otherwise the edge shows quantization bands. SPREAD_AA_STEPS steps
+//   per pixel are required.
+// When both can be met, the geometric mean of the bounds is returned
+// (the same factor of reserve to either side). Otherwise, the lower bound
+// wins, as saturation is the more visible artifact.
+// The glyphs also need the padding of at least `spread * face_size` texels
+// to hold the whole outside range.
+pub fn recommend_spread(face_size: usize, min_px: f32, max_px: f32) -> f32 {
+    assert!(min_px > 0.0 && min_px <= max_px, "invalid size range {}..{}", min_px, max_px);
+    let lower = 1.0 / min_px + 1.0 / face_size as f32;
+    let upper = 127.0 / (SPREAD_AA_STEPS * max_px);
+    if lower < upper {
+        (lower * upper).sqrt()
+    } else {
+        lower
+    }
+}
+
+// sRGB transfer functions (IEC 61966-2-1), values in 0..1
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+}
+
+// Maximal length of outline pieces (in face pixels) for `SdfParams::union_overlaps`
+const UNION_PIECE_LENGTH: f32 = 0.5;
+
+impl SdfParams {
+    // Distance finder for the outline (in face pixels), according to `union_overlaps`
+    pub fn outline_distance(&self, outline: &Outline) -> OutlineDistance {
+        if self.union_overlaps {
+            outline.union_distance(UNION_PIECE_LENGTH)
+        } else {
+            outline.distance()
+        }
+    }
+
+    // Scale between distance in face pixels and the SDF value
+    pub fn scale(&self, face_size: usize) -> f32 {
+        assert!(self.midpoint > 0 && self.midpoint < 255,
+                "midpoint {} out of range 1..254", self.midpoint);
+        let midpoint = self.midpoint as f32;
+        match (self.spread, self.em_spread) {
+            (Some(spread), _) => midpoint / spread,
+            (None, Some(spread)) => midpoint / (spread * face_size as f32),
+            (None, None) => 1920. / face_size as f32,
+        }
+    }
+
+    // Distance (in face pixels) at which the encoded values saturate
+    // (the larger of the outside and inside range)
+    pub fn saturation_distance(&self, face_size: usize) -> f32 {
+        let range = self.midpoint.max(255 - self.midpoint) as f32;
+        range / self.scale(face_size)
+    }
+
+    // Convert float distance (in face pixels) to discrete space (u8):
+    // 0 << midpoint = outside
+    // midpoint (127) = zero distance (the outline)
+    // midpoint + 1 >> 255 = inside
+    // (the other way around with `invert`)
+    pub fn encode(&self, distance: f32, face_size: usize) -> u8 {
+        let shift = self.midpoint as f32;
+        let mut value = shift - distance * self.scale(face_size);
+        if value < 0. { value = 0.; }
+        if value > 255. { value = 255.; }
+        if self.invert {
+            // Mirror the stored (truncated) value, the same as `invert_sdf`
+            value = (2. * shift - value.floor()).max(0.).min(255.);
+        }
+        if self.srgb {
+            return (linear_to_srgb(value / 255.) * 255. + 0.5) as u8;
+        }
+        value as u8
+    }
+
+    // SDF value of the saturated outside: the padding border of the glyphs
+    // and the texture space not covered by any glyph (0, or twice the midpoint
+    // with `invert`)
+    pub fn outside_value(&self) -> u8 {
+        self.encode(f32::INFINITY, 1)
+    }
+
+    // Convert SDF value back to distance in face pixels
+    // (positive = outside, negative = inside)
+    // (`value` as stored in the texture, i.e. before the sRGB conversion)
+    pub fn decode(&self, value: f32, face_size: usize) -> f32 {
+        let value = if self.srgb { srgb_to_linear(value / 255.) * 255. } else { value };
+        let value = if self.invert { 2. * self.midpoint as f32 - value } else { value };
+        (self.midpoint as f32 - value) / self.scale(face_size)
+    }
+
+    // Convert SDF value back to distance in em (multiply by target size in pixels)
+    pub fn decode_em(&self, value: f32, face_size: usize) -> f32 {
+        self.decode(value, face_size) / face_size as f32
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Glyph {
     // coordinates in font texture (top left corner)
     pub x: usize,
@@ -21,17 +644,145 @@ pub struct Glyph {
     // metrics
     pub xmin: isize,
     pub ymin: isize,
+    // pen advance and horizontal bearing (from the pen to the left
+    // edge of the ink), in face pixels
+    pub advance_x: f32,
+    pub advance_y: f32,
+    pub bearing_x: f32,
+    // Padding around the ink box, in texels. When non-zero, the outermost
+    // texels are rendered as saturated outside, so bilinear sampling near
+    // the glyph boundary never pulls in a neighbor's interior value.
+    // Glyphs can then be packed without a gutter (unless mipmapped).
+    pub padding: usize,
+    // channel of interleaved font texture containing the glyph
+    pub channel: usize,
+    // the glyph is an RGBA image (all channels), not SDF
+    // (see `Font::build_from_face_with_images`)
+    pub image: bool,
+    // Resolution of the tile relative to the font's face size (texels per face
+    // pixel), e.g. 0.5 for a glyph stored at half resolution. The box (xmin, ymin,
+    // width, height) and SDF distances are in texels, the advance in face pixels.
+    pub scale: f32,
+}
+
+impl Default for Glyph {
+    fn default() -> Self {
+        Glyph {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+            xmin: 0,
+            ymin: 0,
+            advance_x: 0.0,
+            advance_y: 0.0,
+            bearing_x: 0.0,
+            padding: 0,
+            channel: 0,
+            image: false,
+            scale: 1.0,
+        }
+    }
+}
+
+/// Everything needed to draw a glyph, see `Font::glyph_info`
+///
+/// The placement and advance are in face pixels (multiply by target size / face size),
+/// the placement is the tile's bottom left corner relative to the pen position, Y up.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GlyphInfo {
+    // texture page: always 0 for single font, the layer in `FontArray`
+    pub page: usize,
+    // channel of the texture containing the glyph
+    pub channel: usize,
+    // texture coordinates of the tile: (left, top, right, bottom), in 0..1
+    pub uv_rect: (f32, f32, f32, f32),
+    // tile placement: (x, y, width, height)
+    pub placement: (f32, f32, f32, f32),
+    pub advance: f32,
+}
+
+/// Textured quad of a glyph placed at a pen position, see `Font::glyph_quad`
+///
+/// The vertices are in em units (multiply by target size), Y up, relative
+/// to the same origin as the pen.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GlyphQuad {
+    // texture coordinates (u, v) of the corners, in 0..1:
+    // bottom left, bottom right, top left, top right (triangle strip order)
+    pub uvs: [Vec2; 4],
+    // vertex rectangle: (left, bottom, right, top)
+    pub rect: (f32, f32, f32, f32),
+    // channel of the texture containing the glyph
+    pub channel: usize,
+    // pen advance past the glyph, in em
+    pub advance: f32,
 }
 
 impl Glyph {
     pub fn from_face(face: &ft::Face, face_size: usize,
-                     padding: usize) -> Self {
-        let bbox = face.glyph().get_glyph().unwrap().get_cbox(0);
+                     padding: usize, config: &FontConfig) -> Self {
+        let transform = config.outline_transform(face_size);
+        if config.embolden != 0.0 || transform.is_some() {
+            // The points are moved, the box has to be recomputed from the outline
+            let outline = Glyph::load_outline(face, face_size, config);
+            let mut glyph = Glyph::from_outline(&outline, padding, config);
+            let unit_size = face.em_size() as f32 * 64. / face_size as f32;
+            let advance = face.glyph().advance().x as f32 / unit_size;
+            let xx = transform.map_or(1.0, |m| m.xx);
+            glyph.advance_x = advance * xx + config.embolden;
+            glyph.advance_y = face.glyph().advance().y as f32 / unit_size;
+            return glyph;
+        }
         let unit_size = face.em_size() as f32 * 64. / face_size as f32;
-        let xmin = (bbox.xMin as f32 / unit_size + 0.5).floor();
-        let ymin = (bbox.yMin as f32 / unit_size + 0.5).floor();
-        let xmax = (bbox.xMax as f32 / unit_size + 0.5).floor();
-        let ymax = (bbox.yMax as f32 / unit_size + 0.5).floor();
+        let mut glyph = if face.glyph().raw().outline.n_contours == 0 {
+            // Nothing to render (e.g. space), only the padding is kept
+            Glyph { padding: padding, .. Glyph::default() }
+        } else {
+            let bbox = face.glyph().get_glyph().unwrap().get_cbox(0);
+            Glyph::from_bbox(bbox.xMin as f32 / unit_size,
+                             bbox.yMin as f32 / unit_size,
+                             bbox.xMax as f32 / unit_size,
+                             bbox.yMax as f32 / unit_size,
+                             padding, config.box_rounding)
+        };
+        glyph.advance_x = face.glyph().advance().x as f32 / unit_size;
+        glyph.advance_y = face.glyph().advance().y as f32 / unit_size;
+        glyph.bearing_x = face.glyph().metrics().horiBearingX as f32 / unit_size;
+        glyph
+    }
+
+    // Make glyph box from outline (in face pixels)
+    pub fn from_outline(outline: &Outline, padding: usize, config: &FontConfig) -> Self {
+        // Empty outline has no box (not even the padding), it's not rendered.
+        // The padding is kept, so all glyphs of a build agree on it.
+        if outline.contours.is_empty() {
+            return Glyph { padding: padding, .. Glyph::default() };
+        }
+        let (xmin, ymin, xmax, ymax) = outline.control_box();
+        Glyph::from_bbox(xmin, ymin, xmax, ymax, padding, config.box_rounding)
+    }
+
+    // Make glyph box from outline's bounding box (in face pixels)
+    pub fn from_bbox(xmin: f32, ymin: f32, xmax: f32, ymax: f32,
+                     padding: usize, rounding: BoxRounding) -> Self {
+        let round = |min: f32, max: f32| {
+            let (rmin, rmax) = match rounding {
+                BoxRounding::Expand => (min.floor(), max.ceil()),
+                BoxRounding::Nearest => ((min + 0.5).floor(), (max + 0.5).floor()),
+            };
+            // Thin ink (e.g. underscore) may round to zero size,
+            // expand it to whole pixels (at least one), so the outline gets rendered
+            if rmax <= rmin && max > min {
+                (min.floor(), max.ceil())
+            } else {
+                (rmin, rmax)
+            }
+        };
+        let (xmin, xmax) = round(xmin, xmax);
+        let (ymin, ymax) = round(ymin, ymax);
         Glyph {
             x: 0,
             y: 0,
@@ -39,151 +790,3697 @@ impl Glyph {
             height: (ymax - ymin) as usize + 2 * padding,
             xmin: xmin as isize - padding as isize,
             ymin: ymin as isize - padding as isize,
+            advance_x: 0.0,
+            advance_y: 0.0,
+            bearing_x: xmin,
+            padding: padding,
+            channel: 0,
+            image: false,
+            scale: 1.0,
         }
     }
 
-    pub fn render_sdf(&self, face: &ft::Face, face_size: usize,
-                      buffer: &mut [u8], pitch: usize) {
-        let outline = face.glyph().outline().unwrap();
+    // Rectangle occupied by the glyph in font texture
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+
+    // Rectangle of the ink box in font texture (the glyph rectangle without padding)
+    pub fn ink_rect(&self) -> Rect {
+        let p = self.padding.min(self.width / 2).min(self.height / 2);
+        Rect::new(self.x + p, self.y + p, self.width - 2 * p, self.height - 2 * p)
+    }
+
+    // Same box size and placement (e.g. the same glyph of a face, loaded again)
+    fn same_box(&self, other: &Glyph) -> bool {
+        (self.width, self.height, self.xmin, self.ymin, self.padding) ==
+            (other.width, other.height, other.xmin, other.ymin, other.padding)
+    }
+
+    // Check that the glyph rectangle fits in texture of `width` x `height`
+    pub fn check_bounds(&self, ch: char, width: usize, height: usize) -> Result<(), FontError> {
+        let rect = self.rect();
+        if rect.right() > width || rect.bottom() > height {
+            return Err(FontError::GlyphOutOfBounds { ch: ch, rect: rect, width: width, height: height });
+        }
+        Ok(())
+    }
+
+    // Dump the glyph's SDF region as ASCII art (one line per texel row),
+    // darker characters are further inside
+    pub fn debug_ascii(&self, buffer: &[u8], pitch: usize) -> String {
+        let ramp = b" .:-=+*#%@";
+        let mut out = String::with_capacity((self.width + 1) * self.height);
+        for y in self.y .. self.y + self.height {
+            for &value in &buffer[y * pitch + self.x .. y * pitch + self.x + self.width] {
+                out.push(ramp[value as usize * ramp.len() / 256] as char);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    // Load glyph for `ch` into face's glyph slot, return its box and outline
+    // (the face has to be set to em size, see `render_glyph_sdf`)
+    pub fn load_char(face: &ft::Face, ch: char, face_size: usize,
+                     padding: usize, config: &FontConfig) -> (Glyph, Outline) {
+        face.load_char(ch as usize, ft::face::NO_HINTING).unwrap();
+        let glyph = Glyph::from_face(face, face_size, padding, config);
+        let outline = Glyph::load_outline(face, face_size, config);
+        (glyph, outline)
+    }
+
+    // Outline of the glyph loaded in face's glyph slot (in face pixels),
+    // transformed and emboldened according to `config`
+    pub fn load_outline(face: &ft::Face, face_size: usize, config: &FontConfig) -> Outline {
         let outline_flags = face.glyph().raw().outline.flags;
         let unit_size = face.em_size() as f32 * 64. / face_size as f32;
+        let mut outline = outline_from_ft(&face.glyph().outline().unwrap(), unit_size);
 
         // Reversed contour orientation (counter-clockwise filled)
-        let reverse_fill = (outline_flags & 0x4) == 0x4; // FT_OUTLINE_REVERSE_FILL;
+        outline.reverse_fill = (outline_flags & 0x4) == 0x4; // FT_OUTLINE_REVERSE_FILL;
+
+        if let Some(ref m) = config.outline_transform(face_size) {
+            outline.transform(m);
+        }
+        outline.embolden(config.embolden);
+        outline
+    }
+
+    // Render SDF of the glyph loaded in face's glyph slot (not emboldened)
+    pub fn render_sdf(&self, face: &ft::Face, face_size: usize, params: &SdfParams,
+                      buffer: &mut [u8], pitch: usize) {
+        let outline = Glyph::load_outline(face, face_size, &FontConfig::default());
+        self.render_outline_sdf(&outline, face_size, params, buffer, pitch);
+    }
+
+    // Render SDF of an outline into new buffer of the glyph's size
+    // (pitch = width), ignoring the glyph position in font texture
+    pub fn render_standalone(&self, outline: &Outline, face_size: usize,
+                             params: &SdfParams) -> Vec<u8> {
+        let tile = Glyph { x: 0, y: 0, .. self.clone() };
+        let mut buffer = vec![0u8; self.width * self.height];
+        tile.render_outline_sdf(outline, face_size, params, &mut buffer, self.width);
+        buffer
+    }
 
+    // Render SDF of an outline (in face pixel coordinates)
+    pub fn render_outline_sdf(&self, outline: &Outline, face_size: usize, params: &SdfParams,
+                              buffer: &mut [u8], pitch: usize) {
         // Feed the outline segments into rasterizer. These are later queried
         // for scanline crossings and minimum distance from a point to the outline.
-        let mut rasterizer = Rasterizer::new();
-        let mut mindist = OutlineDistance::new();
-        for contour in outline.contours_iter() {
-            let mut p0 = vec2_from_ft(contour.start(), unit_size);
-            for curve in contour {
-                match curve {
-                    ft::outline::Curve::Line(a) => {
-                        let p1 = vec2_from_ft(a, unit_size);
-                        rasterizer.push_line(p0, p1);
-                        mindist.push_line(p0, p1);
-                        p0 = p1;
-                    }
-                    ft::outline::Curve::Bezier2(a, b) => {
-                        let p1 = vec2_from_ft(a, unit_size);
-                        let p2 = vec2_from_ft(b, unit_size);
-                        rasterizer.push_bezier2(p0, p1, p2);
-                        mindist.push_bezier2(p0, p1, p2);
-                        p0 = p2;
-                    }
-                    ft::outline::Curve::Bezier3(a, b, c) => {
-                        let p1 = vec2_from_ft(a, unit_size);
-                        let p2 = vec2_from_ft(b, unit_size);
-                        let p3 = vec2_from_ft(c, unit_size);
-                        rasterizer.push_bezier3(p0, p1, p2, p3);
-                        mindist.push_bezier3(p0, p1, p2, p3);
-                        p0 = p3;
-                    }
-                }
-            }
-        }
+        let rasterizer = outline.rasterizer();
+        let mindist = params.outline_distance(outline);
+        self.render_sdf_from(&rasterizer, &mindist, face_size, params, buffer, pitch);
+    }
 
-        // Render
-        for yr in 0 .. self.height {
-            let buffer_offset = (self.y + yr) * pitch + self.x;
-            let buffer_row = &mut buffer[buffer_offset .. buffer_offset + self.width];
+    // Render SDF of an outline already fed into `rasterizer` and `mindist`
+    // (in face pixel coordinates)
+    pub fn render_sdf_from(&self, rasterizer: &Rasterizer, mindist: &OutlineDistance,
+                           face_size: usize, params: &SdfParams,
+                           buffer: &mut [u8], pitch: usize) {
+        // The glyph rectangle has to fit in the buffer (see `check_bounds`)
+        debug_assert!(self.height == 0 || self.x + self.width <= pitch &&
+                      (self.y + self.height - 1) * pitch + self.x + self.width <= buffer.len(),
+                      "glyph {:?} out of buffer (pitch {}, len {})", self.rect(), pitch, buffer.len());
+        self.scan_sdf(rasterizer, mindist, face_size, params, |xr, yr, value| {
+            buffer[(self.y + yr) * pitch + self.x + xr] = value;
+        });
+    }
+
+    // Encoded SDF value of each pixel in the glyph box, passed to `store`
+    // with the pixel position (xr, yr) in the box (see `scan_distances`)
+    fn scan_sdf<F>(&self, rasterizer: &Rasterizer, mindist: &OutlineDistance,
+                   face_size: usize, params: &SdfParams, mut store: F)
+        where F: FnMut(usize, usize, u8)
+    {
+        // Distance (in face pixels) at which the encoded value saturates
+        let spread = params.saturation_distance(face_size);
+        let half_stroke = params.stroke_width.map(|w| 0.5 * w);
+        // The stroke reaches farther from the contours by its half width
+        let radius = spread + half_stroke.unwrap_or(0.0);
+        self.scan_distances(rasterizer, mindist, params, radius, |xr, yr, dist| {
+            let dist = match (dist, half_stroke) {
+                (Some(dist), Some(half)) => Some(dist.abs() - half),
+                (dist, _) => dist,
+            };
+            // Saturated outside border (see `padding`)
+            let value = dist.map_or(params.outside_value(), |dist| params.encode(dist, face_size));
+            store(xr, yr, value);
+        });
+    }
+
+    // Render signed distance of the glyph loaded in face's glyph slot (not emboldened)
+    // in face pixels, negative inside, without encoding (see `render_outline_sdf_f32`)
+    pub fn render_sdf_f32(&self, face: &ft::Face, face_size: usize, params: &SdfParams,
+                          buffer: &mut [f32], pitch: usize) {
+        let outline = Glyph::load_outline(face, face_size, &FontConfig::default());
+        self.render_outline_sdf_f32(&outline, params, buffer, pitch);
+    }
 
+    // Render signed distance of an outline (in face pixels), negative inside.
+    // The distances are exact, not saturated, also in the padding border.
+    pub fn render_outline_sdf_f32(&self, outline: &Outline, params: &SdfParams,
+                                  buffer: &mut [f32], pitch: usize) {
+        let rasterizer = outline.rasterizer();
+        let mindist = params.outline_distance(outline);
+        let tile = Glyph { padding: 0, .. self.clone() };
+        tile.scan_distances(&rasterizer, &mindist, params, f32::INFINITY, |xr, yr, dist| {
+            buffer[(self.y + yr) * pitch + self.x + xr] = dist.unwrap();
+        });
+    }
+
+    // Signed distance (in face pixels, negative inside) at center of each pixel
+    // in the glyph box, passed to `store` with the pixel position (xr, yr) in the box.
+    // Distances farther than `radius` are not computed, `radius` is passed instead.
+    // The pixels of the padding border get None (see `padding`).
+    fn scan_distances<F>(&self, rasterizer: &Rasterizer, mindist: &OutlineDistance,
+                         params: &SdfParams, radius: f32, mut store: F)
+        where F: FnMut(usize, usize, Option<f32>)
+    {
+        let mut crossings = Vec::new();
+        let samples = params.coverage_samples.max(1) as usize;
+        let mut inside_samples = vec![0usize; if samples > 1 { self.width } else { 0 }];
+        for yr in 0 .. self.height {
             let y = (self.ymin + (self.height - yr - 1) as isize) as f32 + 0.5;
 
-            let ref mut crossings = rasterizer.scanline_crossings(y);
+            if samples > 1 {
+                self.count_inside_samples(rasterizer, params.fill_rule, y - 0.5, samples,
+                                          &mut crossings, &mut inside_samples);
+            }
+            rasterizer.scanline_crossings_into(y, &mut crossings);
 
             // Find point distance
             let mut crossings_idx = 0;
             let mut wn = 0i32;
             for xr in 0 .. self.width {
                 let x = (self.xmin + xr as isize) as f32 + 0.5;
-                let mp = Vec2::new(x, y);
-
-                // Compute the distance
-                let mut dist_min = mindist.distance(mp);
-
                 // Is the point inside curve?
                 while crossings.len() > crossings_idx && crossings[crossings_idx].x <= x {
                     wn += crossings[crossings_idx].dir as i32;
                     crossings_idx += 1;
                 }
-                let inside = if reverse_fill { wn < 0 } else { wn > 0 };
+                // The winding is correct for either orientation (the profile
+                // directions come from the segments themselves), the outline's
+                // `reverse_fill` doesn't matter here.
+                let inside = if samples > 1 {
+                    inside_samples[xr] * 2 >= samples * samples
+                } else {
+                    params.fill_rule.is_inside(wn)
+                };
+
+                if self.padding > 0 && (xr == 0 || yr == 0 ||
+                                        xr + 1 == self.width || yr + 1 == self.height) {
+                    store(xr, yr, None);
+                    continue;
+                }
+
+                // Compute the distance. Pixels farther than the radius
+                // are saturated, their exact distance is not needed.
+                let p = Vec2::new(x, y);
+                let mut dist_min = if params.pseudo_distance {
+                    // The pseudo-distance may be within the radius even when
+                    // the true distance is not
+                    mindist.pseudo_distance(p).min(radius)
+                } else if radius.is_finite() {
+                    match mindist.distance_within(p, radius) {
+                        Some(dist) => dist,
+                        None => radius,
+                    }
+                } else {
+                    mindist.distance(p)
+                };
                 if inside {
                     dist_min = -dist_min;
+                } else if params.preserve_thin_strokes && dist_min < 0.5 {
+                    // The winding says outside, but the outline passes
+                    // through the pixel. Prefer the distance here, otherwise
+                    // strokes thinner than a texel would drop out.
+                    // (The distance has no sign, so inside pixels are kept as they are.)
+                    dist_min = 0.0;
                 }
 
-                // Convert float distance to discrete space (u8):
-                // 0 << 127 = outside
-                // 127 = zero distance (the outline)
-                // 128 >> 255 = inside
-                let shift = 127.0;
-                let scale = 1920. / face_size as f32;
-                dist_min = shift - dist_min * scale;
-                if dist_min < 0. { dist_min = 0.; }
-                if dist_min > 255. { dist_min = 255.; }
-                buffer_row[xr] = dist_min as u8;
+                store(xr, yr, Some(dist_min));
             }
         }
     }
-}
 
-pub struct Font {
-    // font texture buffer and size
-    pub buffer: Vec<u8>,
-    pub width: usize,
-    pub height: usize,
-    // metrics for glyphs contained in the texture
-    pub glyphs: HashMap<char, Glyph>,
-}
-
-impl Font {
-    pub fn new(square_size: usize) -> Self {
-        Font {
-            buffer: Vec::with_capacity(square_size * square_size),
-            width: square_size,
-            height: square_size,
-            glyphs: HashMap::new(),
+    // Count samples inside the outline in each pixel of the row whose bottom
+    // is at `bottom`, on a grid of `samples` x `samples` per pixel
+    // (see `SdfParams::coverage_samples`)
+    fn count_inside_samples(&self, rasterizer: &Rasterizer, fill_rule: FillRule, bottom: f32,
+                            samples: usize, crossings: &mut Vec<OrientedCrossing>,
+                            counts: &mut [usize]) {
+        for c in counts.iter_mut() { *c = 0; }
+        let step = 1.0 / samples as f32;
+        for k in 0 .. samples {
+            rasterizer.scanline_crossings_into(bottom + (k as f32 + 0.5) * step, crossings);
+            let mut crossings_idx = 0;
+            let mut wn = 0i32;
+            for xr in 0 .. self.width {
+                for j in 0 .. samples {
+                    let x = (self.xmin + xr as isize) as f32 + (j as f32 + 0.5) * step;
+                    while crossings.len() > crossings_idx && crossings[crossings_idx].x <= x {
+                        wn += crossings[crossings_idx].dir as i32;
+                        crossings_idx += 1;
+                    }
+                    if fill_rule.is_inside(wn) {
+                        counts[xr] += 1;
+                    }
+                }
+            }
         }
     }
 
-    pub fn build_from_file<P>(&mut self, path: P, face_index: isize, face_size: usize, padding: usize, chars: &str)
-        where P: AsRef<path::Path>
-    {
-        let library = ft::Library::init().unwrap();
-        let face = library.new_face(path.as_ref(), face_index).unwrap();
-        self.build_from_face(&face, face_size, padding, chars)
+    // Render SDF (channel 0) and coverage at the bake resolution (channel 1)
+    // of an outline into interleaved two-channel buffer (pitch in pixels).
+    // Both are computed in one pass over the rows: the coverage of each row
+    // is sampled when the distances reach its first pixel.
+    pub fn render_outline_sdf_coverage(&self, outline: &Outline, face_size: usize,
+                                       params: &SdfParams, buffer: &mut [u8], pitch: usize) {
+        let rasterizer = outline.rasterizer();
+        let mindist = params.outline_distance(outline);
+        let mut coverage = vec![0f32; self.width];
+        self.scan_sdf(&rasterizer, &mindist, face_size, params, |xr, yr, value| {
+            if xr == 0 {
+                self.row_coverage(&rasterizer, params.fill_rule, yr, &mut coverage);
+            }
+            let i = ((self.y + yr) * pitch + self.x + xr) * 2;
+            buffer[i] = value;
+            buffer[i + 1] = coverage_value(coverage[xr]);
+        });
     }
 
-    pub fn build_from_face(&mut self, face: &ft::Face, face_size: usize, padding: usize, chars: &str) {
-        let packer_config = rect_packer::Config {
-            width: self.width as i32,
-            height: self.height as i32,
-            border_padding: 0,
-            rectangle_padding: 0,
-        };
-        let mut packer = rect_packer::Packer::new(packer_config);
+    // Render coverage of an outline already fed into `rasterizer`
+    // (0 = empty, 255 = fully covered pixel). Each pixel row is sampled
+    // by COVERAGE_SAMPLES scanlines, with exact coverage along the X axis.
+    pub fn render_coverage_from(&self, rasterizer: &Rasterizer, buffer: &mut [u8], pitch: usize) {
+        self.render_coverage_with_rule(rasterizer, FillRule::NonZero, buffer, pitch);
+    }
 
-        self.glyphs.reserve(chars.len());
-        self.buffer.resize(self.width * self.height, 0u8);
+    // Like `render_coverage_from`, with the given fill rule
+    pub fn render_coverage_with_rule(&self, rasterizer: &Rasterizer, fill_rule: FillRule,
+                                     buffer: &mut [u8], pitch: usize) {
+        let mut coverage = vec![0f32; self.width];
+        for yr in 0 .. self.height {
+            self.row_coverage(rasterizer, fill_rule, yr, &mut coverage);
+            let buffer_offset = (self.y + yr) * pitch + self.x;
+            for (xr, &c) in coverage.iter().enumerate() {
+                buffer[buffer_offset + xr] = coverage_value(c);
+            }
+        }
+    }
 
-        face.set_pixel_sizes(face.em_size() as u32, 0).unwrap();
+    // Coverage of the pixels in row `yr` of the glyph box, summed over
+    // COVERAGE_SAMPLES scanlines (see `coverage_value`)
+    fn row_coverage(&self, rasterizer: &Rasterizer, fill_rule: FillRule, yr: usize,
+                    coverage: &mut [f32]) {
+        let n = COVERAGE_SAMPLES;
+        for c in coverage.iter_mut() { *c = 0.0; }
+        let bottom = (self.ymin + (self.height - yr - 1) as isize) as f32;
+        for k in 0 .. n {
+            let y = bottom + (k as f32 + 0.5) / n as f32;
+            rasterizer.scanline_coverage(y, fill_rule, self.xmin as f32, coverage);
+        }
+    }
+}
 
-        for ch in chars.chars() {
-            face.load_char(ch as usize, ft::face::NO_HINTING).unwrap();
-            let mut glyph = Glyph::from_face(&face, face_size, padding);
+// Packer of glyph rectangles into empty texture, `gap` pixels apart
+fn new_packer(width: usize, height: usize, gap: usize) -> rect_packer::Packer {
+    rect_packer::Packer::new(rect_packer::Config {
+        width: width as i32,
+        height: height as i32,
+        border_padding: 0,
+        rectangle_padding: gap as i32,
+    })
+}
 
-            if let Some(rect) = packer.pack(glyph.width as i32, glyph.height as i32, false) {
-                glyph.x = rect.x as usize;
-                glyph.y = rect.y as usize;
-            } else {
-                panic!("font texture not large enough");
-            }
+// Glyph of the font, by char or by glyph index (see `Font::index_glyphs`)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum GlyphKey {
+    Char(char),
+    Index(u32),
+}
 
-            glyph.render_sdf(&face, face_size, &mut self.buffer, self.width);
+impl GlyphKey {
+    fn texture_full(self) -> FontError {
+        match self {
+            GlyphKey::Char(ch) => FontError::TextureFull(ch),
+            GlyphKey::Index(index) => FontError::TextureFullForIndex(index),
+        }
+    }
+}
+
+// Content of a glyph tile to be rendered into the font texture
+enum GlyphSource {
+    Outline(Outline),
+    // pixels of the tile in all channels (RGBA image, MSDF)
+    Pixels(Vec<u8>),
+    // the same glyph as an already built one (both map to the same glyph in the face),
+    // they share the tile
+    Same(GlyphKey),
+}
+
+// Number of coverage scanlines per pixel
+const COVERAGE_SAMPLES: usize = 8;
+
+// Pixel value of coverage summed by `Glyph::row_coverage`
+fn coverage_value(sum: f32) -> u8 {
+    (sum / COVERAGE_SAMPLES as f32 * 255. + 0.5).min(255.) as u8
+}
+
+// Set the face to em size (for loading outlines) with the config's charmap and variations
+fn load_em_size(face: &ft::Face, config: &FontConfig) -> Result<(), FontError> {
+    check_scalable(face)?;
+    face.set_pixel_sizes(face.em_size() as u32, 0).unwrap();
+    reset_transform(face);
+    if let Some(charmap) = config.charmap {
+        select_charmap(face, charmap)?;
+    }
+    set_variations(face, &config.variations);
+    Ok(())
+}
+
+// Render SDF of single glyph into standalone buffer (pitch = glyph width),
+// without building a font texture. The glyph position (x, y) is zero.
+pub fn render_glyph_sdf(face: &ft::Face, ch: char, face_size: usize, padding: usize,
+                        config: &FontConfig, params: &SdfParams)
+                        -> Result<(Vec<u8>, Glyph), FontError> {
+    load_em_size(face, config)?;
+    let (glyph, outline) = Glyph::load_char(face, ch, face_size, padding, config);
+    let buffer = glyph.render_standalone(&outline, face_size, params);
+    Ok((buffer, glyph))
+}
+
+// Samples per em (in both directions) of `min_stroke_width`
+const STROKE_SAMPLES_PER_EM: f32 = 256.0;
+
+// Maximal cosine of the angle between the directions to the outline from both
+// sides of a stroke peak (about 154°, the edges of a stroke are nearly opposite)
+const STROKE_MAX_COS: f32 = -0.9;
+
+// Estimate the thinnest stroke of the outline (in face pixels). The inside
+// distance is sampled on a grid, its peaks along the rows or columns lie on the medial
+// axis, where the distance is half of the stroke width. The corners make
+// branches of the axis with arbitrarily small peaks. These are rejected:
+// across a stroke, the nearest points from both sides of the peak are on opposite
+// edges, at a corner they're on the adjacent edges, at an angle. A shape
+// without any stroke peak (e.g. a rotated square) has the width of its largest
+// inscribed circle. Infinity for empty outline, zero when no sample is inside.
+fn min_stroke_width(outline: &Outline, face_size: usize, params: &SdfParams) -> f32 {
+    if outline.contours.is_empty() {
+        return f32::INFINITY;
+    }
+    let rasterizer = outline.rasterizer();
+    let mindist = params.outline_distance(outline);
+    let (xmin, ymin, xmax, ymax) = outline.control_box();
+    let step = face_size as f32 / STROKE_SAMPLES_PER_EM;
+    let nx = ((xmax - xmin) / step).ceil() as usize + 1;
+    let ny = ((ymax - ymin) / step).ceil() as usize + 1;
+
+    // Inside distances, negative outside
+    let mut dist = vec![-1.0f32; nx * ny];
+    let mut crossings = Vec::new();
+    for j in 0 .. ny {
+        let y = ymin + j as f32 * step;
+        rasterizer.scanline_crossings_into(y, &mut crossings);
+        let mut crossings_idx = 0;
+        let mut wn = 0i32;
+        for i in 0 .. nx {
+            let x = xmin + i as f32 * step;
+            while crossings.len() > crossings_idx && crossings[crossings_idx].x <= x {
+                wn += crossings[crossings_idx].dir as i32;
+                crossings_idx += 1;
+            }
+            if params.fill_rule.is_inside(wn) {
+                dist[j * nx + i] = mindist.distance(Vec2::new(x, y));
+            }
+        }
+    }
+
+    // Equal neighbours (plateaus along straight edges) are not peaks
+    let eps = step * 1e-3;
+    let at = |i: isize, j: isize| {
+        if i < 0 || j < 0 || i >= nx as isize || j >= ny as isize {
+            -1.0
+        } else {
+            dist[j as usize * nx + i as usize]
+        }
+    };
+    let mut widths = Vec::new();
+    let mut max_dist = 0.0f32;
+    for j in 0 .. ny as isize {
+        for i in 0 .. nx as isize {
+            let d = at(i, j);
+            if d <= 0.0 {
+                continue;
+            }
+            max_dist = max_dist.max(d);
+            let is_peak = |a: f32, b: f32| a < d - eps && b < d - eps;
+            let p = Vec2::new(xmin + i as f32 * step, ymin + j as f32 * step);
+            // The points half-way to the outline on both sides are still inside
+            let across = |axis: Vec2| {
+                let (a, b) = (p - (d / 2.) * axis, p + (d / 2.) * axis);
+                let to_a = (mindist.nearest_point(a).point - a).normalize();
+                let to_b = (mindist.nearest_point(b).point - b).normalize();
+                to_a.dot(to_b) < STROKE_MAX_COS
+            };
+            if (is_peak(at(i - 1, j), at(i + 1, j)) && across(Vec2::new(1.0, 0.0))) ||
+                    (is_peak(at(i, j - 1), at(i, j + 1)) && across(Vec2::new(0.0, 1.0))) {
+                widths.push(2.0 * d);
+            }
+        }
+    }
+    widths.into_iter().fold(None, |min: Option<f32>, w| Some(min.map_or(w, |m| m.min(w))))
+        .unwrap_or(2.0 * max_dist)
+}
+
+// Render SDF of closed contours (in face pixels, outer ones clockwise) into
+// standalone buffer like `render_glyph_sdf`, without a face. The buffer covers
+// the bounding box (xmin, ymin, xmax, ymax), expanded to whole pixels,
+// with `padding` around it.
+pub fn render_contours_sdf(contours: Vec<Vec<PathSegment>>, bbox: (f32, f32, f32, f32),
+                           face_size: usize, padding: usize,
+                           params: &SdfParams) -> (Vec<u8>, Glyph) {
+    let outline = Outline { contours: contours, reverse_fill: false };
+    let (xmin, ymin, xmax, ymax) = bbox;
+    let glyph = Glyph::from_bbox(xmin, ymin, xmax, ymax, padding, BoxRounding::Expand);
+    let buffer = glyph.render_standalone(&outline, face_size, params);
+    (buffer, glyph)
+}
+
+pub struct Font {
+    // font texture buffer and size
+    pub buffer: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    // number of interleaved channels in the buffer (1 = grayscale, 4 = RGBA),
+    // set before building
+    pub channels: usize,
+    // length of buffer row in pixels (>= width), see `align_texture`.
+    // Texture coordinates are still relative to `width`.
+    pub pitch: usize,
+    // face size (in pixels) the glyphs were rendered at
+    pub face_size: usize,
+    // padding (in pixels) around the glyphs of the last build (see `add_chars`)
+    pub padding: usize,
+    // configuration and parameters used when rendering the glyphs
+    pub config: FontConfig,
+    pub params: SdfParams,
+    // line decorations, in face pixels
+    pub underline: Decoration,
+    pub strikeout: Decoration,
+    // vertical metrics of the face, in face pixels (see `line_metrics`)
+    pub vertical: LineMetrics,
+    // metrics for glyphs contained in the texture
+    pub glyphs: HashMap<char, Glyph>,
+    // glyphs built by glyph index of the face (see `build_from_face_indices`),
+    // they share the texture with `glyphs`
+    pub index_glyphs: HashMap<u32, Glyph>,
+    // region of the texture modified since last `take_dirty` (for partial uploads)
+    pub dirty: Option<Rect>,
+    // glyph outlines in em units, with `FontConfig::retain_outlines`
+    pub outlines: HashMap<char, Outline>,
+    // the same for `index_glyphs`
+    pub index_outlines: HashMap<u32, Outline>,
+    // kerning of glyph pairs (left, right) in face pixels, only the non-zero ones
+    // (see `kerning`)
+    pub kerning_pairs: HashMap<(char, char), Vec2>,
+    // free space of the texture per channel, kept between builds
+    packers: HashMap<usize, rect_packer::Packer>,
+}
+
+impl Font {
+    // Font with square texture
+    pub fn new(square_size: usize) -> Self {
+        Font::with_size(square_size, square_size)
+    }
+
+    // Font with texture of `width` x `height` pixels
+    pub fn with_size(width: usize, height: usize) -> Self {
+        Font {
+            buffer: Vec::with_capacity(width * height),
+            width: width,
+            height: height,
+            channels: 1,
+            pitch: width,
+            face_size: 0,
+            padding: 0,
+            config: FontConfig::default(),
+            params: SdfParams::default(),
+            underline: Decoration::default(),
+            strikeout: Decoration::default(),
+            vertical: LineMetrics::default(),
+            glyphs: HashMap::new(),
+            index_glyphs: HashMap::new(),
+            dirty: None,
+            outlines: HashMap::new(),
+            index_outlines: HashMap::new(),
+            kerning_pairs: HashMap::new(),
+            packers: HashMap::new(),
+        }
+    }
+
+    // Adjust the texture size for restrictive GPU backends (before building,
+    // after setting `channels`): with `power_of_two`, round the width and height
+    // up to a power of two, and pad the buffer rows to a multiple
+    // of `row_alignment` bytes (e.g. 4 for GL's default UNPACK_ALIGNMENT).
+    // The padded rows are uploaded with the row length set to `pitch`.
+    pub fn align_texture(&mut self, power_of_two: bool, row_alignment: usize) {
+        assert!(self.glyphs.is_empty(), "texture layout can't change after building");
+        if power_of_two {
+            self.width = self.width.next_power_of_two();
+            self.height = self.height.next_power_of_two();
+        }
+        self.pitch = self.width;
+        while (self.pitch * self.channels) % row_alignment.max(1) != 0 {
+            self.pitch += 1;
+        }
+        self.buffer.clear();
+    }
+
+    // Extend the dirty region by `rect`
+    pub fn mark_dirty(&mut self, rect: Rect) {
+        if rect.w == 0 || rect.h == 0 {
+            return;
+        }
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(&rect),
+            None => rect,
+        });
+    }
+
+    // Get the dirty region and reset it
+    pub fn take_dirty(&mut self) -> Option<Rect> {
+        self.dirty.take()
+    }
+
+    // Pack all glyphs anew into the texture, moving their rendered regions.
+    // This removes the fragmentation after many glyphs were added and removed.
+    // On error (the glyphs don't fit), the font is left unchanged.
+    pub fn repack(&mut self) -> Result<(), FontError> {
+        let mut packer = new_packer(self.width, self.height, self.config.gap);
+        // Biggest first packs tighter, the char (index) makes the order stable
+        let mut keys: Vec<GlyphKey> = self.glyphs.keys().map(|&ch| GlyphKey::Char(ch))
+            .chain(self.index_glyphs.keys().map(|&index| GlyphKey::Index(index)))
+            .collect();
+        keys.sort_by_key(|&key| {
+            let glyph = self.glyph_by_key(key);
+            (!0 - glyph.height, !0 - glyph.width, key)
+        });
+        let mut placed = Vec::with_capacity(keys.len());
+        // New positions of the tiles, shared tiles stay shared
+        let mut moved: HashMap<Rect, (usize, usize)> = HashMap::new();
+        let mut packed = Vec::with_capacity(keys.len());
+        for &key in keys.iter() {
+            let glyph = self.glyph_by_key(key);
+            if glyph.width == 0 || glyph.height == 0 {
+                placed.push((key, 0, 0));
+                continue;
+            }
+            if let Some(&(x, y)) = moved.get(&glyph.rect()) {
+                placed.push((key, x, y));
+                continue;
+            }
+            match packer.pack(glyph.width as i32, glyph.height as i32, false) {
+                Some(rect) => {
+                    let (x, y) = (rect.x as usize, rect.y as usize);
+                    moved.insert(glyph.rect(), (x, y));
+                    placed.push((key, x, y));
+                    packed.push((glyph.width, glyph.height));
+                }
+                None => return Err(key.texture_full()),
+            }
+        }
+        // Copy the regions (all channels) into new buffer
+        let outside = self.params.outside_value();
+        let mut buffer = vec![outside; self.pitch * self.height * self.channels];
+        for &(key, x, y) in placed.iter() {
+            let glyph = match key {
+                GlyphKey::Char(ch) => self.glyphs.get_mut(&ch).unwrap(),
+                GlyphKey::Index(index) => self.index_glyphs.get_mut(&index).unwrap(),
+            };
+            let row_len = glyph.width * self.channels;
+            for yr in 0 .. glyph.height {
+                let src = ((glyph.y + yr) * self.pitch + glyph.x) * self.channels;
+                let dst = ((y + yr) * self.pitch + x) * self.channels;
+                buffer[dst .. dst + row_len].copy_from_slice(&self.buffer[src .. src + row_len]);
+            }
+            glyph.x = x;
+            glyph.y = y;
+        }
+        self.buffer = buffer;
+        self.dirty = Some(Rect::new(0, 0, self.width, self.height));
+        // Each channel continues packing after the new layout
+        // (the packing is deterministic, replay it for every channel)
+        self.packers.clear();
+        let mut channels: Vec<usize> = self.glyphs.values().chain(self.index_glyphs.values())
+            .map(|glyph| glyph.channel).collect();
+        channels.sort();
+        channels.dedup();
+        for channel in channels {
+            let mut packer = new_packer(self.width, self.height, self.config.gap);
+            for &(width, height) in packed.iter() {
+                packer.pack(width as i32, height as i32, false);
+            }
+            self.packers.insert(channel, packer);
+        }
+        Ok(())
+    }
+
+    fn glyph_by_key(&self, key: GlyphKey) -> &Glyph {
+        self.find_glyph(key).unwrap()
+    }
+
+    fn find_glyph(&self, key: GlyphKey) -> Option<&Glyph> {
+        match key {
+            GlyphKey::Char(ch) => self.glyphs.get(&ch),
+            GlyphKey::Index(index) => self.index_glyphs.get(&index),
+        }
+    }
+
+    fn insert_glyph(&mut self, key: GlyphKey, glyph: Glyph) {
+        match key {
+            GlyphKey::Char(ch) => self.glyphs.insert(ch, glyph),
+            GlyphKey::Index(index) => self.index_glyphs.insert(index, glyph),
+        };
+    }
+
+    // Render SDF of a glyph from its retained outline at `face_size`, bypassing
+    // the texture (e.g. for deep zoom). The glyph has the same padding as in
+    // the texture, its position (x, y) is zero. None when there is no outline
+    // for `ch` (see `FontConfig::retain_outlines`).
+    pub fn render_glyph_exact(&self, ch: char, face_size: usize) -> Option<(Vec<u8>, Glyph)> {
+        let (outline, atlas_glyph) = match (self.outlines.get(&ch), self.glyphs.get(&ch)) {
+            (Some(outline), Some(glyph)) => (outline, glyph),
+            _ => return None,
+        };
+        let mut outline = outline.clone();
+        outline.transform(&Transform::scale(face_size as f32, face_size as f32));
+        let mut glyph = Glyph::from_outline(&outline, atlas_glyph.padding, &self.config);
+        let scale = face_size as f32 / self.face_size as f32;
+        glyph.advance_x = atlas_glyph.advance_x * scale;
+        glyph.advance_y = atlas_glyph.advance_y * scale;
+        let buffer = glyph.render_standalone(&outline, face_size, &self.params);
+        Some((buffer, glyph))
+    }
+
+    // Texture location, placement and advance of glyph for `ch`
+    pub fn glyph_info(&self, ch: char) -> Option<GlyphInfo> {
+        self.glyphs.get(&ch).map(|glyph| {
+            // Relative to the texture width, not the row pitch
+            let (w, h) = (self.width as f32, self.height as f32);
+            GlyphInfo {
+                page: 0,
+                channel: glyph.channel,
+                uv_rect: (glyph.x as f32 / w, glyph.y as f32 / h,
+                          (glyph.x + glyph.width) as f32 / w, (glyph.y + glyph.height) as f32 / h),
+                placement: (glyph.xmin as f32 / glyph.scale, glyph.ymin as f32 / glyph.scale,
+                            glyph.width as f32 / glyph.scale, glyph.height as f32 / glyph.scale),
+                advance: glyph.advance_x,
+            }
+        })
+    }
+
+    // Quad drawing the glyph for `ch` with the pen at `pen` (in em, on the baseline).
+    // The texture coordinates are relative to the texture size (not the pitch),
+    // the edges of the tile (not the texel centers) map to the quad's edges.
+    pub fn glyph_quad(&self, ch: char, pen: Vec2) -> Option<GlyphQuad> {
+        self.glyph_info(ch).map(|info| {
+            let em = 1.0 / self.face_size as f32;
+            let (u0, v0, u1, v1) = info.uv_rect;
+            let (x, y, w, h) = info.placement;
+            let (left, bottom) = (pen.x + x * em, pen.y + y * em);
+            GlyphQuad {
+                uvs: [Vec2::new(u0, v1), Vec2::new(u1, v1), Vec2::new(u0, v0), Vec2::new(u1, v0)],
+                rect: (left, bottom, left + w * em, bottom + h * em),
+                channel: info.channel,
+                advance: info.advance * em,
+            }
+        })
+    }
+
+    // Kerning of the pair of chars (in face pixels), to be added to the pen
+    // position between them. Zero when the face has no kerning for the pair.
+    pub fn kerning(&self, left: char, right: char) -> Vec2 {
+        match self.kerning_pairs.get(&(left, right)) {
+            Some(&kerning) => kerning,
+            None => Vec2::new(0.0, 0.0),
+        }
+    }
+
+    // Read kerning of all pairs of glyphs in the font from the faces. Each char has
+    // kerning with chars from the same face (the first face containing the char).
+    fn load_kerning(&mut self, faces: &[&ft::Face]) {
+        let mut indices = Vec::with_capacity(self.glyphs.len());
+        for &ch in self.glyphs.keys() {
+            let found = faces.iter().enumerate()
+                .map(|(i, face)| (i, face.get_char_index(ch as usize)))
+                .find(|&(_, index)| index != 0);
+            if let Some((face_idx, index)) = found {
+                indices.push((ch, face_idx, index));
+            }
+        }
+        for &(left, left_face, left_index) in &indices {
+            let face = faces[left_face];
+            if !face.has_kerning() {
+                continue;
+            }
+            // font units to face pixels
+            let scale = self.face_size as f32 / face.em_size() as f32;
+            for &(right, right_face, right_index) in &indices {
+                if right_face != left_face {
+                    continue;
+                }
+                if let Ok(kerning) = face.get_kerning(left_index, right_index,
+                                                      ft::face::KerningMode::KerningUnscaled) {
+                    if kerning.x != 0 || kerning.y != 0 {
+                        let kerning = Vec2::new(kerning.x as f32 * scale, kerning.y as f32 * scale);
+                        self.kerning_pairs.insert((left, right), kerning);
+                    }
+                }
+            }
+        }
+    }
+
+    // Vertical metrics for text laid out at `target_px` size
+    pub fn line_metrics(&self, target_px: f32) -> LineMetrics {
+        self.vertical.scaled(target_px / self.face_size as f32)
+    }
+
+    // The texture thresholded at the outline value (see `sdf_to_monochrome`),
+    // in the same layout as `buffer`
+    pub fn threshold_to_monochrome(&self) -> Vec<u8> {
+        let threshold = self.params.encode(0.0, self.face_size);
+        if self.params.invert {
+            sdf_to_monochrome(&invert_sdf(&self.buffer, self.params.midpoint), threshold)
+        } else {
+            sdf_to_monochrome(&self.buffer, threshold)
+        }
+    }
+
+    // Check that the texture and glyph metadata are consistent: the buffer size
+    // matches the dimensions, every glyph lies in the texture and no two glyphs
+    // in the same channel overlap. Use after loading a font baked into files.
+    pub fn validate(&self) -> Result<(), FontError> {
+        let size = self.pitch * self.height * self.channels;
+        if self.buffer.len() != size && !(self.buffer.is_empty() && self.glyphs.is_empty() &&
+                                          self.index_glyphs.is_empty()) {
+            return Err(FontError::CorruptAtlas(format!(
+                "buffer has {} bytes, expected {} ({}x{}, pitch {}, {} channels)",
+                self.buffer.len(), size, self.width, self.height, self.pitch, self.channels)));
+        }
+        self.validate_layout()
+    }
+
+    // The glyph part of `validate` (bounds and overlaps), without the buffer
+    fn validate_layout(&self) -> Result<(), FontError> {
+        let mut keys: Vec<GlyphKey> = self.glyphs.keys().map(|&ch| GlyphKey::Char(ch))
+            .chain(self.index_glyphs.keys().map(|&index| GlyphKey::Index(index)))
+            .collect();
+        keys.sort();
+        let label = |key: GlyphKey| match key {
+            GlyphKey::Char(ch) => format!("{:?}", ch),
+            GlyphKey::Index(index) => format!("#{}", index),
+        };
+        for (i, &key) in keys.iter().enumerate() {
+            let glyph = self.glyph_by_key(key);
+            let rect = glyph.rect();
+            if rect.right() > self.width || rect.bottom() > self.height ||
+                    glyph.channel >= self.channels {
+                return Err(FontError::CorruptAtlas(format!(
+                    "glyph {} at {:?} (channel {}) is out of {}x{} texture ({} channels)",
+                    label(key), rect, glyph.channel, self.width, self.height, self.channels)));
+            }
+            for &other_key in keys[i + 1 ..].iter() {
+                let other = self.glyph_by_key(other_key);
+                // The chars of the same glyph share the tile
+                if other.channel == glyph.channel && rect.intersects(&other.rect()) &&
+                        rect != other.rect() {
+                    return Err(FontError::CorruptAtlas(format!(
+                        "glyphs {} at {:?} and {} at {:?} overlap",
+                        label(key), rect, label(other_key), other.rect())));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Find characters from `chars` which have no glyph in the face
+    // (these would be rendered as `.notdef` glyph)
+    pub fn missing_chars(face: &ft::Face, chars: &str) -> Vec<char> {
+        let mut missing = Vec::new();
+        for ch in chars.chars() {
+            if face.get_char_index(ch as usize) == 0 && !missing.contains(&ch) {
+                missing.push(ch);
+            }
+        }
+        missing
+    }
+
+    // Open the face from file and build the glyphs (see `build_from_face`).
+    // For a named instance of a variable font, give its number in the upper
+    // 16 bits of `face_index` (FreeType's convention), or set the axes
+    // by `FontConfig::variations`.
+    pub fn build_from_file<P>(&mut self, path: P, face_index: isize, face_size: usize,
+                              padding: usize, chars: &str) -> Result<(), FontError>
+        where P: AsRef<path::Path>
+    {
+        let library = ft::Library::init().unwrap();
+        let face = library.new_face(path.as_ref(), face_index).unwrap();
+        self.build_from_face(&face, face_size, padding, chars)
+    }
+
+    // Build the glyphs of `chars` from the face at `face_size` (in pixels per em),
+    // with `padding` pixels around each glyph. Fails when the face is not
+    // scalable, the glyphs don't fit (`TextureFull`) or a glyph box lies outside
+    // the texture (`GlyphOutOfBounds`), the glyphs built so far stay valid.
+    pub fn build_from_face(&mut self, face: &ft::Face, face_size: usize, padding: usize,
+                           chars: &str) -> Result<(), FontError> {
+        self.build_channel_from_face(face, face_size, padding, chars, 0)
+    }
+
+    // Build the glyphs into one `channel` of interleaved texture buffer
+    // (see `channels`), so several fonts can be layered into one texture.
+    // Chars already present in the font are rendered into their existing boxes
+    // (the layout is shared, so the channels line up), other chars are packed anew
+    // and may overlap glyphs in the other channels.
+    pub fn build_channel_from_face(&mut self, face: &ft::Face, face_size: usize, padding: usize,
+                                   chars: &str, channel: usize) -> Result<(), FontError> {
+        self.build_face(face, face_size, padding, chars, channel, None, None, None)
+    }
+
+    // Like `build_from_face`, but checks `cancel` before each glyph.
+    // When it's set, the build stops with `FontError::Cancelled`,
+    // leaving the font with the glyphs rendered so far (these are valid).
+    pub fn build_from_face_cancellable(&mut self, face: &ft::Face, face_size: usize, padding: usize,
+                                       chars: &str, cancel: &AtomicBool) -> Result<(), FontError> {
+        self.build_face(face, face_size, padding, chars, 0, Some(cancel), None, None)
+    }
+
+    // Like `build_from_face`, but calls `on_glyph` after each glyph is rendered,
+    // with the glyph and its pixels (its rectangle in the channel, pitch = width),
+    // e.g. to upload the glyphs to GPU incrementally or to report progress.
+    // The glyphs are rendered one by one, even with the `rayon` feature.
+    pub fn build_from_face_with<F>(&mut self, face: &ft::Face, face_size: usize, padding: usize,
+                                   chars: &str, mut on_glyph: F) -> Result<(), FontError>
+        where F: FnMut(char, &Glyph, &[u8])
+    {
+        self.build_face(face, face_size, padding, chars, 0, None, None, Some(&mut on_glyph))
+    }
+
+    // Like `build_from_file`, but the texture grows when the glyphs don't fit
+    // (see `build_from_face_auto`)
+    pub fn build_from_file_auto<P>(&mut self, path: P, face_index: isize, face_size: usize,
+                                   padding: usize, chars: &str, max_size: usize)
+                                   -> Result<(usize, usize), FontError>
+        where P: AsRef<path::Path>
+    {
+        let library = ft::Library::init().unwrap();
+        let face = library.new_face(path.as_ref(), face_index).unwrap();
+        self.build_from_face_auto(&face, face_size, padding, chars, max_size)
+    }
+
+    // Like `build_from_face`, but when the glyphs don't fit, the texture is
+    // doubled in both dimensions and the build starts over (discarding
+    // all glyphs of the font), as long as the larger side stays within `max_size`.
+    // Returns the final texture size (width, height). A glyph which doesn't fit
+    // even the largest texture fails with `FontError::TextureFull`.
+    pub fn build_from_face_auto(&mut self, face: &ft::Face, face_size: usize, padding: usize,
+                                chars: &str, max_size: usize) -> Result<(usize, usize), FontError> {
+        // An empty texture wouldn't grow by doubling
+        assert!(self.width > 0 && self.height > 0, "texture size must not be zero");
+        loop {
+            match self.build_face(face, face_size, padding, chars, 0, None, None, None) {
+                Ok(()) => return Ok((self.width, self.height)),
+                Err(FontError::TextureFull(ch)) if self.width.max(self.height) * 2 <= max_size => {
+                    warn!("glyph {:?} doesn't fit in {}x{} texture, doubling it",
+                          ch, self.width, self.height);
+                    self.width *= 2;
+                    self.height *= 2;
+                    // Keeps the row alignment (see `align_texture`)
+                    self.pitch *= 2;
+                    self.clear_glyphs();
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Remove all glyphs and clear the texture
+    fn clear_glyphs(&mut self) {
+        self.buffer.clear();
+        self.glyphs.clear();
+        self.index_glyphs.clear();
+        self.outlines.clear();
+        self.index_outlines.clear();
+        self.kerning_pairs.clear();
+        self.packers.clear();
+        self.dirty = None;
+    }
+
+    // Re-render glyph `ch`, already in the font, from `face` into its existing
+    // tile (e.g. after changing the face's variation coordinates), without
+    // disturbing the layout. The tile is marked dirty. The new glyph box
+    // must be the same as the stored one (`FontError::GlyphBoxChanged`).
+    // The advance is updated.
+    pub fn rerender_glyph(&mut self, ch: char, face: &ft::Face) -> Result<(), FontError> {
+        let existing = match self.glyphs.get(&ch) {
+            Some(glyph) => glyph.clone(),
+            None => return Err(FontError::GlyphNotFound(ch)),
+        };
+        face.set_pixel_sizes(face.em_size() as u32, 0).unwrap();
+        reset_transform(face);
+        if let Some(charmap) = self.config.charmap {
+            select_charmap(face, charmap)?;
+        }
+        let (glyph, outline) = Glyph::load_char(face, ch, self.face_size, existing.padding, &self.config);
+        if existing.image || (glyph.width, glyph.height, glyph.xmin, glyph.ymin) !=
+                             (existing.width, existing.height, existing.xmin, existing.ymin) {
+            return Err(FontError::GlyphBoxChanged(ch));
+        }
+        if self.config.retain_outlines {
+            let mut em_outline = outline.clone();
+            let scale = 1.0 / self.face_size as f32;
+            em_outline.transform(&Transform::scale(scale, scale));
+            self.outlines.insert(ch, em_outline);
+        }
+        let advance_x = glyph.advance_x;
+        let mut source = Some((glyph, outline));
+        self.build_glyphs(&ch.to_string(), existing.channel, None, |_| source.take().unwrap())?;
+        self.glyphs.get_mut(&ch).unwrap().advance_x = advance_x;
+        Ok(())
+    }
+
+    // Add a batch of new chars to the font (e.g. ASCII first, then extended sets),
+    // packed into the space left by previous builds. Unlike `build_from_face`,
+    // chars already present are skipped, the existing glyphs are kept as they are.
+    pub fn build_append(&mut self, face: &ft::Face, face_size: usize, padding: usize,
+                        chars: &str) -> Result<(), FontError> {
+        let new_chars: String = chars.chars().filter(|ch| !self.glyphs.contains_key(ch)).collect();
+        if new_chars.is_empty() {
+            return Ok(());
+        }
+        self.build_face(face, face_size, padding, &new_chars, 0, None, None, None)
+    }
+
+    // Add new chars to an already built font, at its face size and glyph padding
+    // (see `build_append`). The occupied regions of the texture are not touched.
+    // Fails with `FontError::TextureFull` when a glyph doesn't fit in the free space.
+    pub fn add_chars(&mut self, face: &ft::Face, chars: &str) -> Result<(), FontError> {
+        assert!(self.face_size != 0, "font is not built yet");
+        let (face_size, padding) = (self.face_size, self.padding);
+        self.build_append(face, face_size, padding, chars)
+    }
+
+    // Render SDF of a single char with the font's config and params into
+    // standalone buffer (pitch = glyph width), without touching the texture.
+    // The box is the same as the char would get in the atlas, the position
+    // (x, y) is zero. See `render_glyph_sdf`.
+    pub fn render_char_to_buffer(&self, face: &ft::Face, face_size: usize, padding: usize,
+                                 ch: char) -> Result<(Glyph, Vec<u8>), FontError> {
+        let (buffer, glyph) = render_glyph_sdf(face, ch, face_size, padding,
+                                               &self.config, &self.params)?;
+        Ok((glyph, buffer))
+    }
+
+    // Estimate the thinnest stroke of each char at `face_size` (in face pixels)
+    // with the font's config and params, before building the texture. Strokes
+    // thinner than 2 pixels lose their interior in the SDF (see `min_face_size`),
+    // so a larger face size may be needed. Chars without outline (space) get infinity.
+    pub fn analyze(&self, face: &ft::Face, face_size: usize, chars: &str)
+                   -> Result<Vec<(char, f32)>, FontError> {
+        load_em_size(face, &self.config)?;
+        Ok(chars.chars().map(|ch| {
+            let (_, outline) = Glyph::load_char(face, ch, face_size, 0, &self.config);
+            (ch, min_stroke_width(&outline, face_size, &self.params))
+        }).collect())
+    }
+
+    // Build glyphs by their index in the face (e.g. ligatures or alternates picked
+    // by a shaper, which have no char in the charmap), stored in `index_glyphs`.
+    // The glyphs are packed into channel 0, next to the glyphs built from chars.
+    // A glyph already built for a char shares its tile. Indices already present
+    // are skipped.
+    pub fn build_from_face_indices(&mut self, face: &ft::Face, face_size: usize,
+                                   padding: usize, indices: &[u32]) -> Result<(), FontError> {
+        self.build_face_indices(face, face_size, padding, indices, None)
+    }
+
+    // Like `build_from_face_indices`, but checks `cancel` before each glyph
+    // (see `build_from_face_cancellable`)
+    pub fn build_from_face_indices_cancellable(&mut self, face: &ft::Face, face_size: usize,
+                                               padding: usize, indices: &[u32],
+                                               cancel: &AtomicBool) -> Result<(), FontError> {
+        self.build_face_indices(face, face_size, padding, indices, Some(cancel))
+    }
+
+    fn build_face_indices(&mut self, face: &ft::Face, face_size: usize, padding: usize,
+                          indices: &[u32], cancel: Option<&AtomicBool>) -> Result<(), FontError> {
+        self.prepare_face(face, face_size, padding)?;
+        let outside = self.params.outside_value();
+        self.buffer.resize(self.pitch * self.height * self.channels, outside);
+
+        let mut keys = Vec::with_capacity(indices.len());
+        let mut seen = HashSet::new();
+        for &index in indices {
+            if !self.index_glyphs.contains_key(&index) && seen.insert(index) {
+                keys.push(GlyphKey::Index(index));
+            }
+        }
+        // Chars in channel 0 by their glyph index
+        let mut chars_by_index = HashMap::new();
+        for (&ch, glyph) in &self.glyphs {
+            if glyph.channel == 0 {
+                chars_by_index.entry(face.get_char_index(ch as usize)).or_insert((ch, glyph.clone()));
+            }
+        }
+        let config = self.config.clone();
+        let mut load = |key: GlyphKey| -> Result<(Glyph, GlyphSource), FontError> {
+            let index = match key {
+                GlyphKey::Index(index) => index,
+                GlyphKey::Char(_) => unreachable!(),
+            };
+            if face.load_glyph(index, ft::face::NO_HINTING).is_err() {
+                return Err(FontError::GlyphIndexNotFound(index));
+            }
+            let glyph = Glyph::from_face(face, face_size, padding, &config);
+            if let Some(&(ch, ref built)) = chars_by_index.get(&index) {
+                if glyph.same_box(built) {
+                    return Ok((Glyph::default(), GlyphSource::Same(GlyphKey::Char(ch))));
+                }
+            }
+            let outline = Glyph::load_outline(face, face_size, &config);
+            Ok((glyph, GlyphSource::Outline(outline)))
+        };
+        let mut tiles = Vec::with_capacity(keys.len());
+        let result = self.pack_keys(&keys, 0, cancel, &mut load, &mut tiles);
+        self.render_tiles(&tiles, 0);
+        result
+    }
+
+    // Build the glyphs from a fallback chain of faces: each char comes from
+    // the first face which has it (chars missing in all faces are rendered
+    // as .notdef of the first face). The faces may have different units per em,
+    // each glyph is scaled by its own face, so the glyphs match in size and baseline.
+    // The line decorations are taken from the first face.
+    pub fn build_from_faces(&mut self, faces: &[&ft::Face], face_size: usize,
+                            padding: usize, chars: &str) -> Result<(), FontError> {
+        assert!(!faces.is_empty(), "no faces given");
+        for face in faces.iter() {
+            check_scalable(face)?;
+        }
+        self.face_size = face_size;
+        self.padding = padding;
+        self.underline = Decoration::underline_from_face(faces[0], face_size);
+        self.strikeout = Decoration::strikeout_from_face(faces[0], face_size);
+        self.vertical = LineMetrics::from_face(faces[0], face_size);
+        for face in faces.iter() {
+            face.set_pixel_sizes(face.em_size() as u32, 0).unwrap();
+            reset_transform(face);
+            if let Some(charmap) = self.config.charmap {
+                select_charmap(face, charmap)?;
+            }
+        }
+        let config = self.config.clone();
+        self.build_glyphs(chars, 0, None, |ch| {
+            let face = match faces.iter().find(|face| face.get_char_index(ch as usize) != 0) {
+                Some(face) => face,
+                None => {
+                    warn!("missing glyph for {:?} in all faces, rendering .notdef", ch);
+                    faces[0]
+                }
+            };
+            Glyph::load_char(face, ch, face_size, padding, &config)
+        })?;
+        self.load_kerning(faces);
+        Ok(())
+    }
+
+    // Build multi-channel SDF of the glyphs into RGB texture (`channels` = 3),
+    // see `MsdfGenerator`. The shape is reconstructed by the median
+    // of the channels. Corners of the outline are the joints where its
+    // direction changes by more than `angle_threshold` (see MSDF_ANGLE_THRESHOLD).
+    pub fn build_msdf_from_face(&mut self, face: &ft::Face, face_size: usize, padding: usize,
+                                chars: &str, angle_threshold: f32) -> Result<(), FontError> {
+        assert_eq!(self.channels, 3, "MSDF needs RGB texture");
+        self.prepare_face(face, face_size, padding)?;
+
+        let config = self.config.clone();
+        let params = self.params.clone();
+        self.build_glyphs_from(chars, 0, None, |ch| {
+            let (glyph, outline) = Glyph::load_char(face, ch, face_size, padding, &config);
+            let tile = Glyph { x: 0, y: 0, .. glyph.clone() };
+            let mut data = vec![0u8; glyph.width * glyph.height * 3];
+            MsdfGenerator::new(&outline, angle_threshold)
+                .render(&tile, face_size, &params, &mut data, glyph.width);
+            (glyph, GlyphSource::Pixels(data))
+        })?;
+        self.load_kerning(&[face]);
+        Ok(())
+    }
+
+    // Build the glyphs like `build_from_face`, rendering signed distances in face
+    // pixels (negative inside) into returned single-channel texture of `width` * `height`
+    // (not padded to `pitch`), together with the glyph boxes in it. The font itself
+    // is not changed, the glyphs are laid out in an empty texture of its size with
+    // its config and params, the same as they would be by `build_from_face`.
+    // The texels not covered by any glyph are infinitely far outside.
+    // See `Glyph::render_outline_sdf_f32`.
+    pub fn build_f32_from_face(&self, face: &ft::Face, face_size: usize, padding: usize,
+                               chars: &str) -> Result<(HashMap<char, Glyph>, Vec<f32>), FontError> {
+        let mut layout = Font::with_size(self.width, self.height);
+        layout.config = self.config.clone();
+        layout.params = self.params.clone();
+        layout.prepare_face(face, face_size, padding)?;
+        let config = self.config.clone();
+        let mut tiles = Vec::with_capacity(chars.len());
+        layout.pack_glyphs(chars, 0, None, &mut |ch| {
+            let (glyph, outline) = Glyph::load_char(face, ch, face_size, padding, &config);
+            (glyph, GlyphSource::Outline(outline))
+        }, &mut tiles)?;
+        let mut distances = vec![f32::INFINITY; self.width * self.height];
+        for &(ref glyph, ref outline) in &tiles {
+            glyph.render_outline_sdf_f32(outline, &self.params, &mut distances, self.width);
+        }
+        Ok((layout.glyphs, distances))
+    }
+
+    // Like `build_from_face`, with `images` supplying RGBA images (data, width, height)
+    // for chars which have no outline in the face (bitmap glyphs like color emoji)
+    // or are missing entirely. The texture has to have 4 channels. Chars for which
+    // `images` returns None are rendered from the face as usual (missing ones as .notdef).
+    // The image is placed on the baseline, it advances by its width (in face pixels).
+    // Such glyphs have `Glyph::image` set.
+    pub fn build_from_face_with_images<F>(&mut self, face: &ft::Face, face_size: usize,
+                                          padding: usize, chars: &str, mut images: F)
+                                          -> Result<(), FontError>
+        where F: FnMut(char) -> Option<(Vec<u8>, usize, usize)>
+    {
+        assert_eq!(self.channels, 4, "images need RGBA texture");
+        self.build_face(face, face_size, padding, chars, 0, None, Some(&mut images), None)
+    }
+
+    // Take the metrics of the face and set it up for loading the glyphs
+    fn prepare_face(&mut self, face: &ft::Face, face_size: usize,
+                    padding: usize) -> Result<(), FontError> {
+        check_scalable(face)?;
+        self.face_size = face_size;
+        self.padding = padding;
+        self.underline = Decoration::underline_from_face(face, face_size);
+        self.strikeout = Decoration::strikeout_from_face(face, face_size);
+        self.vertical = LineMetrics::from_face(face, face_size);
+
+        face.set_pixel_sizes(face.em_size() as u32, 0).unwrap();
+        reset_transform(face);
+        if let Some(charmap) = self.config.charmap {
+            select_charmap(face, charmap)?;
+        }
+        set_variations(face, &self.config.variations);
+        Ok(())
+    }
+
+    fn build_face(&mut self, face: &ft::Face, face_size: usize, padding: usize,
+                  chars: &str, channel: usize, cancel: Option<&AtomicBool>,
+                  mut images: Option<&mut FnMut(char) -> Option<(Vec<u8>, usize, usize)>>,
+                  on_glyph: Option<&mut FnMut(char, &Glyph, &[u8])>)
+                  -> Result<(), FontError> {
+        self.prepare_face(face, face_size, padding)?;
+
+        for ch in Font::missing_chars(face, chars) {
+            warn!("missing glyph for {:?}, rendering .notdef", ch);
+        }
+
+        let config = self.config.clone();
+        let index_glyphs = self.index_glyphs.clone();
+        let mut first_chars = HashMap::new();
+        let mut load = |ch: char| {
+            if let Some(ref mut images) = images {
+                let has_outline = face.get_char_index(ch as usize) != 0 &&
+                    face.load_char(ch as usize, ft::face::NO_HINTING).is_ok() &&
+                    face.glyph().outline().is_some();
+                if !has_outline {
+                    if let Some((data, width, height)) = (*images)(ch) {
+                        assert_eq!(data.len(), width * height * 4, "image of {:?} is not RGBA", ch);
+                        let glyph = Glyph { width: width, height: height, advance_x: width as f32,
+                                            image: true, .. Default::default() };
+                        return (glyph, GlyphSource::Pixels(data));
+                    }
+                }
+            }
+            // Chars mapped to the same glyph (aliases, missing chars as .notdef)
+            // share the tile of the first one
+            let index = face.get_char_index(ch as usize);
+            if let Some(&first) = first_chars.get(&index) {
+                return (Glyph::default(), GlyphSource::Same(GlyphKey::Char(first)));
+            }
+            first_chars.insert(index, ch);
+            let (glyph, outline) = Glyph::load_char(face, ch, face_size, padding, &config);
+            // ... or the same glyph built by its index
+            match index_glyphs.get(&index) {
+                Some(built) if built.channel == channel && glyph.same_box(built) =>
+                    (Glyph::default(), GlyphSource::Same(GlyphKey::Index(index))),
+                _ => (glyph, GlyphSource::Outline(outline)),
+            }
+        };
+        let result = match on_glyph {
+            Some(on_glyph) => self.build_glyphs_streaming(chars, channel, cancel, &mut load, on_glyph),
+            None => self.build_glyphs_from(chars, channel, cancel, &mut load),
+        };
+        self.load_kerning(&[face]);
+        result
+    }
+
+    // Pack and render glyphs produced by `load` (box and outline in face pixels)
+    fn build_glyphs<F>(&mut self, chars: &str, channel: usize, cancel: Option<&AtomicBool>,
+                       mut load: F) -> Result<(), FontError>
+        where F: FnMut(char) -> (Glyph, Outline)
+    {
+        self.build_glyphs_from(chars, channel, cancel, |ch| {
+            let (glyph, outline) = load(ch);
+            (glyph, GlyphSource::Outline(outline))
+        })
+    }
+
+    // Build the glyphs one at a time, passing each one to `on_glyph`
+    // as soon as it's in the texture
+    fn build_glyphs_streaming<F>(&mut self, chars: &str, channel: usize, cancel: Option<&AtomicBool>,
+                                 load: &mut F, on_glyph: &mut FnMut(char, &Glyph, &[u8]))
+                                 -> Result<(), FontError>
+        where F: FnMut(char) -> (Glyph, GlyphSource)
+    {
+        let mut utf8 = [0u8; 4];
+        for ch in chars.chars() {
+            self.build_glyphs_from(ch.encode_utf8(&mut utf8), channel, cancel, &mut *load)?;
+            if let Some(glyph) = self.glyphs.get(&ch) {
+                let pixels = self.glyph_pixels(glyph);
+                on_glyph(ch, glyph, &pixels);
+            }
+        }
+        Ok(())
+    }
+
+    // Pack and render glyphs produced by `load` (box with outline or image)
+    fn build_glyphs_from<F>(&mut self, chars: &str, channel: usize, cancel: Option<&AtomicBool>,
+                            mut load: F) -> Result<(), FontError>
+        where F: FnMut(char) -> (Glyph, GlyphSource)
+    {
+        assert!(channel < self.channels, "channel out of range");
+        let spread = self.params.saturation_distance(self.face_size);
+        if self.padding == 0 && (self.config.gap as f32) < spread && !chars.is_empty() {
+            warn!("gap {} is less than the spread {} and the glyphs have no padding, \
+                   the neighbors bleed into each other", self.config.gap, spread);
+        }
+        self.glyphs.reserve(chars.len());
+        let outside = self.params.outside_value();
+        self.buffer.resize(self.pitch * self.height * self.channels, outside);
+
+        // Pack all glyphs first, the outlines are rendered afterwards.
+        // If the packing stops early, the glyphs packed so far are still rendered.
+        let mut tiles = Vec::with_capacity(chars.len());
+        let result = self.pack_glyphs(chars, channel, cancel, &mut load, &mut tiles);
+        self.render_tiles(&tiles, channel);
+        result
+    }
+
+    // Pack glyphs produced by `load`, collecting the tiles to be rendered from outline.
+    // Chars already in the font keep their box (the layout is shared between
+    // the channels), the others are packed anew.
+    fn pack_glyphs<F>(&mut self, chars: &str, channel: usize, cancel: Option<&AtomicBool>,
+                      load: &mut F, tiles: &mut Vec<(Glyph, Outline)>)
+                      -> Result<(), FontError>
+        where F: FnMut(char) -> (Glyph, GlyphSource)
+    {
+        let keys: Vec<GlyphKey> = chars.chars().map(GlyphKey::Char).collect();
+        self.pack_keys(&keys, channel, cancel, &mut |key: GlyphKey|
+                -> Result<(Glyph, GlyphSource), FontError> {
+            match key {
+                GlyphKey::Char(ch) => Ok(load(ch)),
+                GlyphKey::Index(_) => unreachable!(),
+            }
+        }, tiles)
+    }
+
+    // Pack glyphs by char or by glyph index (see `pack_glyphs`)
+    fn pack_keys<F>(&mut self, keys: &[GlyphKey], channel: usize, cancel: Option<&AtomicBool>,
+                    load: &mut F, tiles: &mut Vec<(Glyph, Outline)>)
+                    -> Result<(), FontError>
+        where F: FnMut(GlyphKey) -> Result<(Glyph, GlyphSource), FontError>
+    {
+        let (width, height, gap) = (self.width, self.height, self.config.gap);
+        for &key in keys {
+            if cancel.map_or(false, |c| c.load(Ordering::Relaxed)) {
+                return Err(FontError::Cancelled);
+            }
+            let (mut glyph, source) = load(key)?;
+            let shared = self.find_glyph(key).is_some();
+
+            if let GlyphSource::Same(other) = source {
+                if !shared {
+                    if let Some(existing) = self.find_glyph(other).cloned() {
+                        self.insert_glyph(key, existing);
+                    }
+                }
+                continue;
+            }
+
+            if shared {
+                let stored = self.glyph_by_key(key).clone();
+                // The pixels are copied into the stored box, they have to fit exactly
+                if let (GlyphKey::Char(ch), &GlyphSource::Pixels(_)) = (key, &source) {
+                    if (glyph.width, glyph.height) != (stored.width, stored.height) {
+                        return Err(FontError::GlyphBoxChanged(ch));
+                    }
+                }
+                glyph = stored;
+            } else if glyph.width == 0 || glyph.height == 0 {
+                // Nothing to render (e.g. space), the glyph takes no space in the texture
+                glyph.channel = channel;
+                self.insert_glyph(key, glyph);
+                continue;
+            } else if let Some(rect) = self.packers.entry(channel)
+                    .or_insert_with(|| new_packer(width, height, gap))
+                    .pack(glyph.width as i32, glyph.height as i32, false) {
+                glyph.x = rect.x as usize;
+                glyph.y = rect.y as usize;
+                glyph.channel = channel;
+            } else {
+                error!("no space for glyph {:?} ({}x{}) in {}x{} texture",
+                       key, glyph.width, glyph.height, self.width, self.height);
+                return Err(key.texture_full());
+            }
+
+            if let GlyphKey::Char(ch) = key {
+                glyph.check_bounds(ch, self.width, self.height)?;
+            }
+            self.mark_dirty(glyph.rect());
+            let outline = match source {
+                GlyphSource::Outline(outline) => outline,
+                GlyphSource::Pixels(data) => {
+                    // Copy the pixels into all channels
+                    let row_len = glyph.width * self.channels;
+                    for y in 0 .. glyph.height {
+                        let i = ((glyph.y + y) * self.pitch + glyph.x) * self.channels;
+                        let src = &data[y * row_len .. (y + 1) * row_len];
+                        self.buffer[i .. i + row_len].copy_from_slice(src);
+                    }
+                    if !shared {
+                        self.insert_glyph(key, glyph);
+                    }
+                    continue;
+                }
+                GlyphSource::Same(_) => unreachable!(),
+            };
+            if self.config.retain_outlines && !shared {
+                let mut em_outline = outline.clone();
+                let scale = 1.0 / self.face_size as f32;
+                em_outline.transform(&Transform::scale(scale, scale));
+                match key {
+                    GlyphKey::Char(ch) => self.outlines.insert(ch, em_outline),
+                    GlyphKey::Index(index) => self.index_outlines.insert(index, em_outline),
+                };
+            }
 
             //println!("{} {:#?}", ch, glyph);
-            self.glyphs.insert(ch, glyph);
+            if !shared {
+                self.insert_glyph(key, glyph.clone());
+            }
+            tiles.push((glyph, outline));
+        }
+        Ok(())
+    }
+
+    // Render SDF of packed tiles into the channel of the texture
+    #[cfg(not(feature = "rayon"))]
+    fn render_tiles(&mut self, tiles: &[(Glyph, Outline)], channel: usize) {
+        self.render_tiles_serial(tiles, channel);
+    }
+
+    // Render SDF of packed tiles into the channel of the texture.
+    // The tiles don't overlap, each one is rendered in parallel into its own
+    // buffer, which is then copied into the texture.
+    #[cfg(feature = "rayon")]
+    fn render_tiles(&mut self, tiles: &[(Glyph, Outline)], channel: usize) {
+        self.render_tiles_parallel(tiles, channel);
+    }
+
+    fn render_tiles_serial(&mut self, tiles: &[(Glyph, Outline)], channel: usize) {
+        for &(ref glyph, ref outline) in tiles {
+            if self.channels == 1 {
+                glyph.render_outline_sdf(outline, self.face_size, &self.params,
+                                         &mut self.buffer, self.pitch);
+            } else {
+                // Render separately, then copy into the channel
+                let pixels = glyph.render_standalone(outline, self.face_size, &self.params);
+                self.copy_tile(glyph, &pixels, channel);
+            }
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    fn render_tiles_parallel(&mut self, tiles: &[(Glyph, Outline)], channel: usize) {
+        use rayon::prelude::*;
+        let (face_size, params) = (self.face_size, &self.params);
+        let rendered: Vec<Vec<u8>> = tiles.par_iter()
+            .map(|&(ref glyph, ref outline)| glyph.render_standalone(outline, face_size, params))
+            .collect();
+        for (&(ref glyph, _), pixels) in tiles.iter().zip(rendered.iter()) {
+            self.copy_tile(glyph, pixels, channel);
+        }
+    }
+
+    // Pixels of the glyph's rectangle in its channel (pitch = width)
+    fn glyph_pixels(&self, glyph: &Glyph) -> Vec<u8> {
+        let mut data = Vec::with_capacity(glyph.width * glyph.height);
+        for yr in 0 .. glyph.height {
+            for xr in 0 .. glyph.width {
+                let i = (glyph.y + yr) * self.pitch + glyph.x + xr;
+                data.push(self.buffer[i * self.channels + glyph.channel]);
+            }
+        }
+        data
+    }
+
+    // Copy single-channel pixels of a tile (pitch = width) into the glyph's rectangle
+    fn copy_tile(&mut self, glyph: &Glyph, pixels: &[u8], channel: usize) {
+        for y in 0 .. glyph.height {
+            for x in 0 .. glyph.width {
+                let i = ((glyph.y + y) * self.pitch + glyph.x + x) * self.channels + channel;
+                self.buffer[i] = pixels[y * glyph.width + x];
+            }
+        }
+    }
+
+    // Measure `text` at `target_px` size from the face metrics (advances and kerning),
+    // without rendering anything. The width of each line is the sum of advances.
+    pub fn measure_only(face: &ft::Face, target_px: f32, text: &str) -> LayoutMetrics {
+        face.set_pixel_sizes(face.em_size() as u32, 0).unwrap();
+        reset_transform(face);
+        let em_size = face.em_size() as f32;
+        // font units (26.6 at em size) to target pixels
+        let scale = target_px / (em_size * 64.);
+        let mut metrics = LayoutMetrics::default();
+        for line in text.split('\n') {
+            let mut width = 0.0;
+            let mut prev_index = 0;
+            for ch in line.chars() {
+                let index = face.get_char_index(ch as usize);
+                if prev_index != 0 && index != 0 {
+                    if let Ok(kerning) = face.get_kerning(prev_index, index, ft::face::KerningMode::KerningUnscaled) {
+                        width += kerning.x as f32 * 64. * scale;
+                    }
+                }
+                if face.load_char(ch as usize, ft::face::NO_HINTING).is_ok() {
+                    width += face.glyph().advance().x as f32 * scale;
+                }
+                prev_index = index;
+            }
+            metrics.width = metrics.width.max(width);
+            metrics.line_widths.push(width);
+        }
+        let line_height = face.raw().height as f32 * 64. * scale;
+        metrics.height = line_height * metrics.line_widths.len() as f32;
+        metrics
+    }
+
+    // Ratio between the area occupied by glyphs and the whole texture area
+    pub fn coverage_ratio(&self) -> f32 {
+        if self.width * self.height == 0 {
+            return 0.0;
+        }
+        let used: usize = self.glyphs.values().map(|g| g.width * g.height).sum();
+        used as f32 / (self.width * self.height) as f32
+    }
+
+    // Debug image (RGB) of the texture packing: glyph rectangles are filled
+    // with a color (chosen by the char), free space is left black
+    pub fn packing_image(&self) -> Vec<u8> {
+        let palette = [[255, 0, 0], [0, 255, 0], [0, 0, 255],
+                       [255, 255, 0], [0, 255, 255], [255, 0, 255]];
+        let mut image = vec![0u8; self.width * self.height * 3];
+        for (&ch, glyph) in &self.glyphs {
+            let color = palette[ch as usize % palette.len()];
+            for y in glyph.y .. glyph.y + glyph.height {
+                for x in glyph.x .. glyph.x + glyph.width {
+                    let i = (y * self.width + x) * 3;
+                    image[i .. i + 3].copy_from_slice(&color);
+                }
+            }
+        }
+        image
+    }
+
+    /// Render `text` as a single line into a standalone RGBA image.
+    ///
+    /// The glyphs are laid out at `target_px` size and their coverage
+    /// is reconstructed from the SDF texture. Returns image data
+    /// (white color with straight alpha), width and height.
+    pub fn render_string_to_image(&self, text: &str, target_px: f32) -> (Vec<u8>, usize, usize) {
+        self.render_string_to_image_alpha(text, target_px, AlphaMode::Straight)
+    }
+
+    /// Like `render_string_to_image`, with the alpha representation
+    /// selected by `mode` (see `AlphaMode`).
+    pub fn render_string_to_image_alpha(&self, text: &str, target_px: f32,
+                                        mode: AlphaMode) -> (Vec<u8>, usize, usize) {
+        let layout = Layout::new(self, text, target_px);
+        let width = layout.width().ceil() as usize;
+        let height = layout.height().ceil() as usize;
+        let background = match mode {
+            AlphaMode::Straight => [255, 255, 255, 0],
+            AlphaMode::Premultiplied => [0, 0, 0, 0],
+        };
+        let mut image = Vec::with_capacity(width * height * 4);
+        for _ in 0 .. width * height {
+            image.extend_from_slice(&background);
+        }
+        for pos in &layout.glyphs {
+            let glyph = &self.glyphs[&pos.ch];
+            let (left, top, right, bottom) = layout.glyph_quad(pos, glyph);
+            // Glyph quad in image coordinates
+            let (left, right) = (left - layout.xmin, right - layout.xmin);
+            let (top, bottom) = (top - layout.ymin, bottom - layout.ymin);
+            let x0 = left.floor().max(0.0) as usize;
+            let y0 = top.floor().max(0.0) as usize;
+            let x1 = (right.ceil().max(0.0) as usize).min(width);
+            let y1 = (bottom.ceil().max(0.0) as usize).min(height);
+            // Target pixels per texel of the glyph
+            let scale = layout.scale / glyph.scale;
+            for py in y0 .. y1 {
+                for px in x0 .. x1 {
+                    // Sample the glyph at the pixel center (in texels)
+                    let u = (px as f32 + 0.5 - left) / scale;
+                    let v = (py as f32 + 0.5 - top) / scale;
+                    if u < 0.0 || v < 0.0 || u > glyph.width as f32 || v > glyph.height as f32 {
+                        continue;
+                    }
+                    let value = self.sample_glyph(glyph, u, v);
+                    let distance = self.params.decode(value, self.face_size) * scale;
+                    let alpha = sdf_coverage(distance);
+                    // Composite over the previous content. With straight alpha,
+                    // the color stays white, only alpha accumulates.
+                    let i = (py * width + px) * 4;
+                    let channels = match mode {
+                        AlphaMode::Straight => 3 .. 4,
+                        AlphaMode::Premultiplied => 0 .. 4,
+                    };
+                    for c in channels {
+                        let dst = image[i + c] as f32 / 255.;
+                        let out = alpha + dst * (1.0 - alpha);
+                        image[i + c] = (out * 255. + 0.5) as u8;
+                    }
+                }
+            }
+        }
+        (image, width, height)
+    }
+
+    // Signed distance (in face pixels, negative inside) reconstructed from
+    // the texture at (u, v) of the char's glyph, the same way the shaders do
+    // (see `sample_glyph` for the coordinates and the clamping).
+    // Infinite (outside) for chars not in the font, or empty glyphs.
+    pub fn sample_distance(&self, ch: char, u: f32, v: f32) -> f32 {
+        match self.glyphs.get(&ch) {
+            Some(glyph) if glyph.width != 0 && glyph.height != 0 =>
+                self.params.decode(self.sample_glyph(glyph, u, v), self.face_size),
+            _ => f32::INFINITY,
+        }
+    }
+
+    // Bilinear sample of glyph's SDF texture at (u, v), in texels relative
+    // to the glyph's top left corner. Texel centers are at half-integer
+    // coordinates, samples are clamped to the glyph edges (like GL's
+    // CLAMP_TO_EDGE wrap function).
+    fn sample_glyph(&self, glyph: &Glyph, u: f32, v: f32) -> f32 {
+        if glyph.width == 0 || glyph.height == 0 {
+            return 0.0;
+        }
+        let fx = (u - 0.5).max(0.0).min((glyph.width - 1) as f32);
+        let fy = (v - 0.5).max(0.0).min((glyph.height - 1) as f32);
+        let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+        let x1 = (x0 + 1).min(glyph.width - 1);
+        let y1 = (y0 + 1).min(glyph.height - 1);
+        let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+        let texel = |x: usize, y: usize| {
+            let i = (glyph.y + y) * self.pitch + glyph.x + x;
+            self.buffer[i * self.channels + glyph.channel] as f32
+        };
+        let upper = (1.0 - tx) * texel(x0, y0) + tx * texel(x1, y0);
+        let lower = (1.0 - tx) * texel(x0, y1) + tx * texel(x1, y1);
+        (1.0 - ty) * upper + ty * lower
+    }
+}
+
+// Interoperability with `image` crate. Both use the same row order
+// (top row first), so the data is copied as it is.
+#[cfg(feature = "image")]
+impl Font {
+    // Channel 0 of the font texture as an image (without the row padding)
+    pub fn to_gray_image(&self) -> ::image::GrayImage {
+        let data = self.buffer.chunks(self.pitch * self.channels)
+            .flat_map(|row| row[.. self.width * self.channels].chunks(self.channels).map(|px| px[0]))
+            .collect();
+        ::image::GrayImage::from_raw(self.width as u32, self.height as u32, data).unwrap()
+    }
+
+    // Image of the glyph's SDF (its rectangle in its channel), None if the font doesn't have it
+    pub fn glyph_gray_image(&self, ch: char) -> Option<::image::GrayImage> {
+        let glyph = match self.glyphs.get(&ch) {
+            Some(glyph) => glyph,
+            None => return None,
+        };
+        let data = self.glyph_pixels(glyph);
+        ::image::GrayImage::from_raw(glyph.width as u32, glyph.height as u32, data)
+    }
+}
+
+#[cfg(feature = "png")]
+impl Font {
+    // Save the font texture as PNG file (without the row padding).
+    // The color type follows `channels`: gray, gray + alpha, RGB (MSDF) or RGBA.
+    pub fn save_png<P: AsRef<path::Path>>(&self, path: P) -> io::Result<()> {
+        use png::HasParameters;
+        let color_type = match self.channels {
+            1 => ::png::ColorType::Grayscale,
+            2 => ::png::ColorType::GrayscaleAlpha,
+            3 => ::png::ColorType::RGB,
+            4 => ::png::ColorType::RGBA,
+            n => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           format!("can't save {} channels as PNG", n))),
+        };
+        let row_len = self.width * self.channels;
+        let mut data = Vec::with_capacity(row_len * self.height);
+        for row in self.buffer.chunks(self.pitch * self.channels).take(self.height) {
+            data.extend_from_slice(&row[.. row_len]);
+        }
+        let file = fs::File::create(path)?;
+        let mut encoder = ::png::Encoder::new(io::BufWriter::new(file),
+                                              self.width as u32, self.height as u32);
+        encoder.set(color_type).set(::png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        Ok(())
+    }
+}
+
+// JSON atlas descriptor (see `Font::export_metrics`)
+#[cfg(feature = "json")]
+#[derive(Serialize, Deserialize)]
+struct AtlasMetrics {
+    width: usize,
+    height: usize,
+    channels: usize,
+    pitch: usize,
+    face_size: usize,
+    padding: usize,
+    params: EncodingMetrics,
+    vertical: VerticalMetrics,
+    underline: DecorationMetrics,
+    strikeout: DecorationMetrics,
+    glyphs: Vec<CharMetrics>,
+    index_glyphs: Vec<IndexMetrics>,
+    kerning_pairs: Vec<KerningMetrics>,
+}
+
+// The part of `SdfParams` needed to decode the texture
+#[cfg(feature = "json")]
+#[derive(Serialize, Deserialize)]
+struct EncodingMetrics {
+    spread: Option<f32>,
+    em_spread: Option<f32>,
+    midpoint: u8,
+    srgb: bool,
+    invert: bool,
+    stroke_width: Option<f32>,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize, Deserialize)]
+struct VerticalMetrics {
+    ascent: f32,
+    descent: f32,
+    line_height: f32,
+    baseline: f32,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize, Deserialize)]
+struct DecorationMetrics {
+    position: f32,
+    thickness: f32,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize, Deserialize)]
+struct CharMetrics {
+    ch: char,
+    glyph: GlyphMetrics,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize, Deserialize)]
+struct IndexMetrics {
+    index: u32,
+    glyph: GlyphMetrics,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize, Deserialize)]
+struct KerningMetrics {
+    left: char,
+    right: char,
+    x: f32,
+    y: f32,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize, Deserialize)]
+struct GlyphMetrics {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    xmin: isize,
+    ymin: isize,
+    advance_x: f32,
+    advance_y: f32,
+    bearing_x: f32,
+    padding: usize,
+    channel: usize,
+    image: bool,
+    scale: f32,
+}
+
+#[cfg(feature = "json")]
+impl GlyphMetrics {
+    fn from_glyph(glyph: &Glyph) -> Self {
+        GlyphMetrics {
+            x: glyph.x,
+            y: glyph.y,
+            width: glyph.width,
+            height: glyph.height,
+            xmin: glyph.xmin,
+            ymin: glyph.ymin,
+            advance_x: glyph.advance_x,
+            advance_y: glyph.advance_y,
+            bearing_x: glyph.bearing_x,
+            padding: glyph.padding,
+            channel: glyph.channel,
+            image: glyph.image,
+            scale: glyph.scale,
+        }
+    }
+
+    fn to_glyph(&self) -> Glyph {
+        Glyph {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+            xmin: self.xmin,
+            ymin: self.ymin,
+            advance_x: self.advance_x,
+            advance_y: self.advance_y,
+            bearing_x: self.bearing_x,
+            padding: self.padding,
+            channel: self.channel,
+            image: self.image,
+            scale: self.scale,
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl Font {
+    // JSON document with the texture size, the SDF encoding, the line metrics,
+    // kerning and metrics of all glyphs (sorted by char / index), so a baked
+    // texture can be used without the face (see `import_metrics`)
+    pub fn export_metrics(&self) -> String {
+        let mut glyphs: Vec<CharMetrics> = self.glyphs.iter().map(|(&ch, glyph)| CharMetrics {
+            ch: ch,
+            glyph: GlyphMetrics::from_glyph(glyph),
+        }).collect();
+        glyphs.sort_by_key(|m| m.ch);
+        let mut index_glyphs: Vec<IndexMetrics> = self.index_glyphs.iter()
+            .map(|(&index, glyph)| IndexMetrics {
+                index: index,
+                glyph: GlyphMetrics::from_glyph(glyph),
+            }).collect();
+        index_glyphs.sort_by_key(|m| m.index);
+        let mut kerning_pairs: Vec<KerningMetrics> = self.kerning_pairs.iter()
+            .map(|(&(left, right), kerning)| KerningMetrics {
+                left: left,
+                right: right,
+                x: kerning.x,
+                y: kerning.y,
+            }).collect();
+        kerning_pairs.sort_by_key(|m| (m.left, m.right));
+        let decoration = |d: &Decoration| DecorationMetrics { position: d.position,
+                                                              thickness: d.thickness };
+        let metrics = AtlasMetrics {
+            width: self.width,
+            height: self.height,
+            channels: self.channels,
+            pitch: self.pitch,
+            face_size: self.face_size,
+            padding: self.padding,
+            params: EncodingMetrics {
+                spread: self.params.spread,
+                em_spread: self.params.em_spread,
+                midpoint: self.params.midpoint,
+                srgb: self.params.srgb,
+                invert: self.params.invert,
+                stroke_width: self.params.stroke_width,
+            },
+            vertical: VerticalMetrics {
+                ascent: self.vertical.ascent,
+                descent: self.vertical.descent,
+                line_height: self.vertical.line_height,
+                baseline: self.vertical.baseline,
+            },
+            underline: decoration(&self.underline),
+            strikeout: decoration(&self.strikeout),
+            glyphs: glyphs,
+            index_glyphs: index_glyphs,
+            kerning_pairs: kerning_pairs,
+        };
+        ::serde_json::to_string_pretty(&metrics).unwrap()
+    }
+
+    // Font with glyphs from JSON made by `export_metrics`. The buffer is empty,
+    // the texture is loaded separately (see `load`). Glyphs out of the texture
+    // or overlapping each other fail with `FontError::CorruptAtlas`.
+    // Only the encoding part of `params` is restored, the rest is default.
+    pub fn import_metrics(json: &str) -> Result<Font, FontError> {
+        let metrics: AtlasMetrics = ::serde_json::from_str(json)
+            .map_err(|err| FontError::CorruptAtlas(err.to_string()))?;
+        if metrics.pitch < metrics.width || metrics.channels == 0 {
+            return Err(FontError::CorruptAtlas(format!(
+                "pitch {} is less than width {} or no channels", metrics.pitch, metrics.width)));
+        }
+        if metrics.params.midpoint == 0 || metrics.params.midpoint == 255 {
+            return Err(FontError::CorruptAtlas(format!(
+                "midpoint {} out of range 1..254", metrics.params.midpoint)));
+        }
+        let mut font = Font::with_size(metrics.width, metrics.height);
+        font.channels = metrics.channels;
+        font.pitch = metrics.pitch;
+        font.face_size = metrics.face_size;
+        font.padding = metrics.padding;
+        font.params.spread = metrics.params.spread;
+        font.params.em_spread = metrics.params.em_spread;
+        font.params.midpoint = metrics.params.midpoint;
+        font.params.srgb = metrics.params.srgb;
+        font.params.invert = metrics.params.invert;
+        font.params.stroke_width = metrics.params.stroke_width;
+        font.vertical = LineMetrics {
+            ascent: metrics.vertical.ascent,
+            descent: metrics.vertical.descent,
+            line_height: metrics.vertical.line_height,
+            baseline: metrics.vertical.baseline,
+        };
+        let decoration = |d: DecorationMetrics| Decoration { position: d.position,
+                                                             thickness: d.thickness };
+        font.underline = decoration(metrics.underline);
+        font.strikeout = decoration(metrics.strikeout);
+        for m in metrics.glyphs {
+            font.glyphs.insert(m.ch, m.glyph.to_glyph());
+        }
+        for m in metrics.index_glyphs {
+            font.index_glyphs.insert(m.index, m.glyph.to_glyph());
+        }
+        for m in metrics.kerning_pairs {
+            font.kerning_pairs.insert((m.left, m.right), Vec2::new(m.x, m.y));
+        }
+        font.validate_layout()?;
+        Ok(font)
+    }
+}
+
+#[cfg(all(feature = "png", feature = "json"))]
+impl Font {
+    // Load an atlas saved by `save_png` and `export_metrics`. The PNG has to match
+    // the size and channels from the JSON, anything else fails with
+    // `FontError::CorruptAtlas` (as do glyphs out of the texture or overlapping).
+    pub fn load<P: AsRef<path::Path>, Q: AsRef<path::Path>>(png_path: P, json_path: Q)
+            -> Result<Font, FontError> {
+        use std::io::Read;
+        let mut json = String::new();
+        fs::File::open(json_path)
+            .and_then(|mut file| file.read_to_string(&mut json))
+            .map_err(|err| FontError::CorruptAtlas(err.to_string()))?;
+        let mut font = Font::import_metrics(&json)?;
+        let file = fs::File::open(png_path)
+            .map_err(|err| FontError::CorruptAtlas(err.to_string()))?;
+        let (info, mut reader) = ::png::Decoder::new(file).read_info()
+            .map_err(|err| FontError::CorruptAtlas(err.to_string()))?;
+        let channels = match info.color_type {
+            ::png::ColorType::Grayscale => 1,
+            ::png::ColorType::GrayscaleAlpha => 2,
+            ::png::ColorType::RGB => 3,
+            ::png::ColorType::RGBA => 4,
+            _ => 0,
+        };
+        if (info.width as usize, info.height as usize) != (font.width, font.height) ||
+                channels != font.channels || info.bit_depth != ::png::BitDepth::Eight {
+            return Err(FontError::CorruptAtlas(format!(
+                "PNG is {}x{} ({:?}, {:?}), expected {}x{} with {} 8-bit channels",
+                info.width, info.height, info.color_type, info.bit_depth,
+                font.width, font.height, font.channels)));
+        }
+        let mut data = vec![0u8; info.buffer_size()];
+        reader.next_frame(&mut data)
+            .map_err(|err| FontError::CorruptAtlas(err.to_string()))?;
+        // Pad the rows back to the pitch
+        let row_len = font.width * font.channels;
+        let pitch = font.pitch * font.channels;
+        font.buffer = vec![0u8; pitch * font.height];
+        for (row, src) in font.buffer.chunks_mut(pitch).zip(data.chunks(row_len)) {
+            row[.. row_len].copy_from_slice(src);
+        }
+        font.validate()?;
+        Ok(font)
+    }
+}
+
+
+// Tests
+// -----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Font with single glyph 'x' consisting of a filled 4x4 tile
+    fn filled_font() -> Font {
+        let mut font = Font::new(8);
+        font.buffer.resize(8 * 8, 0u8);
+        font.face_size = 4;
+        for y in 0 .. 4 {
+            for x in 0 .. 4 {
+                font.buffer[y * 8 + x] = 255;
+            }
+        }
+        font.glyphs.insert('x', Glyph { width: 4, height: 4, advance_x: 4.0, .. Default::default() });
+        font
+    }
+
+    #[test]
+    fn test_sample_distance() {
+        let mut font = filled_font();
+        assert!(font.sample_distance('x', 2.0, 2.0) < 0.0);
+        assert_eq!(font.sample_distance('x', 2.0, 2.0), font.params.decode(255.0, 4));
+        // Clamped to the glyph's edges
+        font.buffer[0] = 127;
+        assert_eq!(font.sample_distance('x', 0.5, 0.5), 0.0);
+        assert_eq!(font.sample_distance('x', -3.0, -1.0), 0.0);
+        assert_eq!(font.sample_distance('x', 1.0, 0.5), font.params.decode(191.0, 4));
+        assert_eq!(font.sample_distance('x', 9.0, 2.0), font.sample_distance('x', 3.5, 2.0));
+        assert_eq!(font.sample_distance('y', 2.0, 2.0), f32::INFINITY);
+    }
+
+    #[test]
+    fn test_render_string_to_image() {
+        let font = filled_font();
+        let (image, width, height) = font.render_string_to_image("xx", 8.0);
+        assert_eq!((width, height), (16, 8));
+        // Both glyphs are opaque
+        assert_eq!(image[(4 * width + 4) * 4 + 3], 255);
+        assert_eq!(image[(4 * width + 12) * 4 + 3], 255);
+        // Unknown chars are skipped
+        let (_, width, _) = font.render_string_to_image("x?", 8.0);
+        assert_eq!(width, 8);
+    }
+
+    #[test]
+    fn test_premultiplied_alpha() {
+        let mut font = filled_font();
+        // The whole glyph lies on the outline, half covered
+        for y in 0 .. 4 {
+            for x in 0 .. 4 {
+                font.buffer[y * 8 + x] = 127;
+            }
+        }
+        let (image, width, _) = font.render_string_to_image("x", 4.0);
+        let i = (2 * width + 2) * 4;
+        assert_eq!(&image[i .. i + 4], &[255, 255, 255, 128]);
+        let (image, _, _) = font.render_string_to_image_alpha("x", 4.0, AlphaMode::Premultiplied);
+        assert_eq!(&image[i .. i + 4], &[128, 128, 128, 128]);
+    }
+
+    #[test]
+    fn test_chars_from_corpus() {
+        assert_eq!(chars_from_corpus(&["Hello,\nwörld", "Привет", "こんにちは", "Hello"]),
+                   ",HdelorwöПвеиртこちにはん");
+        // Decomposed 'é' (e + combining acute accent)
+        assert_eq!(chars_from_corpus(&["Ce\u{301}"]), "Ce\u{301}");
+        let chars = chars_from_corpus(&[ASCII_PRINTABLE, "Ж"]);
+        assert_eq!(ASCII_PRINTABLE.len(), 95);
+        assert_eq!(chars.chars().count(), 96);
+        assert!(chars.starts_with(" !\""));
+        assert!(chars.ends_with("~Ж"));
+    }
+
+    #[test]
+    fn test_coverage_ratio() {
+        let mut font = filled_font();
+        assert_eq!(font.coverage_ratio(), 0.25);
+        font.glyphs.insert('y', Glyph { x: 4, width: 4, height: 2, .. Default::default() });
+        assert_eq!(font.coverage_ratio(), 0.375);
+        let image = font.packing_image();
+        // Packed rectangles are colored, free space is black
+        assert!(image[(1 * 8 + 5) * 3 .. (1 * 8 + 6) * 3].iter().any(|&c| c != 0));
+        assert!(image[(3 * 8 + 5) * 3 .. (3 * 8 + 6) * 3].iter().all(|&c| c == 0));
+    }
+
+    #[test]
+    fn test_box_rounding() {
+        // The right edge lands near a half-texel
+        let glyph = Glyph::from_bbox(0.2, 0.0, 3.45, 2.0, 0, BoxRounding::Expand);
+        assert_eq!((glyph.xmin, glyph.width), (0, 4));
+        assert!(glyph.xmin as f32 + glyph.width as f32 >= 3.45);
+        // Rounding to nearest clips the ink
+        let glyph = Glyph::from_bbox(0.2, 0.0, 3.45, 2.0, 0, BoxRounding::Nearest);
+        assert_eq!((glyph.xmin, glyph.width), (0, 3));
+        // Padding is added on top
+        let glyph = Glyph::from_bbox(0.2, 0.0, 3.45, 2.0, 3, BoxRounding::Expand);
+        assert_eq!((glyph.xmin, glyph.ymin, glyph.width, glyph.height), (-3, -3, 10, 8));
+    }
+
+    // Square outline (0, 0) - (2, 2)
+    fn square() -> Outline {
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(0.0, 0.0));
+        builder.line_to(Vec2::new(0.0, 2.0));
+        builder.line_to(Vec2::new(2.0, 2.0));
+        builder.line_to(Vec2::new(2.0, 0.0));
+        builder.finish()
+    }
+
+    // Bilinear sample of the whole texture (like GPU does, across the glyph boundaries)
+    fn sample_texture(font: &Font, u: f32, v: f32) -> f32 {
+        let (fx, fy) = (u - 0.5, v - 0.5);
+        let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+        let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+        let texel = |x: usize, y: usize| font.buffer[y * font.width + x] as f32;
+        (1.0 - ty) * ((1.0 - tx) * texel(x0, y0) + tx * texel(x0 + 1, y0)) +
+              ty * ((1.0 - tx) * texel(x0, y0 + 1) + tx * texel(x0 + 1, y0 + 1))
+    }
+
+    #[test]
+    fn test_outside_border() {
+        // Two glyphs packed side by side without a gutter
+        let mut font = Font::new(8);
+        font.buffer.resize(8 * 8, 0xAA);
+        let outline = square();
+        for &x in [0, 4].iter() {
+            let mut glyph = Glyph::from_bbox(0.0, 0.0, 2.0, 2.0, 1, BoxRounding::Expand);
+            glyph.x = x;
+            glyph.render_outline_sdf(&outline, 128, &SdfParams::default(), &mut font.buffer, 8);
+            assert_eq!((glyph.width, glyph.height), (4, 4));
+        }
+        // The border is saturated outside, the center is inside
+        for i in 0 .. 4 {
+            assert_eq!(font.buffer[i * 8 + 3], 0);
+            assert_eq!(font.buffer[i * 8 + 4], 0);
+            assert_eq!(font.buffer[3 * 8 + i], 0);
+        }
+        assert!(font.buffer[1 * 8 + 1] > 127);
+        // Sampling at the corner between the glyphs pulls in no interior value
+        assert_eq!(sample_texture(&font, 4.0, 1.0), 0.0);
+        assert_eq!(sample_texture(&font, 4.0, 2.0), 0.0);
+    }
+
+    #[test]
+    fn test_embolden() {
+        let mut config = FontConfig::default();
+        let regular = Glyph::from_outline(&square(), 1, &config);
+        config.embolden = 2.0;
+        let mut outline = square();
+        outline.embolden(config.embolden);
+        let bold = Glyph::from_outline(&outline, 1, &config);
+        assert_eq!(regular.ink_rect(), Rect::new(1, 1, 2, 2));
+        assert_eq!(bold.ink_rect(), Rect::new(1, 1, 4, 4));
+        assert_eq!((bold.xmin, bold.ymin), (-2, -2));
+        // Pixel center (0.5, 1.5) is now 1.5 px inside the dilated edge
+        let mut buffer = vec![0u8; bold.width * bold.height];
+        bold.render_outline_sdf(&outline, 128, &SdfParams::default(), &mut buffer, bold.width);
+        assert_eq!(buffer[2 * bold.width + 2], 149);
+    }
+
+    #[test]
+    fn test_transform() {
+        let mut config = FontConfig::default();
+        config.transform = Some(Transform::identity());
+        let mut outline = square();
+        outline.transform(&Transform::identity());
+        let glyph = Glyph::from_outline(&outline, 1, &config);
+        assert_eq!((glyph.xmin, glyph.ymin, glyph.width, glyph.height), (-1, -1, 4, 4));
+        // Scaled 2x horizontally and 1.5x vertically
+        let scale = Transform::scale(2.0, 1.5);
+        config.transform = Some(scale);
+        let mut outline = square();
+        outline.transform(&scale);
+        let glyph = Glyph::from_outline(&outline, 1, &config);
+        assert_eq!((glyph.xmin, glyph.ymin, glyph.width, glyph.height), (-1, -1, 6, 5));
+        assert_eq!(glyph.ink_rect(), Rect::new(1, 1, 4, 3));
+    }
+
+    #[test]
+    fn test_check_bounds() {
+        let mut font = Font::new(8);
+        font.face_size = 128;
+        let config = FontConfig::default();
+        font.build_glyphs("a", 0, None, |_| (Glyph::from_outline(&square(), 1, &config), square())).unwrap();
+        // Box of the shared glyph doesn't match the texture
+        font.glyphs.get_mut(&'a').unwrap().x = 6;
+        let result = font.build_glyphs("a", 0, None, |_| (Glyph::default(), square()));
+        assert_eq!(result, Err(FontError::GlyphOutOfBounds {
+            ch: 'a', rect: Rect::new(6, 0, 4, 4), width: 8, height: 8 }));
+    }
+
+    #[test]
+    fn test_build_superset() {
+        let mut font = Font::new(16);
+        font.face_size = 128;
+        let config = FontConfig::default();
+        let load_square = |_: char| (Glyph::from_outline(&square(), 1, &config), square());
+        font.build_glyphs("ab", 0, None, &load_square).unwrap();
+        let (a, b) = (font.glyphs[&'a'].clone(), font.glyphs[&'b'].clone());
+        let pixels_a = font.glyph_pixels(&a);
+        // The existing chars keep their boxes, the new one is packed beside them
+        font.build_glyphs("abc", 0, None, &load_square).unwrap();
+        assert_eq!((font.glyphs[&'a'].x, font.glyphs[&'a'].y), (a.x, a.y));
+        assert_eq!((font.glyphs[&'b'].x, font.glyphs[&'b'].y), (b.x, b.y));
+        let c = font.glyphs[&'c'].clone();
+        assert!(!c.rect().intersects(&a.rect()) && !c.rect().intersects(&b.rect()));
+        assert_eq!(font.glyph_pixels(&a), pixels_a);
+        font.validate().unwrap();
+    }
+
+    // Square ring (like 'O'): outer contour and a hole of opposite orientation
+    fn ring(reversed: bool) -> Outline {
+        let mut builder = OutlineBuilder::new();
+        let contours = [[(0.0, 0.0), (0.0, 6.0), (6.0, 6.0), (6.0, 0.0)],
+                        [(2.0, 2.0), (4.0, 2.0), (4.0, 4.0), (2.0, 4.0)]];
+        for contour in contours.iter() {
+            let mut points = contour.to_vec();
+            if reversed {
+                points.reverse();
+            }
+            builder.move_to(Vec2::new(points[0].0, points[0].1));
+            for &(x, y) in points[1..].iter() {
+                builder.line_to(Vec2::new(x, y));
+            }
+        }
+        let mut outline = builder.finish();
+        outline.reverse_fill = reversed;
+        outline
+    }
+
+    #[test]
+    fn test_contour_orientation() {
+        let glyph = Glyph::from_outline(&ring(false), 1, &FontConfig::default());
+        let params = SdfParams::default();
+        let normal = glyph.render_standalone(&ring(false), 128, &params);
+        let reversed = glyph.render_standalone(&ring(true), 128, &params);
+        assert_eq!(normal, reversed);
+        // Texel (x, y) covers face pixel (x - 1, 6 - y)
+        let texel = |x: usize, y: usize| normal[y * glyph.width + x];
+        assert!(texel(2, 2) > 127);   // ring
+        assert!(texel(4, 4) < 127);   // hole
+        // Mixed orientation without a hole: both contours are filled
+        let mut builder = OutlineBuilder::new();
+        for &(x0, x1) in [(0.0, 2.0), (6.0, 4.0)].iter() {
+            builder.move_to(Vec2::new(x0, 0.0));
+            builder.line_to(Vec2::new(x0, 2.0));
+            builder.line_to(Vec2::new(x1, 2.0));
+            builder.line_to(Vec2::new(x1, 0.0));
+        }
+        let outline = builder.finish();
+        let glyph = Glyph::from_outline(&outline, 1, &FontConfig::default());
+        let buffer = glyph.render_standalone(&outline, 128, &params);
+        assert!(buffer[2 * glyph.width + 2] > 127);
+        assert!(buffer[2 * glyph.width + 6] > 127);
+    }
+
+    #[test]
+    fn test_ink_rect() {
+        let mut glyph = Glyph::from_bbox(0.0, 0.0, 2.0, 3.0, 3, BoxRounding::Expand);
+        glyph.x = 4;
+        glyph.y = 1;
+        assert_eq!(glyph.rect(), Rect::new(4, 1, 8, 9));
+        assert_eq!(glyph.ink_rect(), Rect::new(7, 4, 2, 3));
+    }
+
+    #[test]
+    fn test_channels() {
+        let mut font = Font::new(8);
+        font.channels = 4;
+        font.face_size = 128;
+        let config = FontConfig::default();
+        let load_square = |_: char| (Glyph::from_outline(&square(), 1, &config), square());
+        font.build_glyphs("ab", 0, None, &load_square).unwrap();
+        // Different chars in G are packed independently, they overlap R glyphs
+        let load_bar = |_: char| {
+            let mut builder = OutlineBuilder::new();
+            builder.move_to(Vec2::new(0.0, 0.0));
+            builder.line_to(Vec2::new(0.0, 4.0));
+            builder.line_to(Vec2::new(1.0, 4.0));
+            builder.line_to(Vec2::new(1.0, 0.0));
+            let outline = builder.finish();
+            (Glyph::from_outline(&outline, 1, &config), outline)
+        };
+        font.build_glyphs("c", 1, None, load_bar).unwrap();
+        assert_eq!(font.buffer.len(), 8 * 8 * 4);
+        let (a, c) = (font.glyphs[&'a'].clone(), font.glyphs[&'c'].clone());
+        assert_eq!((a.x, a.y, a.channel), (0, 0, 0));
+        assert_eq!((c.x, c.y, c.channel), (0, 0, 1));
+        assert_eq!((c.width, c.height), (3, 6));
+        // Read back each channel: a square in R, a bar in G, B and A untouched
+        let texel = |x: usize, y: usize, ch: usize| font.buffer[(y * 8 + x) * 4 + ch];
+        assert!(texel(1, 1, 0) > 127 && texel(2, 2, 0) > 127 && texel(3, 3, 0) == 0);
+        assert!(texel(1, 1, 1) > 127 && texel(1, 4, 1) > 127 && texel(2, 1, 1) < 127);
+        assert!((0 .. 8 * 8).all(|i| font.buffer[i * 4 + 2] == 0 && font.buffer[i * 4 + 3] == 0));
+        // Sampling a glyph respects its channel
+        assert_eq!(font.sample_glyph(&c, 1.5, 4.5), texel(1, 4, 1) as f32);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut font = Font::new(16);
+        font.face_size = 128;
+        let config = FontConfig::default();
+        let cancel = AtomicBool::new(false);
+        let result = font.build_glyphs("abcd", 0, Some(&cancel), |ch| {
+            // Cancelled while rendering 'b', it's finished
+            if ch == 'b' {
+                cancel.store(true, Ordering::Relaxed);
+            }
+            (Glyph::from_outline(&square(), 1, &config), square())
+        });
+        assert_eq!(result, Err(FontError::Cancelled));
+        assert_eq!(font.glyphs.len(), 2);
+        let b = font.glyphs[&'b'].clone();
+        assert!(font.buffer[(b.y + 1) * font.width + b.x + 1] > 127);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_render() {
+        let mut font = Font::new(32);
+        font.face_size = 16;
+        let config = FontConfig::default();
+        let outlines = [square(), ring(false), ring(true)];
+        let mut tiles = Vec::new();
+        font.pack_glyphs("abc", 0, None, &mut |ch| {
+            let outline = outlines[ch as usize - 'a' as usize].clone();
+            (Glyph::from_outline(&outline, 2, &config), GlyphSource::Outline(outline))
+        }, &mut tiles).unwrap();
+        assert_eq!(tiles.len(), 3);
+        font.buffer.resize(32 * 32, 0u8);
+        font.render_tiles_serial(&tiles, 0);
+        let serial = font.buffer.clone();
+        for v in font.buffer.iter_mut() { *v = 0; }
+        font.render_tiles_parallel(&tiles, 0);
+        assert_eq!(font.buffer, serial);
+    }
+
+    // Face from the font used by the examples. The font files aren't committed,
+    // the tests using them are ignored (run with `cargo test -- --ignored`).
+    // The library is returned too, it has to outlive the face.
+    fn test_face() -> (ft::Face, ft::Library) {
+        test_face_from("assets/FreeSans.ttf")
+    }
+
+    fn test_face_from(path: &str) -> (ft::Face, ft::Library) {
+        let library = ft::Library::init().unwrap();
+        let face = library.new_face(path, 0)
+            .unwrap_or_else(|err| panic!("the test needs {} ({:?})", path, err));
+        (face, library)
+    }
+
+    #[test]
+    fn test_charmap() {
+        assert_eq!(Charmap::Unicode.ft_encoding(), 0x756E6963);
+        assert_eq!(Charmap::Symbol.ft_encoding(), 0x73796D62);
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_charmap_face() {
+        let (face, _library) = test_face();
+        assert_eq!(select_charmap(&face, Charmap::Unicode), Ok(()));
+        assert!(Font::missing_chars(&face, "Aa").is_empty());
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_measure_only() {
+        let (face, _library) = test_face();
+        let metrics = Font::measure_only(&face, 16.0, "AVA\nA");
+        assert_eq!(metrics.line_widths.len(), 2);
+        assert_eq!(metrics.width, metrics.line_widths[0]);
+        assert!(metrics.line_widths[1] > 0.0 && metrics.line_widths[1] < metrics.width);
+        assert!(metrics.height > 16.0 && metrics.height < 48.0);
+        // Metrics scale linearly with the size
+        let double = Font::measure_only(&face, 32.0, "AVA\nA");
+        assert!((double.width - 2.0 * metrics.width).abs() < 1e-3);
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_face_width() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(256);
+        font.build_from_face(&face, 32, 2, "H").unwrap();
+        let mut expanded = Font::new(256);
+        expanded.config.face_width = Some(64);
+        expanded.build_from_face(&face, 32, 2, "H").unwrap();
+        let (glyph, wide) = (&font.glyphs[&'H'], &expanded.glyphs[&'H']);
+        assert_eq!(wide.height, glyph.height);
+        let (ink, wide_ink) = (glyph.ink_rect(), wide.ink_rect());
+        assert!((wide_ink.w as isize - 2 * ink.w as isize).abs() <= 2);
+        assert!((wide.advance_x - 2.0 * glyph.advance_x).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_thin_glyph_box() {
+        // A bar 0.2 px high, rounded to nearest, would be zero size
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(1.0, 10.1));
+        builder.line_to(Vec2::new(1.0, 10.3));
+        builder.line_to(Vec2::new(9.0, 10.3));
+        builder.line_to(Vec2::new(9.0, 10.1));
+        let outline = builder.finish();
+        let config = FontConfig { box_rounding: BoxRounding::Nearest, .. Default::default() };
+        let glyph = Glyph::from_outline(&outline, 1, &config);
+        assert_eq!((glyph.ymin, glyph.height), (9, 3));
+        let buffer = glyph.render_standalone(&outline, 64, &SdfParams::default());
+        // The ink row is near the outline, not saturated
+        let row = &buffer[glyph.width .. 2 * glyph.width];
+        assert!(row[1 .. glyph.width - 1].iter().all(|&v| v > 100 && v < 127));
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_rerender_glyph() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(64);
+        font.build_from_face(&face, 16, 2, "ab").unwrap();
+        let original = font.buffer.clone();
+        let rect = font.glyphs[&'a'].rect();
+        for v in font.buffer.iter_mut() {
+            *v = 0;
+        }
+        font.take_dirty();
+        assert_eq!(font.rerender_glyph('a', &face), Ok(()));
+        assert_eq!(font.take_dirty(), Some(rect));
+        for y in rect.y .. rect.bottom() {
+            let row = y * font.pitch;
+            assert_eq!(&font.buffer[row + rect.x .. row + rect.right()],
+                       &original[row + rect.x .. row + rect.right()]);
+        }
+        assert_eq!(font.rerender_glyph('c', &face), Err(FontError::GlyphNotFound('c')));
+        font.glyphs.get_mut(&'b').unwrap().width += 1;
+        assert_eq!(font.rerender_glyph('b', &face), Err(FontError::GlyphBoxChanged('b')));
+    }
+
+    #[test]
+    fn test_non_square() {
+        let mut font = Font::with_size(2048, 512);
+        font.face_size = 128;
+        assert_eq!((font.width, font.height, font.pitch), (2048, 512, 2048));
+        let config = FontConfig::default();
+        // 50 tiles of 124x124 pixels, more than fit in 512x512
+        let chars: String = (0 .. 50).map(|i| ::std::char::from_u32(0x100 + i).unwrap()).collect();
+        let load_tile = |_: char| (Glyph::from_outline(&square(), 61, &config), square());
+        font.build_glyphs(&chars, 0, None, &load_tile).unwrap();
+        assert_eq!(font.glyphs.len(), 50);
+        assert_eq!(font.buffer.len(), 2048 * 512);
+        for glyph in font.glyphs.values() {
+            assert_eq!((glyph.width, glyph.height), (124, 124));
+            assert!(glyph.rect().right() <= 2048 && glyph.rect().bottom() <= 512);
+        }
+        assert!(font.glyphs.values().any(|glyph| glyph.x >= 512));
+        assert_eq!(font.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_gap() {
+        let mut font = Font::new(16);
+        font.face_size = 128;
+        font.config.gap = 3;
+        let config = FontConfig::default();
+        let load_square = |_: char| (Glyph::from_outline(&square(), 1, &config), square());
+        font.build_glyphs("ab", 0, None, &load_square).unwrap();
+        let (a, b) = (font.glyphs[&'a'].rect(), font.glyphs[&'b'].rect());
+        // The rectangles extended by the gap don't reach the other one
+        let extend = |r: Rect| Rect::new(r.x, r.y, r.w + 3, r.h + 3);
+        assert!(!extend(a).intersects(&b) && !extend(b).intersects(&a));
+        // The pixels between them are saturated outside
+        let (left, right) = if a.x < b.x { (a, b) } else { (b, a) };
+        if left.right() < right.x {
+            for y in 0 .. 16 {
+                for x in left.right() .. right.x {
+                    assert_eq!(font.buffer[y * font.pitch + x], 0);
+                }
+            }
+        } else {
+            let (top, bottom) = if a.y < b.y { (a, b) } else { (b, a) };
+            for y in top.bottom() .. bottom.y {
+                assert!(font.buffer[y * font.pitch .. (y + 1) * font.pitch].iter().all(|&v| v == 0));
+            }
+        }
+        assert_eq!(font.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_shared_pixels() {
+        let mut font = Font::new(8);
+        let image = |size: usize| move |_: char| {
+            (Glyph { width: size, height: size, .. Default::default() },
+             GlyphSource::Pixels(vec![200u8; size * size]))
+        };
+        font.build_glyphs_from("a", 0, None, image(4)).unwrap();
+        // Same box, the pixels are copied again
+        assert_eq!(font.build_glyphs_from("a", 0, None, image(4)), Ok(()));
+        // Smaller or larger image doesn't fit the stored box
+        assert_eq!(font.build_glyphs_from("a", 0, None, image(2)),
+                   Err(FontError::GlyphBoxChanged('a')));
+        assert_eq!(font.build_glyphs_from("a", 0, None, image(6)),
+                   Err(FontError::GlyphBoxChanged('a')));
+        assert_eq!(font.glyphs[&'a'].rect(), Rect::new(0, 0, 4, 4));
+        assert_eq!(font.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_empty_and_same_glyphs() {
+        let mut font = Font::new(4);
+        font.face_size = 128;
+        let config = FontConfig::default();
+        // The empty glyph takes no space, the square fills the whole texture
+        font.build_glyphs_from(" xy", 0, None, |ch| match ch {
+            ' ' => {
+                let mut glyph = Glyph::from_outline(&Outline::new(), 1, &config);
+                glyph.advance_x = 3.0;
+                (glyph, GlyphSource::Outline(Outline::new()))
+            }
+            'x' => (Glyph::from_outline(&square(), 1, &config), GlyphSource::Outline(square())),
+            _ => (Glyph::default(), GlyphSource::Same(GlyphKey::Char('x'))),
+        }).unwrap();
+        let space = font.glyphs[&' '].clone();
+        assert_eq!((space.width, space.height, space.advance_x), (0, 0, 3.0));
+        assert_eq!(space.padding, 1);
+        assert_eq!(font.glyphs[&'y'].rect(), font.glyphs[&'x'].rect());
+        assert_eq!(font.take_dirty(), Some(Rect::new(0, 0, 4, 4)));
+        assert_eq!(font.validate(), Ok(()));
+        assert_eq!(font.repack(), Ok(()));
+        assert_eq!(font.glyphs[&'y'].rect(), font.glyphs[&'x'].rect());
+    }
+
+    #[test]
+    fn test_pack_keys() {
+        let mut font = Font::new(8);
+        font.face_size = 128;
+        font.config.retain_outlines = true;
+        let config = FontConfig::default();
+        // Index 7 is the glyph of 'a', index 8 is another one
+        let keys = [GlyphKey::Char('a'), GlyphKey::Index(7), GlyphKey::Index(8)];
+        let mut load = |key: GlyphKey| -> Result<(Glyph, GlyphSource), FontError> {
+            match key {
+                GlyphKey::Index(7) => Ok((Glyph::default(), GlyphSource::Same(GlyphKey::Char('a')))),
+                _ => Ok((Glyph::from_outline(&square(), 1, &config), GlyphSource::Outline(square()))),
+            }
+        };
+        let mut tiles = Vec::new();
+        assert_eq!(font.pack_keys(&keys, 0, None, &mut load, &mut tiles), Ok(()));
+        assert_eq!(tiles.len(), 2);
+        assert_eq!(font.index_glyphs[&7].rect(), font.glyphs[&'a'].rect());
+        assert!(font.index_glyphs[&8].rect() != font.glyphs[&'a'].rect());
+        assert!(font.outlines.contains_key(&'a'));
+        assert!(font.index_outlines.contains_key(&8));
+        assert!(!font.index_outlines.contains_key(&7));
+        assert_eq!(font.validate_layout(), Ok(()));
+        // Cancelled before the first glyph
+        let cancel = AtomicBool::new(true);
+        assert_eq!(font.pack_keys(&[GlyphKey::Index(9)], 0, Some(&cancel), &mut load, &mut tiles),
+                   Err(FontError::Cancelled));
+        assert!(!font.index_glyphs.contains_key(&9));
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_empty_and_same_glyphs_face() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(64);
+        font.build_from_face(&face, 16, 2, "a \u{10FFFF}\u{10FFFE}").unwrap();
+        let space = font.glyphs[&' '].clone();
+        assert_eq!((space.width, space.height, space.padding), (0, 0, 2));
+        assert!(space.advance_x > 0.0);
+        // Missing chars share .notdef
+        assert_eq!(font.glyphs[&'\u{10FFFF}'].rect(), font.glyphs[&'\u{10FFFE}'].rect());
+        assert_eq!(font.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_build_append() {
+        let mut font = Font::new(8);
+        font.face_size = 128;
+        let config = FontConfig::default();
+        let load_square = |_: char| (Glyph::from_outline(&square(), 1, &config), square());
+        font.build_glyphs("ab", 0, None, &load_square).unwrap();
+        // The second build packs into the remaining space
+        font.build_glyphs("cd", 0, None, &load_square).unwrap();
+        assert_eq!(font.validate(), Ok(()));
+        assert_eq!(font.build_glyphs("e", 0, None, &load_square), Err(FontError::TextureFull('e')));
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_build_append_face() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(128);
+        font.build_from_face(&face, 16, 2, "abc").unwrap();
+        let a = font.glyphs[&'a'].clone();
+        font.build_append(&face, 16, 2, "cdef").unwrap();
+        assert_eq!(font.glyphs.len(), 6);
+        assert_eq!(font.glyphs[&'a'].rect(), a.rect());
+        assert_eq!(font.validate(), Ok(()));
+        assert_eq!(font.build_append(&face, 16, 2, "fa"), Ok(()));
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_add_chars() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(64);
+        // The space has no box, the padding comes from the build
+        font.build_from_face(&face, 16, 2, "A B").unwrap();
+        assert_eq!(font.padding, 2);
+        let original = font.buffer.clone();
+        let rects: Vec<Rect> = ['A', 'B'].iter().map(|ch| font.glyphs[ch].rect()).collect();
+        assert_eq!(font.add_chars(&face, "C"), Ok(()));
+        assert_eq!(font.glyphs.len(), 4);
+        assert_eq!(font.glyphs[&'C'].padding, 2);
+        for rect in &rects {
+            for y in rect.y .. rect.bottom() {
+                let row = y * font.pitch;
+                assert_eq!(&font.buffer[row + rect.x .. row + rect.right()],
+                           &original[row + rect.x .. row + rect.right()]);
+            }
+        }
+        assert_eq!(font.validate(), Ok(()));
+        // No space left
+        let mut font = Font::new(24);
+        font.build_from_face(&face, 16, 2, "W").unwrap();
+        assert_eq!(font.add_chars(&face, "MW"), Err(FontError::TextureFull('M')));
+    }
+
+    #[test]
+    fn test_index_glyphs() {
+        let mut font = Font::new(8);
+        font.glyphs.insert('a', Glyph { width: 4, height: 4, .. Default::default() });
+        font.index_glyphs.insert(7, Glyph { x: 2, y: 2, width: 4, height: 4, .. Default::default() });
+        font.buffer.resize(64, 0);
+        match font.validate() {
+            Err(FontError::CorruptAtlas(details)) => assert!(details.contains("#7")),
+            other => panic!("unexpected {:?}", other),
+        }
+        font.index_glyphs.get_mut(&7).unwrap().x = 4;
+        assert_eq!(font.validate(), Ok(()));
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_index_glyphs_face() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(128);
+        font.build_from_face(&face, 16, 2, "A").unwrap();
+        let index = face.get_char_index('A' as usize);
+        assert_eq!(font.build_from_face_indices(&face, 16, 2, &[index]), Ok(()));
+        let (by_char, by_index) = (&font.glyphs[&'A'], &font.index_glyphs[&index]);
+        assert_eq!((by_index.width, by_index.height), (by_char.width, by_char.height));
+        assert_eq!((by_index.xmin, by_index.ymin), (by_char.xmin, by_char.ymin));
+        assert_eq!(by_index.advance_x, by_char.advance_x);
+        // The same glyph shares the tile with the char
+        assert_eq!(by_index.rect(), by_char.rect());
+        assert_eq!(font.validate(), Ok(()));
+        assert_eq!(font.build_from_face_indices(&face, 16, 2, &[0xFFFFFF]),
+                   Err(FontError::GlyphIndexNotFound(0xFFFFFF)));
+    }
+
+    #[test]
+    fn test_variations() {
+        assert_eq!(make_tag(b"wght"), 0x77676874);
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_variations_face() {
+        // Non-variable face ignores the settings
+        let (face, _library) = test_face();
+        let mut font = Font::new(128);
+        font.build_from_face(&face, 32, 2, "o").unwrap();
+        let mut varied = Font::new(128);
+        varied.config.variations = vec![(*b"wght", 900.0)];
+        varied.build_from_face(&face, 32, 2, "o").unwrap();
+        assert_eq!(varied.glyphs[&'o'].rect(), font.glyphs[&'o'].rect());
+        assert_eq!(varied.buffer, font.buffer);
+    }
+
+    // needs assets/RobotoFlex-Variable.ttf
+    #[test]
+    #[ignore]
+    fn test_variable_face() {
+        let (face, _library) = test_face_from("assets/RobotoFlex-Variable.ttf");
+        let build = |weight: f32| {
+            let mut font = Font::new(128);
+            font.config.variations = vec![(*b"wght", weight), (*b"none", 1.0)];
+            font.build_from_face(&face, 32, 2, "o").unwrap();
+            font.glyphs[&'o'].clone()
+        };
+        let (light, bold) = (build(300.0), build(800.0));
+        assert!(bold.ink_rect().w > light.ink_rect().w);
+        assert!(bold.advance_x > light.advance_x);
+    }
+
+    // needs assets/6x13.bdf (a bitmap-only face)
+    #[test]
+    #[ignore]
+    fn test_not_scalable() {
+        let (face, _library) = test_face_from("assets/6x13.bdf");
+        let mut font = Font::new(64);
+        assert_eq!(font.build_append(&face, 16, 2, "A"), Err(FontError::NotScalable));
+        assert_eq!(font.build_from_face_indices(&face, 16, 2, &[1]), Err(FontError::NotScalable));
+        assert_eq!(font.build_from_face(&face, 16, 2, "A"), Err(FontError::NotScalable));
+        assert_eq!(font.build_from_faces(&[&face], 16, 2, "A"), Err(FontError::NotScalable));
+        assert!(font.analyze(&face, 16, "A").is_err());
+        assert!(render_glyph_sdf(&face, 'A', 16, 2, &font.config, &font.params).is_err());
+        // Nothing was built
+        assert_eq!(font.face_size, 0);
+        assert!(font.glyphs.is_empty());
+    }
+
+    #[test]
+    fn test_line_metrics() {
+        let mut font = Font::new(8);
+        font.face_size = 32;
+        font.vertical = LineMetrics { ascent: 24.0, descent: 8.0, line_height: 36.0, baseline: 26.0 };
+        let metrics = font.line_metrics(16.0);
+        assert_eq!(metrics, LineMetrics { ascent: 12.0, descent: 4.0, line_height: 18.0, baseline: 13.0 });
+        for &px in [8.0, 12.5, 64.0].iter() {
+            assert!((font.line_metrics(px).line_height - metrics.line_height * px / 16.0).abs() < 1e-4);
+        }
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_line_metrics_face() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(8);
+        font.build_from_face(&face, 32, 2, "").unwrap();
+        let metrics = font.line_metrics(32.0);
+        assert!(metrics.ascent > 16.0 && metrics.descent > 0.0);
+        assert!(metrics.line_height >= metrics.ascent + metrics.descent);
+        assert!(metrics.baseline >= metrics.ascent);
+    }
+
+    #[test]
+    fn test_union_overlaps() {
+        // Two overlapping circles of the same orientation
+        let mut builder = OutlineBuilder::new();
+        let k = 0.5523 * 6.0;
+        for &cx in [10.0, 18.0].iter() {
+            let p = |x: f32, y: f32| Vec2::new(cx + x, 10.0 + y);
+            builder.move_to(p(6.0, 0.0));
+            builder.curve_to(p(6.0, -k), p(k, -6.0), p(0.0, -6.0));
+            builder.curve_to(p(-k, -6.0), p(-6.0, -k), p(-6.0, 0.0));
+            builder.curve_to(p(-6.0, k), p(-k, 6.0), p(0.0, 6.0));
+            builder.curve_to(p(k, 6.0), p(6.0, k), p(6.0, 0.0));
+        }
+        let outline = builder.finish();
+        let glyph = Glyph::from_outline(&outline, 1, &FontConfig::default());
+        assert_eq!((glyph.xmin, glyph.ymin, glyph.width, glyph.height), (3, 3, 22, 14));
+        let plain = glyph.render_standalone(&outline, 32, &SdfParams::default());
+        let params = SdfParams { union_overlaps: true, .. SdfParams::default() };
+        let union = glyph.render_standalone(&outline, 32, &params);
+        // Pixel at (15.5, 9.5) is half a pixel from the inner arc of the left circle
+        let i = 7 * glyph.width + 12;
+        assert!(plain[i] < 200);
+        assert_eq!(union[i], 255);
+        // The overlap is solidly inside, without a seam
+        for x in 11 .. 14 {
+            assert_eq!(union[7 * glyph.width + x], 255);
+        }
+        // The outside is the same
+        for (a, b) in plain.iter().zip(union.iter()) {
+            if *a < 127 {
+                assert!((*a as i32 - *b as i32).abs() <= 1);
+            }
+        }
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_build_msdf() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(64);
+        font.channels = 3;
+        font.build_msdf_from_face(&face, 32, 2, "L", MSDF_ANGLE_THRESHOLD).unwrap();
+        assert_eq!(font.buffer.len(), 64 * 64 * 3);
+        // Inside of the vertical stem, near the bottom left corner
+        let glyph = &font.glyphs[&'L'];
+        let ink = glyph.ink_rect();
+        let i = ((ink.bottom() - 2) * font.pitch + ink.x + 1) * 3;
+        let texel = &font.buffer[i .. i + 3];
+        assert!(median(texel[0] as f32, texel[1] as f32, texel[2] as f32) > 127.0);
+    }
+
+    #[test]
+    fn test_kerning() {
+        let mut font = filled_font();
+        font.kerning_pairs.insert(('A', 'V'), Vec2::new(-1.5, 0.0));
+        assert_eq!(font.kerning('A', 'V'), Vec2::new(-1.5, 0.0));
+        // No kerning pair
+        assert_eq!(font.kerning('V', 'A'), Vec2::new(0.0, 0.0));
+        assert_eq!(font.kerning('x', '?'), Vec2::new(0.0, 0.0));
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_kerning_face() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(128);
+        font.build_from_face(&face, 32, 2, "AVx").unwrap();
+        let a = font.glyphs[&'A'].clone();
+        assert!(a.advance_x > 0.0 && a.advance_y == 0.0);
+        assert!((a.xmin as f32 + a.padding as f32 - a.bearing_x).abs() <= 1.0);
+        assert!(font.kerning('A', 'V').x < 0.0);
+        assert_eq!(font.kerning('x', 'x'), Vec2::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn test_render_contours_sdf() {
+        let line = |x0, y0, x1, y1| PathSegment::Line(LinearSegment::new(Vec2::new(x0, y0),
+                                                                         Vec2::new(x1, y1)));
+        let triangle = vec![line(0.0, 0.0, 4.0, 8.0), line(4.0, 8.0, 8.0, 0.0),
+                            line(8.0, 0.0, 0.0, 0.0)];
+        let (buffer, glyph) = render_contours_sdf(vec![triangle], (0.0, 0.0, 8.0, 8.0),
+                                                  16, 1, &SdfParams::default());
+        assert_eq!((glyph.width, glyph.height, glyph.xmin, glyph.ymin), (10, 10, -1, -1));
+        assert_eq!(buffer.len(), 100);
+        // Pixel centered at (4.5, 3.5) is inside, the padding is outside
+        assert!(buffer[5 * 10 + 5] > 127);
+        assert_eq!(buffer[0], 0);
+        // Corner of the box, outside of the triangle
+        assert!(buffer[1 * 10 + 1] < 127);
+    }
+
+    #[test]
+    fn test_render_f32() {
+        let config = FontConfig::default();
+        let params = SdfParams::default();
+        let outline = ring(false);
+        let glyph = Glyph::from_outline(&outline, 2, &config);
+        let (w, h) = (glyph.width, glyph.height);
+        let encoded = glyph.render_standalone(&outline, 16, &params);
+        let mut distances = vec![0f32; w * h];
+        glyph.render_outline_sdf_f32(&outline, &params, &mut distances, w);
+        for yr in 0 .. h {
+            for xr in 0 .. w {
+                let i = yr * w + xr;
+                if xr == 0 || yr == 0 || xr + 1 == w || yr + 1 == h {
+                    // The padding border is saturated in u8, exact in f32
+                    assert_eq!(encoded[i], 0);
+                    assert!(distances[i] > 0.0);
+                } else {
+                    assert_eq!(params.encode(distances[i], 16), encoded[i], "at {}, {}", xr, yr);
+                }
+            }
+        }
+        // Pixels by their bottom left corner: inside of the ring, in the hole
+        let p = |x: isize, y: isize| ((glyph.ymin + h as isize - 1 - y) * w as isize
+                                       + x - glyph.xmin) as usize;
+        assert!((distances[p(0, 0)] + 0.5).abs() < 1e-6);
+        assert!((distances[p(1, 1)] + 0.5f32.hypot(0.5)).abs() < 1e-6);
+        assert!((distances[p(3, 3)] - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_render_pseudo_distance() {
+        let config = FontConfig::default();
+        let outline = square();
+        let glyph = Glyph::from_outline(&outline, 3, &config);
+        let (w, h) = (glyph.width, glyph.height);
+        let mut true_dist = vec![0f32; w * h];
+        let mut pseudo = vec![0f32; w * h];
+        glyph.render_outline_sdf_f32(&outline, &SdfParams::default(), &mut true_dist, w);
+        let params = SdfParams { pseudo_distance: true, .. Default::default() };
+        glyph.render_outline_sdf_f32(&outline, &params, &mut pseudo, w);
+        // By bottom left corner of the pixel, relative to the square
+        let p = |x: isize, y: isize| ((glyph.ymin + h as isize - 1 - y) * w as isize
+                                       + x - glyph.xmin) as usize;
+        // Inside and facing the edges, the same
+        for &(x, y) in [(0, 0), (1, 1), (0, 2), (-1, 1), (1, -2)].iter() {
+            assert!((pseudo[p(x, y)] - true_dist[p(x, y)]).abs() < 1e-6, "at {}, {}", x, y);
+        }
+        // Diagonally off the corner: the true distance to the vertex,
+        // the pseudo-distance to the prolonged edge
+        assert!((true_dist[p(2, 2)] - 0.5f32.hypot(0.5)).abs() < 1e-6);
+        assert!((pseudo[p(2, 2)] - 0.5).abs() < 1e-6);
+        assert!((true_dist[p(3, 2)] - 1.5f32.hypot(0.5)).abs() < 1e-6);
+        assert!((pseudo[p(3, 2)] - 1.5).abs() < 1e-6);
+        // The encoded SDF is larger (closer to the outline) at the corner
+        let encoded = glyph.render_standalone(&outline, 16, &SdfParams::default());
+        let sharp = glyph.render_standalone(&outline, 16, &params);
+        assert!(sharp[p(2, 2)] > encoded[p(2, 2)]);
+    }
+
+    #[test]
+    fn test_fill_rule() {
+        // Five-pointed star drawn by one self-intersecting contour,
+        // the pentagon in the middle is wound twice
+        let mut builder = OutlineBuilder::new();
+        for k in 0 .. 5 {
+            let angle = (90.0 + 144.0 * k as f32).to_radians();
+            let point = Vec2::new(10.0 + 8.0 * angle.cos(), 10.0 + 8.0 * angle.sin());
+            if k == 0 { builder.move_to(point) } else { builder.line_to(point) }
+        }
+        let outline = builder.finish();
+        let glyph = Glyph::from_outline(&outline, 1, &FontConfig::default());
+        let (w, h) = (glyph.width, glyph.height);
+        let p = |x: isize, y: isize| ((glyph.ymin + h as isize - 1 - y) * w as isize
+                                       + x - glyph.xmin) as usize;
+        let mut non_zero = vec![0f32; w * h];
+        glyph.render_outline_sdf_f32(&outline, &SdfParams::default(), &mut non_zero, w);
+        let params = SdfParams { fill_rule: FillRule::EvenOdd, .. Default::default() };
+        let mut even_odd = vec![0f32; w * h];
+        glyph.render_outline_sdf_f32(&outline, &params, &mut even_odd, w);
+        // The middle
+        assert!(non_zero[p(10, 10)] < 0.0);
+        assert!(even_odd[p(10, 10)] > 0.0);
+        assert_eq!(non_zero[p(10, 10)], -even_odd[p(10, 10)]);
+        // The arm and the outside are the same
+        assert!(non_zero[p(9, 13)] < 0.0 && even_odd[p(9, 13)] < 0.0);
+        assert!(non_zero[p(10, 18)] > 0.0 && even_odd[p(10, 18)] > 0.0);
+        // Coverage follows the rule
+        let mut coverage = vec![0u8; w * h * 2];
+        glyph.render_outline_sdf_coverage(&outline, 16, &params, &mut coverage, w);
+        assert_eq!(coverage[p(10, 10) * 2 + 1], 0);
+        glyph.render_outline_sdf_coverage(&outline, 16, &SdfParams::default(), &mut coverage, w);
+        assert_eq!(coverage[p(10, 10) * 2 + 1], 255);
+    }
+
+    #[test]
+    fn test_stroke_width() {
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(0.0, 0.0));
+        builder.line_to(Vec2::new(0.0, 40.0));
+        builder.line_to(Vec2::new(40.0, 40.0));
+        builder.line_to(Vec2::new(40.0, 0.0));
+        let outline = builder.finish();
+        let glyph = Glyph::from_outline(&outline, 6, &FontConfig::default());
+        let (w, h) = (glyph.width, glyph.height);
+        let p = |x: isize, y: isize| ((glyph.ymin + h as isize - 1 - y) * w as isize
+                                       + x - glyph.xmin) as usize;
+        let mut params = SdfParams { spread: Some(4.0), .. Default::default() };
+        let mut filled = vec![0u8; w * h];
+        glyph.render_outline_sdf(&outline, 64, &params, &mut filled, w);
+        params.stroke_width = Some(4.0);
+        let mut stroked = vec![0u8; w * h];
+        glyph.render_outline_sdf(&outline, 64, &params, &mut stroked, w);
+        // The interior is outside the stroke
+        assert!(filled[p(20, 20)] > 127);
+        assert_eq!(stroked[p(20, 20)], 0);
+        assert!(stroked[p(2, 20)] < 127);
+        // The band around the contour is inside, on both sides of it
+        assert!(filled[p(0, 20)] > 127 && stroked[p(0, 20)] > 127);
+        assert!(filled[p(-1, 20)] < 127 && stroked[p(-1, 20)] > 127);
+        // Beyond the half stroke, outside again
+        assert!(stroked[p(-3, 20)] < 127);
+        // The value is symmetric around the contour
+        assert_eq!(stroked[p(0, 20)], stroked[p(-1, 20)]);
+    }
+
+    #[test]
+    fn test_invert() {
+        let outline = square();
+        let glyph = Glyph::from_outline(&outline, 2, &FontConfig::default());
+        let params = SdfParams { spread: Some(2.0), .. SdfParams::default() };
+        let inverted = SdfParams { invert: true, .. params.clone() };
+        let normal_sdf = glyph.render_standalone(&outline, 16, &params);
+        let inverted_sdf = glyph.render_standalone(&outline, 16, &inverted);
+        assert_eq!(inverted.encode(0.0, 16), 127);
+        // The padding border is saturated outside, in either encoding
+        assert_eq!((normal_sdf[0], inverted_sdf[0]), (0, 254));
+        assert_eq!(inverted.outside_value(), 254);
+        let mut font = Font::new(16);
+        font.params = inverted.clone();
+        let config = FontConfig::default();
+        font.build_glyphs("a", 0, None, |_| (Glyph::from_outline(&square(), 1, &config), square())).unwrap();
+        assert_eq!(*font.buffer.last().unwrap(), 254);
+        assert!(normal_sdf.iter().any(|&v| v > 127));
+        for (&a, &b) in normal_sdf.iter().zip(inverted_sdf.iter()) {
+            assert!((a > 127) == (b < 127) && (a == 127) == (b == 127));
+            assert_eq!(inverted.decode(b as f32, 16), params.decode(a as f32, 16));
+        }
+        assert_eq!(invert_sdf(&normal_sdf, 127), inverted_sdf);
+        // Inverting twice gives the original
+        assert_eq!(invert_sdf(&inverted_sdf, 127), normal_sdf);
+        assert_eq!(invert_sdf(&invert_sdf(&[0, 126, 127, 128, 254, 255], 127), 127),
+                   vec![0, 126, 127, 128, 254, 254]);
+    }
+
+    #[test]
+    fn test_threshold_to_monochrome() {
+        assert_eq!(sdf_to_monochrome(&[0, 126, 127, 128, 255], 127), vec![0, 0, 255, 255, 255]);
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_threshold_to_monochrome_face() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(128);
+        font.config.retain_outlines = true;
+        font.build_from_face(&face, 48, 3, "o").unwrap();
+        let bitmap = font.threshold_to_monochrome();
+        assert_eq!(bitmap.len(), font.buffer.len());
+        // Direct monochrome render, sampled at the same pixel centers
+        let glyph = &font.glyphs[&'o'];
+        let mut outline = font.outlines[&'o'].clone();
+        outline.transform(&Transform::scale(48.0, 48.0));
+        let rasterizer = outline.rasterizer();
+        let mut agree = 0;
+        for yr in 0 .. glyph.height {
+            let y = (glyph.ymin + (glyph.height - yr - 1) as isize) as f32 + 0.5;
+            for xr in 0 .. glyph.width {
+                let x = (glyph.xmin + xr as isize) as f32 + 0.5;
+                let inside = rasterizer.contains(Vec2::new(x, y), FillRule::NonZero);
+                let value = bitmap[(glyph.y + yr) * font.pitch + glyph.x + xr];
+                if (value == 255) == inside {
+                    agree += 1;
+                }
+            }
+        }
+        assert!(agree * 100 > glyph.width * glyph.height * 99,
+                "{} of {} pixels agree", agree, glyph.width * glyph.height);
+    }
+
+    #[test]
+    fn test_coverage_samples() {
+        // Vertical hairline between the pixel centers, x in 2.55 .. 3.45
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(2.55, 0.0));
+        builder.line_to(Vec2::new(2.55, 6.0));
+        builder.line_to(Vec2::new(3.45, 6.0));
+        builder.line_to(Vec2::new(3.45, 0.0));
+        let outline = builder.finish();
+        let glyph = Glyph::from_outline(&outline, 1, &FontConfig::default());
+        let midpoint = SdfParams::default().midpoint;
+        // Only pixel centers: the stem is outside everywhere
+        let plain = glyph.render_standalone(&outline, 16, &SdfParams::default());
+        assert!(plain.iter().all(|&value| value < midpoint));
+        // Half of the samples in the pixels around it are inside
+        let params = SdfParams { coverage_samples: 4, .. Default::default() };
+        let sampled = glyph.render_standalone(&outline, 16, &params);
+        let row = glyph.width * (glyph.height / 2);
+        let inside: Vec<isize> = (0 .. glyph.width).filter(|&xr| sampled[row + xr] >= midpoint)
+            .map(|xr| glyph.xmin + xr as isize).collect();
+        assert_eq!(inside, vec![2, 3]);
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_single_glyph_matches_atlas() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(256);
+        font.config.box_rounding = BoxRounding::Nearest;
+        font.build_from_face(&face, 32, 3, "g").unwrap();
+        let (buffer, single) = render_glyph_sdf(&face, 'g', 32, 3, &font.config, &font.params).unwrap();
+        let glyph = &font.glyphs[&'g'];
+        assert_eq!((single.width, single.height), (glyph.width, glyph.height));
+        assert_eq!((single.xmin, single.ymin), (glyph.xmin, glyph.ymin));
+        for yr in 0 .. glyph.height {
+            let offset = (glyph.y + yr) * font.pitch + glyph.x;
+            assert_eq!(&buffer[yr * glyph.width .. (yr + 1) * glyph.width],
+                       &font.buffer[offset .. offset + glyph.width]);
+        }
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_render_char_to_buffer() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(256);
+        font.params.spread = Some(2.5);
+        font.build_from_face(&face, 24, 2, "&Aq").unwrap();
+        for ch in "&Aq".chars() {
+            let (single, buffer) = font.render_char_to_buffer(&face, 24, 2, ch).unwrap();
+            let glyph = &font.glyphs[&ch];
+            assert_eq!(buffer.len(), single.width * single.height);
+            assert_eq!((single.x, single.y), (0, 0));
+            assert_eq!((single.width, single.height), (glyph.width, glyph.height));
+            assert_eq!((single.xmin, single.ymin), (glyph.xmin, glyph.ymin));
+            for yr in 0 .. glyph.height {
+                let offset = (glyph.y + yr) * font.pitch + glyph.x;
+                assert_eq!(&buffer[yr * glyph.width .. (yr + 1) * glyph.width],
+                           &font.buffer[offset .. offset + glyph.width], "{:?} row {}", ch, yr);
+            }
+        }
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_build_from_face_with() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(256);
+        let mut seen = Vec::new();
+        font.build_from_face_with(&face, 24, 2, "A q.", |ch, glyph, pixels| {
+            assert_eq!(pixels.len(), glyph.width * glyph.height);
+            seen.push((ch, glyph.clone(), pixels.to_vec()));
+        }).unwrap();
+        assert_eq!(seen.len(), 4);
+        for (&(ch, ref glyph, ref pixels), expected) in seen.iter().zip("A q.".chars()) {
+            assert_eq!(ch, expected);
+            // The pixels are final, the same as in the finished texture
+            let stored = &font.glyphs[&ch];
+            assert_eq!((glyph.x, glyph.y, glyph.width, glyph.height),
+                       (stored.x, stored.y, stored.width, stored.height));
+            for yr in 0 .. glyph.height {
+                let offset = (glyph.y + yr) * font.pitch + glyph.x;
+                assert_eq!(&pixels[yr * glyph.width .. (yr + 1) * glyph.width],
+                           &font.buffer[offset .. offset + glyph.width]);
+            }
+        }
+        // Space has no pixels
+        assert!(seen[1].2.is_empty());
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_build_from_face_auto() {
+        let (face, _library) = test_face();
+        let chars = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+        let mut font = Font::new(256);
+        // The chars don't fit in the initial texture
+        let result = font.build_from_face_cancellable(&face, 64, 2, chars, &AtomicBool::new(false));
+        assert!(result.is_err());
+        let mut font = Font::new(256);
+        assert_eq!(font.build_from_face_auto(&face, 64, 2, chars, 1024), Ok((512, 512)));
+        assert_eq!((font.width, font.pitch, font.buffer.len()), (512, 512, 512 * 512));
+        assert_eq!(font.glyphs.len(), chars.len());
+        font.validate().unwrap();
+        // A glyph larger than the maximal texture
+        let mut font = Font::new(64);
+        assert_eq!(font.build_from_face_auto(&face, 512, 2, "W", 256), Err(FontError::TextureFull('W')));
+        assert_eq!(font.width, 256);
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_build_f32_from_face() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(128);
+        font.build_from_face(&face, 32, 3, "ag ").unwrap();
+        let (glyphs, distances) = font.build_f32_from_face(&face, 32, 3, "ag ").unwrap();
+        assert_eq!(distances.len(), 128 * 128);
+        assert_eq!(glyphs.len(), 3);
+        // The font is left as it was
+        assert_eq!(font.glyphs.len(), 3);
+        assert_eq!(font.buffer.len(), 128 * 128);
+        for ch in "ag".chars() {
+            let (glyph, built) = (&glyphs[&ch], &font.glyphs[&ch]);
+            assert_eq!((glyph.x, glyph.y, glyph.width, glyph.height),
+                       (built.x, built.y, built.width, built.height));
+            // The u8 texture is the quantized f32 one (except the saturated padding border)
+            for yr in 1 .. glyph.height - 1 {
+                for xr in 1 .. glyph.width - 1 {
+                    let i = (glyph.y + yr) * 128 + glyph.x + xr;
+                    assert_eq!(font.params.encode(distances[i], 32), font.buffer[i],
+                               "{:?} at {}, {}", ch, xr, yr);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_min_stroke_width() {
+        let params = SdfParams::default();
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(0.0, 0.0));
+        builder.line_to(Vec2::new(0.0, 3.0));
+        builder.line_to(Vec2::new(20.0, 3.0));
+        builder.line_to(Vec2::new(20.0, 0.0));
+        let bar = builder.finish();
+        assert!((min_stroke_width(&bar, 64, &params) - 3.0).abs() < 0.1);
+        assert!((min_stroke_width(&square(), 64, &params) - 2.0).abs() < 0.1);
+        assert_eq!(min_stroke_width(&Outline::new(), 64, &params), f32::INFINITY);
+        // A thick stem with a short hairline (much less than a tenth of the peaks)
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(0.0, 0.0));
+        builder.line_to(Vec2::new(0.0, 10.0));
+        builder.line_to(Vec2::new(10.0, 10.0));
+        builder.line_to(Vec2::new(10.0, 5.25));
+        builder.line_to(Vec2::new(11.5, 5.25));
+        builder.line_to(Vec2::new(11.5, 4.75));
+        builder.line_to(Vec2::new(10.0, 4.75));
+        builder.line_to(Vec2::new(10.0, 0.0));
+        let stem = builder.finish();
+        assert!((min_stroke_width(&stem, 64, &params) - 0.5).abs() < 0.1);
+        // A rotated corner of the stem alone makes no small peaks
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(5.0, 0.0));
+        builder.line_to(Vec2::new(0.0, 5.0));
+        builder.line_to(Vec2::new(5.0, 10.0));
+        builder.line_to(Vec2::new(10.0, 5.0));
+        let diamond = builder.finish();
+        assert!(min_stroke_width(&diamond, 64, &params) > 6.0);
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_analyze() {
+        let (face, _library) = test_face();
+        let font = Font::new(256);
+        let small = font.analyze(&face, 8, "& ").unwrap();
+        let large = font.analyze(&face, 64, "& ").unwrap();
+        assert_eq!((small[0].0, small[1].0), ('&', ' '));
+        // Too small to keep the strokes' interior
+        assert!(small[0].1 < 2.0);
+        assert!(large[0].1 > 2.0);
+        assert!((large[0].1 - 8.0 * small[0].1).abs() < 0.5);
+        assert_eq!(small[1].1, f32::INFINITY);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_gray_image() {
+        let mut font = filled_font();
+        font.buffer[1] = 10;   // top row
+        let image = font.to_gray_image();
+        assert_eq!(image.dimensions(), (8, 8));
+        assert_eq!(image.get_pixel(1, 0).data, [10]);
+        assert_eq!(image.into_raw(), font.buffer);
+        let image = font.glyph_gray_image('x').unwrap();
+        assert_eq!(image.dimensions(), (4, 4));
+        assert_eq!(image.get_pixel(1, 0).data, [10]);
+        assert!(font.glyph_gray_image('y').is_none());
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_save_png() {
+        let mut font = filled_font();
+        font.buffer[1] = 10;
+        let path = ::std::env::temp_dir().join("sdf-text-test-atlas.png");
+        font.save_png(&path).unwrap();
+        let decoder = ::png::Decoder::new(fs::File::open(&path).unwrap());
+        let (info, mut reader) = decoder.read_info().unwrap();
+        assert_eq!((info.width, info.height), (8, 8));
+        assert_eq!(info.color_type, ::png::ColorType::Grayscale);
+        let mut data = vec![0u8; info.buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        assert_eq!(data, font.buffer);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(all(feature = "png", feature = "json"))]
+    fn write_file(path: &path::Path, text: &str) {
+        use std::io::Write;
+        fs::File::create(path).unwrap().write_all(text.as_bytes()).unwrap();
+    }
+
+    #[cfg(all(feature = "png", feature = "json"))]
+    #[test]
+    fn test_load() {
+        let mut font = filled_font();
+        font.buffer[1] = 10;
+        font.glyphs.insert('A', Glyph { x: 4, y: 4, width: 4, height: 4, advance_x: 4.0,
+                                        .. Default::default() });
+        let dir = ::std::env::temp_dir();
+        let png_path = dir.join("sdf-text-test-load.png");
+        let json_path = dir.join("sdf-text-test-load.json");
+        font.save_png(&png_path).unwrap();
+        write_file(&json_path, &font.export_metrics());
+        let loaded = Font::load(&png_path, &json_path).unwrap();
+        assert_eq!(loaded.buffer, font.buffer);
+        assert_eq!(loaded.glyphs.len(), 2);
+        assert_eq!(loaded.glyphs[&'A'].rect(), font.glyphs[&'A'].rect());
+        // The PNG doesn't match the JSON size
+        font.width = 6;
+        font.pitch = 6;
+        font.glyphs.remove(&'A');
+        write_file(&json_path, &font.export_metrics());
+        match Font::load(&png_path, &json_path) {
+            Err(FontError::CorruptAtlas(_)) => {}
+            other => panic!("unexpected result {:?}", other.map(|font| font.glyphs)),
+        }
+        // A glyph out of the texture
+        font.glyphs.insert('A', Glyph { x: 4, y: 4, width: 4, height: 4, .. Default::default() });
+        match Font::import_metrics(&font.export_metrics()) {
+            Err(FontError::CorruptAtlas(_)) => {}
+            other => panic!("unexpected result {:?}", other.map(|font| font.glyphs)),
+        }
+        fs::remove_file(&png_path).unwrap();
+        fs::remove_file(&json_path).unwrap();
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_export_metrics() {
+        let mut font = filled_font();
+        font.glyphs.insert('\u{159}', Glyph { x: 4, y: 2, width: 3, height: 6, xmin: -1, ymin: -2,
+                                             advance_x: 2.5, .. Default::default() });
+        font.glyphs.insert('A', Glyph { x: 0, y: 4, width: 4, height: 4, xmin: 1, ymin: 0,
+                                        advance_x: 5.0, advance_y: -0.5, bearing_x: 0.75,
+                                        padding: 1, channel: 1, scale: 0.5,
+                                        .. Default::default() });
+        font.glyphs.insert('\u{1F600}', Glyph { x: 4, y: 0, width: 2, height: 2, image: true,
+                                                advance_x: 2.0, .. Default::default() });
+        font.index_glyphs.insert(42, Glyph { x: 0, y: 4, width: 4, height: 4, advance_x: 5.0,
+                                             channel: 1, .. Default::default() });
+        font.kerning_pairs.insert(('A', 'V'), Vec2::new(-1.25, 0.0));
+        font.kerning_pairs.insert(('T', 'o'), Vec2::new(-0.5, 0.25));
+        font.channels = 2;
+        font.pitch = 12;
+        font.padding = 1;
+        font.params.spread = Some(3.5);
+        font.params.em_spread = Some(0.125);
+        font.params.midpoint = 100;
+        font.params.srgb = true;
+        font.params.invert = true;
+        font.vertical = LineMetrics { ascent: 3.5, descent: 1.0, line_height: 5.25, baseline: 3.5 };
+        font.underline = Decoration { position: -1.5, thickness: 0.5 };
+        font.strikeout = Decoration { position: 2.25, thickness: 0.75 };
+        let json = font.export_metrics();
+        let imported = Font::import_metrics(&json).unwrap();
+        assert_eq!((imported.width, imported.height, imported.face_size), (8, 8, 4));
+        assert_eq!((imported.channels, imported.pitch, imported.padding), (2, 12, 1));
+        let params = &imported.params;
+        assert_eq!((params.spread, params.em_spread, params.midpoint), (Some(3.5), Some(0.125), 100));
+        assert_eq!((params.srgb, params.invert, params.stroke_width), (true, true, None));
+        assert_eq!(imported.vertical, font.vertical);
+        assert_eq!((imported.underline.position, imported.underline.thickness), (-1.5, 0.5));
+        assert_eq!((imported.strikeout.position, imported.strikeout.thickness), (2.25, 0.75));
+        assert_eq!(imported.kerning_pairs, font.kerning_pairs);
+        assert!(imported.buffer.is_empty());
+        assert_eq!(imported.glyphs.len(), 4);
+        let same = |a: &Glyph, b: &Glyph| {
+            assert_eq!((a.rect(), a.xmin, a.ymin, a.padding, a.channel),
+                       (b.rect(), b.xmin, b.ymin, b.padding, b.channel));
+            assert_eq!((a.advance_x, a.advance_y, a.bearing_x, a.image, a.scale),
+                       (b.advance_x, b.advance_y, b.bearing_x, b.image, b.scale));
+        };
+        for (ch, glyph) in &font.glyphs {
+            same(&imported.glyphs[ch], glyph);
+        }
+        assert_eq!(imported.index_glyphs.len(), 1);
+        same(&imported.index_glyphs[&42], &font.index_glyphs[&42]);
+        assert_eq!(imported.export_metrics(), json);
+        match Font::import_metrics("{\"width\": 8}") {
+            Err(FontError::CorruptAtlas(_)) => {}
+            other => panic!("unexpected result {:?}", other.map(|font| font.glyphs)),
+        }
+    }
+
+    #[test]
+    fn test_align_texture() {
+        let mut font = Font::new(6);
+        font.face_size = 128;
+        font.align_texture(false, 4);
+        assert_eq!((font.width, font.height, font.pitch), (6, 6, 8));
+        font.channels = 2;
+        font.align_texture(true, 4);
+        assert_eq!((font.width, font.height, font.pitch), (8, 8, 8));
+        font.channels = 1;
+        font.width = 9;
+        font.align_texture(false, 4);
+        assert_eq!(font.pitch, 12);
+        // The glyphs are rendered against the pitch
+        let config = FontConfig::default();
+        font.build_glyphs("a", 0, None, |_| (Glyph::from_outline(&square(), 1, &config), square())).unwrap();
+        assert_eq!(font.buffer.len(), 12 * 8);
+        assert_eq!(font.validate(), Ok(()));
+        let glyph = font.glyphs[&'a'].clone();
+        let pixels = glyph.render_standalone(&square(), 128, &SdfParams::default());
+        for yr in 0 .. glyph.height {
+            let row = (glyph.y + yr) * 12 + glyph.x;
+            assert_eq!(&font.buffer[row .. row + glyph.width],
+                       &pixels[yr * glyph.width .. (yr + 1) * glyph.width]);
+        }
+    }
+
+    // needs assets/FreeSans.ttf
+    #[test]
+    #[ignore]
+    fn test_build_with_images() {
+        let (face, _library) = test_face();
+        let mut font = Font::new(256);
+        font.channels = 4;
+        let mut requested = Vec::new();
+        font.build_from_face_with_images(&face, 32, 2, "A\u{1F600}", |ch| {
+            requested.push(ch);
+            Some((vec![200; 3 * 2 * 4], 3, 2))
+        }).unwrap();
+        // Only the char missing in the face is requested
+        assert_eq!(requested, vec!['\u{1F600}']);
+        assert!(!font.glyphs[&'A'].image);
+        let emoji = font.glyphs[&'\u{1F600}'].clone();
+        assert!(emoji.image);
+        assert_eq!((emoji.width, emoji.height, emoji.advance_x), (3, 2, 3.0));
+        let i = (emoji.y * font.pitch + emoji.x) * 4;
+        assert_eq!(&font.buffer[i .. i + 4], &[200; 4]);
+    }
+
+    #[test]
+    fn test_render_glyph_exact() {
+        let mut font = Font::new(8);
+        font.face_size = 2;
+        let config = FontConfig::default();
+        font.build_glyphs("a", 0, None, |_| (Glyph::from_outline(&square(), 1, &config), square())).unwrap();
+        assert!(font.render_glyph_exact('a', 20).is_none());
+        font.config.retain_outlines = true;
+        font.glyphs.clear();
+        font.build_glyphs("a", 0, None, |_| {
+            let mut glyph = Glyph::from_outline(&square(), 1, &config);
+            glyph.advance_x = 3.0;
+            (glyph, square())
+        }).unwrap();
+        // The square is the whole em
+        assert_eq!(font.outlines[&'a'].control_box(), (0.0, 0.0, 1.0, 1.0));
+        let (buffer, glyph) = font.render_glyph_exact('a', 20).unwrap();
+        assert_eq!((glyph.width, glyph.height, glyph.advance_x), (22, 22, 30.0));
+        assert_eq!(buffer.len(), 22 * 22);
+        assert!(buffer[11 * 22 + 11] == 255);
+        assert!(font.render_glyph_exact('b', 20).is_none());
+    }
+
+    // needs assets/FreeSans.ttf and assets/DejaVuSans.ttf
+    #[test]
+    #[ignore]
+    fn test_build_from_faces() {
+        // FreeSans has 1000 units per em, DejaVu Sans 2048
+        let (primary, _library) = test_face();
+        let (fallback, _library2) = test_face_from("assets/DejaVuSans.ttf");
+        assert!(primary.em_size() != fallback.em_size());
+        // A char from the fallback face
+        let missing = Font::missing_chars(&primary, "\u{2603}\u{2693}\u{0243}\u{048A}\u{1E9E}");
+        let ch = missing.into_iter().find(|&ch| fallback.get_char_index(ch as usize) != 0)
+            .expect("a char missing in FreeSans but present in DejaVu Sans");
+        let chars: String = vec!['H', ch].into_iter().collect();
+        let mut font = Font::new(256);
+        font.build_from_faces(&[&primary, &fallback], 32, 2, &chars).unwrap();
+        // Same as rendered with the fallback face alone
+        let mut reference = Font::new(256);
+        reference.build_from_face(&fallback, 32, 2, &chars).unwrap();
+        let (glyph, expected) = (&font.glyphs[&ch], &reference.glyphs[&ch]);
+        assert_eq!((glyph.width, glyph.height, glyph.xmin, glyph.ymin),
+                   (expected.width, expected.height, expected.xmin, expected.ymin));
+        assert_eq!(glyph.advance_x, expected.advance_x);
+        // Both faces have cap height about 0.73 em
+        let (h, h_fallback) = (font.glyphs[&'H'].height, reference.glyphs[&'H'].height);
+        assert!((h as isize - h_fallback as isize).abs() <= 1);
+        assert_eq!(font.underline.position, Decoration::underline_from_face(&primary, 32).position);
+    }
+
+    #[test]
+    fn test_glyph_info() {
+        let mut font = filled_font();
+        font.pitch = 12;
+        font.glyphs.insert('y', Glyph { x: 4, y: 2, width: 2, height: 6, xmin: -1, ymin: -2,
+                                        advance_x: 3.5, .. Default::default() });
+        let info = font.glyph_info('y').unwrap();
+        assert_eq!(info, GlyphInfo { page: 0, channel: 0, uv_rect: (0.5, 0.25, 0.75, 1.0),
+                                     placement: (-1.0, -2.0, 2.0, 6.0), advance: 3.5 });
+        assert!(font.glyph_info('z').is_none());
+    }
+
+    #[test]
+    fn test_glyph_quad() {
+        let mut font = filled_font();
+        font.face_size = 8;
+        font.glyphs.insert('y', Glyph { x: 4, y: 2, width: 2, height: 6, xmin: -1, ymin: -2,
+                                        advance_x: 3.5, .. Default::default() });
+        let quad = font.glyph_quad('y', Vec2::new(1.0, 0.5)).unwrap();
+        let glyph = &font.glyphs[&'y'];
+        let (w, h) = (font.width as f32, font.height as f32);
+        let (u0, v0) = (glyph.x as f32 / w, glyph.y as f32 / h);
+        let (u1, v1) = ((glyph.x + glyph.width) as f32 / w, (glyph.y + glyph.height) as f32 / h);
+        assert_eq!(quad.uvs, [Vec2::new(u0, v1), Vec2::new(u1, v1), Vec2::new(u0, v0), Vec2::new(u1, v0)]);
+        assert_eq!(quad.rect, (1.0 - 0.125, 0.5 - 0.25, 1.0 + 0.125, 0.5 + 0.5));
+        assert_eq!(quad.advance, 3.5 / 8.0);
+        assert!(font.glyph_quad('z', Vec2::new(0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut font = filled_font();
+        assert_eq!(font.validate(), Ok(()));
+        font.glyphs.insert('y', Glyph { x: 4, width: 4, height: 4, .. Default::default() });
+        assert_eq!(font.validate(), Ok(()));
+        // Overlapping glyphs
+        font.glyphs.get_mut(&'y').unwrap().x = 3;
+        match font.validate() {
+            Err(FontError::CorruptAtlas(details)) => assert!(details.contains("overlap")),
+            res => panic!("unexpected {:?}", res),
+        }
+        // Out of bounds
+        font.glyphs.get_mut(&'y').unwrap().x = 5;
+        assert!(font.validate().is_err());
+        font.glyphs.get_mut(&'y').unwrap().x = 4;
+        // Buffer doesn't match the dimensions
+        font.buffer.truncate(60);
+        assert!(font.validate().is_err());
+    }
+
+    #[test]
+    fn test_repack() {
+        let mut font = Font::new(8);
+        font.face_size = 128;
+        let config = FontConfig::default();
+        // Triangle in the same box as the square, to tell the glyphs apart
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(0.0, 0.0));
+        builder.line_to(Vec2::new(0.0, 2.0));
+        builder.line_to(Vec2::new(2.0, 0.0));
+        let triangle = builder.finish();
+        font.build_glyphs("abcd", 0, None, |ch| {
+            let outline = if ch == 'd' { triangle.clone() } else { square() };
+            (Glyph::from_outline(&outline, 1, &config), outline)
+        }).unwrap();
+        assert_eq!(font.take_dirty(), Some(Rect::new(0, 0, 8, 8)));
+        assert_eq!(font.take_dirty(), None);
+        let tile = |font: &Font, g: &Glyph| -> Vec<u8> {
+            (0 .. 16).map(|i| font.buffer[(g.y + i / 4) * 8 + g.x + i % 4]).collect()
+        };
+        let pixels = tile(&font, &font.glyphs[&'d']);
+        assert!(pixels != tile(&font, &font.glyphs[&'a']));
+        // Remove all but 'd', it's moved into the corner
+        font.glyphs.retain(|&ch, _| ch == 'd');
+        font.repack().unwrap();
+        let d = font.glyphs[&'d'].clone();
+        assert_eq!((d.x, d.y), (0, 0));
+        assert_eq!(tile(&font, &d), pixels);
+        assert_eq!(font.take_dirty(), Some(Rect::new(0, 0, 8, 8)));
+    }
+
+    #[test]
+    fn test_debug_ascii() {
+        let mut font = filled_font();
+        font.buffer[8 + 1] = 0;
+        font.buffer[8 + 2] = 127;
+        let glyph = Glyph { x: 1, y: 1, width: 3, height: 2, .. Default::default() };
+        assert_eq!(glyph.debug_ascii(&font.buffer, font.width), " =@\n@@@\n");
+    }
+
+    #[test]
+    fn test_sdf_coverage_channels() {
+        // Rectangle 2.5 x 2.25 px, starting at quarter pixels
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(0.25, 0.25));
+        builder.line_to(Vec2::new(0.25, 2.5));
+        builder.line_to(Vec2::new(2.75, 2.5));
+        builder.line_to(Vec2::new(2.75, 0.25));
+        let outline = builder.finish();
+        let glyph = Glyph::from_outline(&outline, 1, &FontConfig::default());
+        assert_eq!((glyph.xmin, glyph.ymin, glyph.width, glyph.height), (-1, -1, 5, 5));
+        let mut buffer = vec![0u8; glyph.width * glyph.height * 2];
+        glyph.render_outline_sdf_coverage(&outline, 128, &SdfParams::default(), &mut buffer, glyph.width);
+        // Reference: area of the rectangle within each pixel, rows from top
+        let overlap = |a0: f32, a1: f32, b0: f32, b1: f32| (a1.min(b1) - a0.max(b0)).max(0.0);
+        for yr in 0 .. 5 {
+            for xr in 0 .. 5 {
+                let (px, py) = (xr as f32 - 1.0, 3.0 - yr as f32);
+                let area = overlap(px, px + 1.0, 0.25, 2.75) * overlap(py, py + 1.0, 0.25, 2.5);
+                let coverage = buffer[(yr * 5 + xr) * 2 + 1] as f32 / 255.0;
+                assert!((coverage - area).abs() < 0.01, "{} {}: {} vs {}", xr, yr, coverage, area);
+            }
+        }
+        // The SDF channel is the same as rendered alone
+        let sdf = glyph.render_standalone(&outline, 128, &SdfParams::default());
+        assert!((0 .. 25).all(|i| buffer[i * 2] == sdf[i]));
+    }
+
+    #[test]
+    fn test_srgb() {
+        let linear = SdfParams::default();
+        let srgb = SdfParams { srgb: true, .. SdfParams::default() };
+        for &dist in [-5.0, -0.5, 0.0, 0.3, 2.0, 7.5].iter() {
+            let raw = linear.encode(dist, 128) as f32;
+            let stored = srgb.encode(dist, 128) as f32;
+            // Sampled through sRGB texture, the pre-distorted value is the linear one
+            let sampled = srgb_to_linear(stored / 255.) * 255.;
+            assert!((sampled - raw).abs() < 1.5, "{}: {} vs {}", dist, sampled, raw);
+            assert!((srgb.decode(stored, 128) - linear.decode(raw, 128)).abs() < 0.1);
+        }
+        // Without the pre-distortion, sRGB sampling moves the outline
+        let outline = linear.encode(0.0, 128) as f32;
+        let sampled = srgb_to_linear(outline / 255.) * 255.;
+        assert!((sampled - outline).abs() > 50.0);
+        assert!(linear.decode(sampled, 128) > 4.0);
+    }
+
+    #[test]
+    fn test_recommend_spread() {
+        let spread = recommend_spread(64, 16.0, 64.0);
+        // Not saturated within a pixel and a texel of the outline at the smallest size
+        assert!(spread > 1.0 / 16.0 + 1.0 / 64.0);
+        // Enough value steps per pixel at the largest size
+        assert!(spread / 127.0 < 1.0 / (4.0 * 64.0));
+        // Larger range of sizes narrows the choice, until the lower bound wins
+        assert!(recommend_spread(64, 8.0, 64.0) > spread);
+        assert!(recommend_spread(64, 16.0, 128.0) < spread);
+        assert_eq!(recommend_spread(32, 4.0, 400.0), 1.0 / 4.0 + 1.0 / 32.0);
+    }
+
+    #[test]
+    fn test_spread() {
+        let glyph = Glyph::from_outline(&ring(false), 4, &FontConfig::default());
+        // Values along the middle row, going from the left border inwards
+        let row = |params: &SdfParams| -> Vec<i32> {
+            let buffer = glyph.render_standalone(&ring(false), 32, params);
+            let y = glyph.height / 2 * glyph.width;
+            buffer[y + 1 .. y + 6].iter().map(|&v| v as i32).collect()
+        };
+        let narrow = row(&SdfParams { spread: Some(2.0), .. SdfParams::default() });
+        let wide = row(&SdfParams { spread: Some(4.0), .. SdfParams::default() });
+        // The outline is at x = 0, between the 3rd and 4th texel (centers at -0.5, 0.5)
+        let slope = |values: &[i32]| values[3] - values[1];
+        assert!((slope(&narrow) - 127).abs() <= 2);
+        assert!((slope(&wide) - 64).abs() <= 2);
+        assert!(narrow[0] == 0 && wide[0] > 0);
+        // The outline value follows the midpoint
+        let params = SdfParams { spread: Some(2.0), midpoint: 63, .. SdfParams::default() };
+        assert_eq!(params.encode(0.0, 32), 63);
+        assert_eq!(params.encode(1.0, 32), 31);
+        assert_eq!(params.decode(94.5, 32), -1.0);
+        assert!((params.saturation_distance(32) - 192.0 / 31.5).abs() < 1e-4);
+    }
+
+    #[test]
+    #[should_panic(expected = "midpoint 0 out of range")]
+    fn test_zero_midpoint() {
+        let params = SdfParams { spread: Some(2.0), midpoint: 0, .. SdfParams::default() };
+        params.encode(1.0, 32);
+    }
+
+    #[test]
+    fn test_em_spread() {
+        let mut params = SdfParams::default();
+        params.em_spread = Some(0.125);
+        // One spread (in em) is the whole outside range, at any face size
+        for &face_size in [32, 64, 100].iter() {
+            let spread_px = 0.125 * face_size as f32;
+            assert_eq!(params.encode(0.0, face_size), 127);
+            assert_eq!(params.encode(spread_px, face_size), 0);
+            assert_eq!(params.encode(-spread_px / 2.0, face_size), 190);
+            let distance = params.decode_em(190.0, face_size);
+            assert!((distance + 0.0625).abs() < 1e-3);
+        }
+    }
+
+    // Vertical hairline, 0.2 texels wide, lying between the pixel centers
+    fn hairline() -> (Rasterizer, OutlineDistance) {
+        let points = [Vec2::new(2.1, 0.0), Vec2::new(2.1, 4.0),
+                      Vec2::new(2.3, 4.0), Vec2::new(2.3, 0.0)];
+        let mut rasterizer = Rasterizer::new();
+        let mut mindist = OutlineDistance::new();
+        for i in 0 .. points.len() {
+            let (p0, p1) = (points[i], points[(i + 1) % points.len()]);
+            rasterizer.push_line(p0, p1);
+            mindist.push_line(p0, p1);
+        }
+        (rasterizer, mindist)
+    }
+
+    // Maximum SDF value of vertical stem (`width` px) rendered at `face_size`
+    fn stem_max_value(x: f32, width: f32, face_size: usize) -> u8 {
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(x, 0.0));
+        builder.line_to(Vec2::new(x, 4.0));
+        builder.line_to(Vec2::new(x + width, 4.0));
+        builder.line_to(Vec2::new(x + width, 0.0));
+        let outline = builder.finish();
+        let glyph = Glyph::from_outline(&outline, 1, &FontConfig::default());
+        let buffer = glyph.render_standalone(&outline, face_size, &SdfParams::default());
+        buffer.into_iter().max().unwrap()
+    }
+
+    #[test]
+    fn test_min_face_size() {
+        let stroke_em = 0.08;
+        let face_size = min_face_size(stroke_em);
+        assert_eq!(face_size, 25);
+        let params = SdfParams::default();
+        let half_texel_inside = params.encode(-0.5, face_size);
+        for i in 0 .. 10 {
+            let x = i as f32 / 10.0;
+            assert!(stem_max_value(x, stroke_em * face_size as f32, face_size) >= half_texel_inside);
+        }
+        // Below the minimum, the stem drops out at some positions
+        let face_size = 12;
+        assert!((0 .. 10).any(|i| {
+            stem_max_value(i as f32 / 10.0 + 0.02, stroke_em * face_size as f32, face_size) < 127
+        }));
+    }
+
+    #[test]
+    fn test_preserve_thin_strokes() {
+        let (rasterizer, mindist) = hairline();
+        let glyph = Glyph { width: 5, height: 4, .. Default::default() };
+        let mut buffer = vec![0u8; 5 * 4];
+        let mut params = SdfParams::default();
+        glyph.render_sdf_from(&rasterizer, &mindist, 128, &params, &mut buffer, 5);
+        // No pixel reaches the outline level, the stroke drops out
+        assert!(buffer.iter().all(|&v| v < 127));
+
+        params.preserve_thin_strokes = true;
+        glyph.render_sdf_from(&rasterizer, &mindist, 128, &params, &mut buffer, 5);
+        for yr in 0 .. 4 {
+            // The pixel next to the stroke is on the outline
+            assert_eq!(buffer[yr * 5 + 2], 127);
+            // Farther pixels are unchanged
+            assert!(buffer[yr * 5 + 1] < 127);
         }
     }
 }
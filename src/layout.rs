@@ -0,0 +1,387 @@
+use std::f32;
+
+use font::*;
+
+// Is `ch` a combining mark (from the main combining blocks)?
+pub fn is_combining_mark(ch: char) -> bool {
+    match ch as u32 {
+        0x0300 ... 0x036F |     // Combining Diacritical Marks
+        0x1AB0 ... 0x1AFF |     // ... Extended
+        0x1DC0 ... 0x1DFF |     // ... Supplement
+        0x20D0 ... 0x20FF |     // ... for Symbols
+        0xFE20 ... 0xFE2F => true,  // Combining Half Marks
+        _ => false,
+    }
+}
+
+/// Glyph placed by the layout
+///
+/// Coordinates are in target pixels, X axis going right, Y axis going down.
+/// The position is the pen position (glyph origin on the baseline).
+
+#[derive(Copy, Clone, Debug)]
+pub struct PositionedGlyph {
+    pub ch: char,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Single line of text laid out at `target_px` size
+///
+/// The pen starts at (0, 0) and advances along the baseline.
+/// With `FontConfig::snap_to_pixel`, the glyph origins are rounded
+/// to whole pixels (the advance is still accumulated exactly).
+/// The bounds cover all glyph quads (including the SDF padding),
+/// relative to the same origin.
+///
+/// Combining marks (see `is_combining_mark`) don't advance the pen,
+/// they're centered over the preceding glyph's advance (no anchors from GPOS).
+/// Kerning (see `Font::kerning`) is applied between consecutive base glyphs.
+
+#[derive(Clone, Debug)]
+pub struct Layout {
+    pub glyphs: Vec<PositionedGlyph>,
+    // ratio between target pixels and font texture pixels
+    pub scale: f32,
+    // final pen position
+    pub advance: f32,
+    // bounding box of the glyph quads
+    pub xmin: f32,
+    pub ymin: f32,
+    pub xmax: f32,
+    pub ymax: f32,
+    // caret position before each char of the text (including the skipped ones),
+    // the last one is the end of the line; false inside a base + mark cluster
+    carets: Vec<(f32, bool)>,
+}
+
+/// Size of text measured without rendering (see `Font::measure_only`)
+///
+/// In target pixels. Lines are separated by '\n'.
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LayoutMetrics {
+    pub width: f32,
+    pub height: f32,
+    pub line_widths: Vec<f32>,
+}
+
+impl Layout {
+    pub fn new(font: &Font, text: &str, target_px: f32) -> Self {
+        let scale = target_px / font.face_size as f32;
+        let mut layout = Layout {
+            glyphs: Vec::with_capacity(text.len()),
+            scale: scale,
+            advance: 0.0,
+            xmin: f32::INFINITY,
+            ymin: f32::INFINITY,
+            xmax: f32::NEG_INFINITY,
+            ymax: f32::NEG_INFINITY,
+            carets: Vec::with_capacity(text.len() + 1),
+        };
+        let snap = |x: f32| if font.config.snap_to_pixel { x.round() } else { x };
+        let mut pen_x = 0.0;
+        // pen position and advance of the last base glyph
+        let mut base: Option<(f32, f32)> = None;
+        // the last base char, for kerning
+        let mut prev: Option<char> = None;
+        for ch in text.chars() {
+            // Characters missing in the font are skipped
+            let glyph = match font.glyphs.get(&ch) {
+                Some(glyph) => glyph,
+                None => {
+                    warn!("char {:?} not in font, skipped in layout", ch);
+                    layout.carets.push((snap(pen_x), true));
+                    prev = None;
+                    continue;
+                }
+            };
+            let mark = is_combining_mark(ch) && base.is_some();
+            if !mark {
+                if let Some(prev) = prev {
+                    pen_x += font.kerning(prev, ch).x * scale;
+                }
+            }
+            let x = match base {
+                Some((base_x, base_advance)) if mark => {
+                    // Center of the mark's tile over the middle of the base
+                    let center = (glyph.xmin as f32 + glyph.width as f32 / 2.) / glyph.scale;
+                    base_x + base_advance / 2. - center * scale
+                }
+                _ => pen_x,
+            };
+            let x = snap(x);
+            // The caret before a mark is after its base (set below)
+            layout.carets.push((x, !mark));
+            let pos = PositionedGlyph { ch: ch, x: x, y: 0.0 };
+            let (x0, y0, x1, y1) = layout.glyph_quad(&pos, glyph);
+            layout.xmin = layout.xmin.min(x0);
+            layout.ymin = layout.ymin.min(y0);
+            layout.xmax = layout.xmax.max(x1);
+            layout.ymax = layout.ymax.max(y1);
+            layout.glyphs.push(pos);
+            if !mark {
+                base = Some((x, glyph.advance_x * scale));
+                pen_x += glyph.advance_x * scale;
+                prev = Some(ch);
+            }
+        }
+        layout.advance = pen_x;
+        layout.carets.push((pen_x, true));
+        // A mark shares the caret after its cluster
+        for i in (0 .. layout.carets.len() - 1).rev() {
+            if !layout.carets[i].1 {
+                layout.carets[i].0 = layout.carets[i + 1].0;
+            }
+        }
+        if layout.glyphs.is_empty() {
+            layout.xmin = 0.0;
+            layout.ymin = 0.0;
+            layout.xmax = 0.0;
+            layout.ymax = 0.0;
+        }
+        layout
+    }
+
+    // Quad covering the glyph's texture tile: (left, top, right, bottom)
+    pub fn glyph_quad(&self, pos: &PositionedGlyph, glyph: &Glyph) -> (f32, f32, f32, f32) {
+        // Target pixels per texel of the glyph (see `Glyph::scale`)
+        let scale = self.scale / glyph.scale;
+        let left = pos.x + glyph.xmin as f32 * scale;
+        let bottom = pos.y - glyph.ymin as f32 * scale;
+        let right = left + glyph.width as f32 * scale;
+        let top = bottom - glyph.height as f32 * scale;
+        (left, top, right, bottom)
+    }
+
+    // Quad of a line decoration (underline, strikeout) along the whole line:
+    // (left, top, right, bottom)
+    pub fn decoration_quad(&self, decoration: &Decoration) -> (f32, f32, f32, f32) {
+        let center = -decoration.position * self.scale;
+        let half = decoration.thickness * self.scale / 2.;
+        (0.0, center - half, self.advance, center + half)
+    }
+
+    // Caret position before char at `index` (index of the char in the text,
+    // chars skipped by the layout are at the current pen position),
+    // the number of chars is the end of the line. The caret before a combining
+    // mark is the one after its base + mark cluster.
+    pub fn caret_x(&self, index: usize) -> f32 {
+        match self.carets.get(index) {
+            Some(&(x, _)) => x,
+            None => self.advance,
+        }
+    }
+
+    // Caret index (char index) nearest to `x` (e.g. position of mouse click),
+    // 0 ..= number of chars, never inside a base + mark cluster
+    pub fn index_at_x(&self, x: f32) -> usize {
+        let mut prev: Option<(usize, f32)> = None;
+        for (i, &(caret, boundary)) in self.carets.iter().enumerate() {
+            if !boundary {
+                continue;
+            }
+            if let Some((prev_index, prev_caret)) = prev {
+                if x < (prev_caret + caret) / 2. {
+                    return prev_index;
+                }
+            }
+            prev = Some((i, caret));
+        }
+        self.carets.len() - 1
+    }
+
+    pub fn width(&self) -> f32 {
+        self.xmax - self.xmin
+    }
+
+    pub fn height(&self) -> f32 {
+        self.ymax - self.ymin
+    }
+}
+
+
+// Tests
+// -----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve::Vec2;
+
+    // Font with single glyph 'x' (4x4 tile, no texture data)
+    fn test_font() -> Font {
+        let mut font = Font::new(8);
+        font.face_size = 4;
+        font.glyphs.insert('x', Glyph { width: 4, height: 4, advance_x: 4.0, .. Default::default() });
+        font
+    }
+
+    #[test]
+    fn test_decoration_quad() {
+        let font = test_font();
+        let layout = Layout::new(&font, "xx", 8.0);
+        assert_eq!(layout.advance, 16.0);
+        let underline = Decoration { position: -1.0, thickness: 0.5 };
+        assert_eq!(layout.decoration_quad(&underline), (0.0, 1.5, 16.0, 2.5));
+    }
+
+    #[test]
+    fn test_glyph_scale() {
+        let mut font = test_font();
+        // Same glyph as 'x', stored at half resolution
+        font.glyphs.insert('y', Glyph { width: 2, height: 2, advance_x: 4.0, scale: 0.5,
+                                        .. Default::default() });
+        let layout = Layout::new(&font, "xy", 8.0);
+        let (x, y) = (&font.glyphs[&'x'], &font.glyphs[&'y']);
+        let (l0, t0, r0, b0) = layout.glyph_quad(&layout.glyphs[0], x);
+        let (l1, t1, r1, b1) = layout.glyph_quad(&layout.glyphs[1], y);
+        assert_eq!((r0 - l0, b0 - t0), (8.0, 8.0));
+        assert_eq!((l1, t1, r1, b1), (l0 + 8.0, t0, r0 + 8.0, b0));
+        assert_eq!(font.glyph_info('y').unwrap().placement, (0.0, 0.0, 4.0, 4.0));
+    }
+
+    #[test]
+    fn test_caret() {
+        let font = test_font();
+        // Glyph advance is 8 pixels, the unknown char is skipped,
+        // but it still has its caret position (at the pen)
+        let layout = Layout::new(&font, "x?xx", 8.0);
+        let carets: Vec<f32> = (0 .. 5).map(|i| layout.caret_x(i)).collect();
+        assert_eq!(carets, vec![0.0, 8.0, 8.0, 16.0, 24.0]);
+        assert_eq!(layout.index_at_x(-5.0), 0);
+        assert_eq!(layout.index_at_x(3.9), 0);
+        assert_eq!(layout.index_at_x(4.1), 1);
+        assert_eq!(layout.index_at_x(13.0), 3);
+        assert_eq!(layout.index_at_x(21.0), 4);
+        assert_eq!(layout.index_at_x(100.0), 4);
+        // Kerning moves the carets
+        let mut font = test_font();
+        font.kerning_pairs.insert(('x', 'x'), Vec2::new(-1.0, 0.0));
+        let layout = Layout::new(&font, "xxx", 8.0);
+        let carets: Vec<f32> = (0 .. 4).map(|i| layout.caret_x(i)).collect();
+        assert_eq!(carets, vec![0.0, 6.0, 12.0, 20.0]);
+        assert_eq!(layout.index_at_x(9.5), 2);
+        let empty = Layout::new(&font, "", 8.0);
+        assert_eq!((empty.caret_x(0), empty.index_at_x(10.0)), (0.0, 0));
+    }
+
+    #[test]
+    fn test_kerning() {
+        let mut font = test_font();
+        font.glyphs.insert('y', font.glyphs[&'x'].clone());
+        font.kerning_pairs.insert(('x', 'y'), Vec2::new(-1.0, 0.0));
+        // Kerning is in font texture pixels, scaled by 2
+        let layout = Layout::new(&font, "xyx", 8.0);
+        let xs: Vec<f32> = layout.glyphs.iter().map(|pos| pos.x).collect();
+        assert_eq!(xs, vec![0.0, 6.0, 14.0]);
+        assert_eq!((layout.advance, layout.xmax), (22.0, 22.0));
+        // Not applied over a skipped char
+        let layout = Layout::new(&font, "x?y", 8.0);
+        assert_eq!(layout.glyphs[1].x, 8.0);
+    }
+
+    #[test]
+    fn test_combining_mark() {
+        let mut font = test_font();
+        // Zero-width mark drawn left of its origin, like in many fonts
+        font.glyphs.insert('\u{301}', Glyph { width: 2, height: 2, xmin: -3, ymin: 3,
+                                              .. Default::default() });
+        let layout = Layout::new(&font, "x\u{301}x", 8.0);
+        assert_eq!(layout.advance, 16.0);
+        let (left, _, right, _) = layout.glyph_quad(&layout.glyphs[1], &font.glyphs[&'\u{301}']);
+        // Centered over the first glyph's advance (0 .. 8)
+        assert_eq!((left, right), (2.0, 6.0));
+        assert_eq!(layout.glyphs[2].x, 8.0);
+        // The mark is in the cluster of its base
+        assert_eq!((layout.caret_x(1), layout.caret_x(2), layout.caret_x(3)), (8.0, 8.0, 16.0));
+        for &(x, index) in [(1.0, 0), (3.9, 0), (4.1, 2), (7.0, 2), (11.0, 2), (13.0, 3)].iter() {
+            assert_eq!(layout.index_at_x(x), index);
+        }
+        // Without a base, the mark is placed as a normal glyph
+        let layout = Layout::new(&font, "\u{301}x", 8.0);
+        assert_eq!(layout.glyphs[0].x, 0.0);
+        assert!(is_combining_mark('\u{301}') && !is_combining_mark('e'));
+    }
+
+    #[test]
+    fn test_snap_to_pixel() {
+        let mut font = test_font();
+        font.glyphs.get_mut(&'x').unwrap().advance_x = 4.3;
+        // Glyphs with different offsets from the baseline, one at half resolution
+        font.glyphs.insert('g', Glyph { width: 4, height: 7, xmin: 1, ymin: -3, advance_x: 4.3,
+                                        .. Default::default() });
+        font.glyphs.insert('h', Glyph { width: 2, height: 3, xmin: -1, ymin: 1, advance_x: 4.3,
+                                        scale: 0.5, .. Default::default() });
+        let text = "xghxghxg";
+        // Origin and baseline of each glyph, recovered from its rendered quad
+        // (at non-integer scale 1.5)
+        let origins = |font: &Font| -> Vec<(f32, f32)> {
+            let layout = Layout::new(font, text, 6.0);
+            layout.glyphs.iter().map(|pos| {
+                let glyph = &font.glyphs[&pos.ch];
+                let (left, _, _, bottom) = layout.glyph_quad(pos, glyph);
+                let texel = layout.scale / glyph.scale;
+                (left - glyph.xmin as f32 * texel, bottom + glyph.ymin as f32 * texel)
+            }).collect()
+        };
+        let unsnapped = origins(&font);
+        assert!(unsnapped.iter().any(|&(x, _)| x.fract() != 0.0));
+        font.config.snap_to_pixel = true;
+        let snapped = origins(&font);
+        assert_eq!(snapped.len(), text.len());
+        // Zero variance of the sub-pixel phase and the baseline across the glyphs
+        for (&(x, baseline), &(exact_x, _)) in snapped.iter().zip(unsnapped.iter()) {
+            assert!(x.fract().abs() < 1e-4, "origin {}", x);
+            assert!((x - exact_x).abs() <= 0.5);
+            assert!(baseline.abs() < 1e-4, "baseline {}", baseline);
+        }
+        // The advance is not rounded
+        let layout = Layout::new(&font, text, 6.0);
+        assert!((layout.advance - 8.0 * 4.3 * 1.5).abs() < 1e-4);
+    }
+
+    #[cfg(feature = "log")]
+    mod capture {
+        use std::cell::RefCell;
+        use log::{self, Log, Level, Metadata, Record};
+
+        thread_local!(static RECORDS: RefCell<Vec<(Level, String)>> = RefCell::new(Vec::new()));
+
+        // Logger keeping the records of current thread (test)
+        struct Capture;
+
+        impl Log for Capture {
+            fn enabled(&self, _: &Metadata) -> bool { true }
+            fn log(&self, record: &Record) {
+                RECORDS.with(|r| r.borrow_mut().push((record.level(), format!("{}", record.args()))));
+            }
+            fn flush(&self) {}
+        }
+
+        static CAPTURE: Capture = Capture;
+
+        pub fn start() {
+            let _ = log::set_logger(&CAPTURE);
+            log::set_max_level(log::LevelFilter::Trace);
+            RECORDS.with(|r| r.borrow_mut().clear());
+        }
+
+        pub fn records() -> Vec<(Level, String)> {
+            RECORDS.with(|r| r.borrow().clone())
+        }
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn test_missing_glyph_warning() {
+        let font = test_font();
+        capture::start();
+        let layout = Layout::new(&font, "xyx", 4.0);
+        assert_eq!(layout.glyphs.len(), 2);
+        let records = capture::records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].0, ::log::Level::Warn);
+        assert!(records[0].1.contains("'y'"));
+    }
+}
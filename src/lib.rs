@@ -1,13 +1,66 @@
 extern crate freetype;
 extern crate rect_packer;
 extern crate roots;
+#[cfg(feature = "log")]
+#[macro_use]
+extern crate log;
+#[cfg(feature = "image")]
+extern crate image;
+#[cfg(feature = "png")]
+extern crate png;
+#[cfg(feature = "json")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "json")]
+extern crate serde_json;
+#[cfg(feature = "simd")]
+extern crate simd;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+
+// Diagnostics are routed through `log` crate when the "log" feature is enabled,
+// otherwise they're compiled out (the arguments are still type-checked)
+#[cfg(not(feature = "log"))]
+macro_rules! warn {
+    ($($arg:tt)*) => { if false { let _ = format!($($arg)*); } }
+}
+#[cfg(not(feature = "log"))]
+macro_rules! error {
+    ($($arg:tt)*) => { if false { let _ = format!($($arg)*); } }
+}
 
 mod curve;
 mod rasterizer;
 mod mindist;
 mod font;
+mod layout;
+mod rect;
+mod outline;
+mod error;
+mod cache;
+mod registry;
+mod msdf;
+
+// Public API. The profiles and solvers used inside the rasterizer
+// and distance computation are not exported.
+pub use curve::{root_fallback_count, Vec2, LinearSegment, QuadraticSegment, CubicSegment};
+pub use rasterizer::{OrientedCrossing, FillRule, ScanlineInterval, Rasterizer};
+pub use mindist::{NearestPoint, OutlineDistance};
+pub use font::{vec2_from_ft, outline_from_ft, sdf_coverage, sdf_to_monochrome, invert_sdf,
+               min_face_size, chars_from_corpus, select_charmap, set_variations,
+               render_glyph_sdf, render_contours_sdf, recommend_spread, srgb_to_linear,
+               linear_to_srgb, ASCII_PRINTABLE, AlphaMode, Decoration, LineMetrics, Charmap,
+               BoxRounding, FontConfig, SdfParams, Glyph, GlyphInfo, GlyphQuad, Font};
+pub use layout::{is_combining_mark, PositionedGlyph, Layout, LayoutMetrics};
+pub use rect::Rect;
+pub use outline::{PathSegment, Transform, Outline, OutlineBuilder};
+pub use error::FontError;
+pub use cache::{FaceId, SdfCacheKey, CachedGlyph, SdfCache};
+pub use registry::{FontId, FontRegistry, FontArray};
+pub use msdf::{median, MSDF_ANGLE_THRESHOLD, EdgeColor, MsdfGenerator};
 
-pub use curve::*;
-pub use rasterizer::*;
-pub use mindist::*;
-pub use font::*;
+/// Commonly used types: `use sdf_text::prelude::*;`
+pub mod prelude {
+    pub use {Font, FontConfig, SdfParams, Glyph, BoxRounding, FontError};
+    pub use {Layout, Outline, OutlineBuilder, Transform, Vec2};
+}
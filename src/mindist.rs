@@ -1,11 +1,74 @@
 use curve::*;
 use std::f32;
 
+/// Nearest point on the outline, as seen from a query point
+
+#[derive(Copy, Clone, Debug)]
+pub struct NearestPoint {
+    pub distance: f32,
+    pub point: Vec2,
+    // tangent of the winning segment at `point` (not normalized)
+    pub tangent: Vec2,
+    // side of the segment where the query point lies:
+    // 1 = right (segment direction rotated clockwise), -1 = left
+    pub orientation: i8,
+}
+
+impl NearestPoint {
+    pub fn new(distance: f32, point: Vec2, tangent: Vec2, p: Vec2) -> Self {
+        // Determinant of (p - point, tangent), same as in distance.py
+        let m = p - point;
+        let side = m.cross(tangent);
+        NearestPoint {
+            distance: distance,
+            point: point,
+            tangent: tangent,
+            orientation: if side < 0.0 { -1 } else { 1 },
+        }
+    }
+
+    // Distance signed by the orientation
+    pub fn signed_distance(&self) -> f32 {
+        self.distance * self.orientation as f32
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct OutlineDistance {
     pub linear_segments: Vec<LinearSegment>,
     pub quadratic_segments: Vec<QuadraticSegment>,
     pub cubic_segments: Vec<CubicSegment>,
+    // spatial index built by `finalize` (None = brute force)
+    grid: Option<SegmentGrid>,
+}
+
+// Reference to a segment in one of the vectors of OutlineDistance
+#[derive(Copy, Clone, Debug)]
+enum SegmentRef {
+    Linear(usize),
+    Quadratic(usize),
+    Cubic(usize),
+}
+
+// Uniform grid of square cells over the control boxes of the segments.
+// Each cell lists the segments whose control box overlaps it.
+#[derive(Clone, Debug)]
+struct SegmentGrid {
+    origin: Vec2,
+    cell_size: f32,
+    cols: usize,
+    rows: usize,
+    cells: Vec<Vec<SegmentRef>>,
+}
+
+impl SegmentGrid {
+    // Cell containing `p` (clamped to the grid)
+    fn cell(&self, p: Vec2) -> (usize, usize) {
+        let x = ((p.x - self.origin.x) / self.cell_size).floor();
+        let y = ((p.y - self.origin.y) / self.cell_size).floor();
+        (x.max(0.0).min((self.cols - 1) as f32) as usize,
+         y.max(0.0).min((self.rows - 1) as f32) as usize)
+    }
 }
 
 impl OutlineDistance {
@@ -14,22 +77,104 @@ impl OutlineDistance {
             linear_segments: Vec::new(),
             quadratic_segments: Vec::new(),
             cubic_segments: Vec::new(),
+            grid: None,
         }
     }
 
+    // Pushing a segment drops the index (call `finalize` again)
     pub fn push_line(&mut self, p0: Vec2, p1: Vec2) {
         self.linear_segments.push(LinearSegment::new(p0, p1));
+        self.grid = None;
     }
 
     pub fn push_bezier2(&mut self, p0: Vec2, p1: Vec2, p2: Vec2) {
         self.quadratic_segments.push(QuadraticSegment::new(p0, p1, p2));
+        self.grid = None;
     }
 
     pub fn push_bezier3(&mut self, p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2) {
         self.cubic_segments.push(CubicSegment::new(p0, p1, p2, p3));
+        self.grid = None;
+    }
+
+    // Build spatial index of the segments, after all `push_*` calls.
+    // Then `distance` and `distance_within` evaluate only the segments
+    // near the query point (the results are the same as without the index).
+    pub fn finalize(&mut self) {
+        let refs = self.segment_refs();
+        if refs.is_empty() {
+            self.grid = None;
+            return;
+        }
+        let boxes: Vec<(f32, f32, f32, f32)> = refs.iter().map(|&sgt| {
+            let (points, count) = self.control_points(sgt);
+            control_box(&points[.. count])
+        }).collect();
+        let xmin = boxes.iter().fold(f32::INFINITY, |m, b| m.min(b.0));
+        let ymin = boxes.iter().fold(f32::INFINITY, |m, b| m.min(b.1));
+        let xmax = boxes.iter().fold(f32::NEG_INFINITY, |m, b| m.max(b.2));
+        let ymax = boxes.iter().fold(f32::NEG_INFINITY, |m, b| m.max(b.3));
+        // About sqrt(n) x sqrt(n) cells for n segments
+        let extent = (xmax - xmin).max(ymax - ymin);
+        let side = (refs.len() as f32).sqrt().ceil();
+        let cell_size = if extent > 0.0 { extent / side } else { 1.0 };
+        let mut grid = SegmentGrid {
+            origin: Vec2::new(xmin, ymin),
+            cell_size: cell_size,
+            cols: ((xmax - xmin) / cell_size) as usize + 1,
+            rows: ((ymax - ymin) / cell_size) as usize + 1,
+            cells: Vec::new(),
+        };
+        grid.cells = vec![Vec::new(); grid.cols * grid.rows];
+        for (&sgt, b) in refs.iter().zip(boxes.iter()) {
+            let (x0, y0) = grid.cell(Vec2::new(b.0, b.1));
+            let (x1, y1) = grid.cell(Vec2::new(b.2, b.3));
+            for y in y0 .. y1 + 1 {
+                for x in x0 .. x1 + 1 {
+                    grid.cells[y * grid.cols + x].push(sgt);
+                }
+            }
+        }
+        self.grid = Some(grid);
     }
 
     pub fn distance(&self, p: Vec2) -> f32 {
+        self.distance_counted(p).0
+    }
+
+    // Distance and the number of segments evaluated
+    fn distance_counted(&self, p: Vec2) -> (f32, usize) {
+        let grid = match self.grid {
+            Some(ref grid) => grid,
+            None => return (self.brute_force_distance(p), self.segment_count()),
+        };
+        // Upper bound from the nearest non-empty ring of cells around `p`
+        let (cx, cy) = grid.cell(p);
+        let (cx, cy) = (cx as isize, cy as isize);
+        let mut bound = f32::INFINITY;
+        let mut count = 0;
+        let mut r = 0;
+        while !bound.is_finite() {
+            for y in cy - r .. cy + r + 1 {
+                for x in cx - r .. cx + r + 1 {
+                    let on_ring = (x - cx).abs() == r || (y - cy).abs() == r;
+                    if !on_ring || x < 0 || y < 0 || x >= grid.cols as isize || y >= grid.rows as isize {
+                        continue;
+                    }
+                    for &sgt in &grid.cells[y as usize * grid.cols + x as usize] {
+                        bound = bound.min(self.segment_distance(sgt, p));
+                        count += 1;
+                    }
+                }
+            }
+            r += 1;
+        }
+        let (dist, within_count) = self.indexed_within(grid, p, bound);
+        (dist.unwrap_or(bound), count + within_count)
+    }
+
+    // Like `distance`, without the index
+    fn brute_force_distance(&self, p: Vec2) -> f32 {
         let mut dist_min = f32::INFINITY;
         for sgt in &self.linear_segments {
             let dist = sgt.distance(p);
@@ -51,4 +196,375 @@ impl OutlineDistance {
         }
         dist_min
     }
+
+    // Batched `distance`: for each of `points`, write its distance to the outline
+    // into `out`. The linear segments are processed 4 points at once
+    // with "simd" feature (nightly).
+    pub fn distances(&self, points: &[Vec2], out: &mut [f32]) {
+        assert_eq!(points.len(), out.len());
+        for dist in out.iter_mut() {
+            *dist = f32::INFINITY;
+        }
+        for sgt in &self.linear_segments {
+            linear_distances(sgt, points, out);
+        }
+        for sgt in &self.quadratic_segments {
+            for (p, dist) in points.iter().zip(out.iter_mut()) {
+                *dist = dist.min(sgt.distance(*p));
+            }
+        }
+        for sgt in &self.cubic_segments {
+            for (p, dist) in points.iter().zip(out.iter_mut()) {
+                *dist = dist.min(sgt.distance(*p));
+            }
+        }
+    }
+
+    // Distance to the outline if it's not farther than `radius`, otherwise None.
+    // Segments whose control box lies farther than the best distance so far
+    // are rejected without solving for the nearest point (the curve lies
+    // inside the convex hull of its control points).
+    pub fn distance_within(&self, p: Vec2, radius: f32) -> Option<f32> {
+        if let Some(ref grid) = self.grid {
+            return self.indexed_within(grid, p, radius).0;
+        }
+        let mut dist_min = radius;
+        let mut found = false;
+        {
+            let mut test = |points: &[Vec2], dist: &Fn() -> f32| {
+                if box_distance(p, points) > dist_min {
+                    return;
+                }
+                let dist = dist();
+                if dist <= dist_min {
+                    dist_min = dist;
+                    found = true;
+                }
+            };
+            for sgt in &self.linear_segments {
+                test(&[sgt.p0, sgt.p1], &|| sgt.distance(p));
+            }
+            for sgt in &self.quadratic_segments {
+                test(&[sgt.p0, sgt.p1, sgt.p2], &|| sgt.distance(p));
+            }
+            for sgt in &self.cubic_segments {
+                test(&[sgt.p0, sgt.p1, sgt.p2, sgt.p3], &|| sgt.distance(p));
+            }
+        }
+        if found { Some(dist_min) } else { None }
+    }
+
+    // `distance_within` of the segments in the cells which overlap the box
+    // of `radius` around `p`, and the number of segments evaluated
+    fn indexed_within(&self, grid: &SegmentGrid, p: Vec2, radius: f32) -> (Option<f32>, usize) {
+        let (x0, y0) = grid.cell(Vec2::new(p.x - radius, p.y - radius));
+        let (x1, y1) = grid.cell(Vec2::new(p.x + radius, p.y + radius));
+        let mut dist_min = radius;
+        let mut found = false;
+        let mut count = 0;
+        for y in y0 .. y1 + 1 {
+            for x in x0 .. x1 + 1 {
+                for &sgt in &grid.cells[y * grid.cols + x] {
+                    let (points, n) = self.control_points(sgt);
+                    if box_distance(p, &points[.. n]) > dist_min {
+                        continue;
+                    }
+                    let dist = self.segment_distance(sgt, p);
+                    count += 1;
+                    if dist <= dist_min {
+                        dist_min = dist;
+                        found = true;
+                    }
+                }
+            }
+        }
+        (if found { Some(dist_min) } else { None }, count)
+    }
+
+    // Same as `segment_refs().len()`, without collecting the refs
+    fn segment_count(&self) -> usize {
+        self.linear_segments.len() + self.quadratic_segments.len() + self.cubic_segments.len()
+    }
+
+    fn segment_refs(&self) -> Vec<SegmentRef> {
+        (0 .. self.linear_segments.len()).map(SegmentRef::Linear)
+            .chain((0 .. self.quadratic_segments.len()).map(SegmentRef::Quadratic))
+            .chain((0 .. self.cubic_segments.len()).map(SegmentRef::Cubic))
+            .collect()
+    }
+
+    // Control points of the segment (the first `count` of the array)
+    fn control_points(&self, sgt: SegmentRef) -> ([Vec2; 4], usize) {
+        let zero = Vec2::new(0.0, 0.0);
+        match sgt {
+            SegmentRef::Linear(i) => {
+                let s = &self.linear_segments[i];
+                ([s.p0, s.p1, zero, zero], 2)
+            }
+            SegmentRef::Quadratic(i) => {
+                let s = &self.quadratic_segments[i];
+                ([s.p0, s.p1, s.p2, zero], 3)
+            }
+            SegmentRef::Cubic(i) => {
+                let s = &self.cubic_segments[i];
+                ([s.p0, s.p1, s.p2, s.p3], 4)
+            }
+        }
+    }
+
+    fn segment_distance(&self, sgt: SegmentRef, p: Vec2) -> f32 {
+        match sgt {
+            SegmentRef::Linear(i) => self.linear_segments[i].distance(p),
+            SegmentRef::Quadratic(i) => self.quadratic_segments[i].distance(p),
+            SegmentRef::Cubic(i) => self.cubic_segments[i].distance(p),
+        }
+    }
+
+    // Pseudo-distance from `p` to the outline: the nearest segment is chosen
+    // by the true distance, but beyond its end points, the distance is measured
+    // to its tangent line. The corners stay sharp (the true distance rounds
+    // them off outside). Never greater than `distance`.
+    pub fn pseudo_distance(&self, p: Vec2) -> f32 {
+        // (distance, orthogonality, pseudo-distance) of the nearest segment
+        let mut best = (f32::INFINITY, 0.0, f32::INFINITY);
+        for sgt in self.segment_refs() {
+            let (dist, t, point, tangent) = self.segment_nearest(sgt, p);
+            let (d, m) = (tangent.normalize(), p - point);
+            let ortho = d.cross(m.normalize()).abs();
+            // Equidistant segments meet at a corner, prefer the one
+            // which the point faces (not its end point)
+            if dist < best.0 - 1e-4 || (dist <= best.0 + 1e-4 && ortho > best.1) {
+                let beyond = (t <= 0.0 && m.dot(d) < 0.0) || (t >= 1.0 && m.dot(d) > 0.0);
+                best = (dist, ortho, if beyond { m.cross(d).abs() } else { dist });
+            }
+        }
+        best.2
+    }
+
+    // Nearest point on the segment: distance, `t`, the point and the tangent there
+    fn segment_nearest(&self, sgt: SegmentRef, p: Vec2) -> (f32, f32, Vec2, Vec2) {
+        match sgt {
+            SegmentRef::Linear(i) => {
+                let s = &self.linear_segments[i];
+                let (dist, t, point) = s.distance_detailed(p);
+                (dist, t, point, s.eval_tangent(t))
+            }
+            SegmentRef::Quadratic(i) => {
+                let s = &self.quadratic_segments[i];
+                let (dist, t, point) = s.distance_detailed(p);
+                (dist, t, point, s.eval_tangent(t))
+            }
+            SegmentRef::Cubic(i) => {
+                let s = &self.cubic_segments[i];
+                let (dist, t, point) = s.distance_detailed(p);
+                (dist, t, point, s.eval_tangent(t))
+            }
+        }
+    }
+
+    // Like `distance`, but also find the nearest point, the tangent
+    // and orientation of the winning segment
+    pub fn nearest_point(&self, p: Vec2) -> NearestPoint {
+        let mut nearest = NearestPoint::new(f32::INFINITY, p, Vec2::new(0.0, 0.0), p);
+        for sgt in &self.linear_segments {
+            let (dist, t) = sgt.nearest(p);
+            if dist < nearest.distance {
+                nearest = NearestPoint::new(dist, sgt.eval_point(t), sgt.eval_tangent(t), p);
+            }
+        }
+        for sgt in &self.quadratic_segments {
+            let (dist, t) = sgt.nearest(p);
+            if dist < nearest.distance {
+                nearest = NearestPoint::new(dist, sgt.eval_point(t), sgt.eval_tangent(t), p);
+            }
+        }
+        for sgt in &self.cubic_segments {
+            let (dist, t) = sgt.nearest(p);
+            if dist < nearest.distance {
+                nearest = NearestPoint::new(dist, sgt.eval_point(t), sgt.eval_tangent(t), p);
+            }
+        }
+        nearest
+    }
+}
+
+// Update `out` with distances of `points` to the line segment, if they're smaller
+#[cfg(not(feature = "simd"))]
+fn linear_distances(sgt: &LinearSegment, points: &[Vec2], out: &mut [f32]) {
+    for (p, dist) in points.iter().zip(out.iter_mut()) {
+        *dist = dist.min(sgt.distance(*p));
+    }
+}
+
+// Same as above, 4 points at once. Follows `LinearSegment::nearest`.
+#[cfg(feature = "simd")]
+fn linear_distances(sgt: &LinearSegment, points: &[Vec2], out: &mut [f32]) {
+    use simd::f32x4;
+    let a = sgt.p1 - sgt.p0;
+    let aa = a.dot(a);
+    // Degenerate segment: t = 0, like the NaN clamped in the scalar version
+    let inv = f32x4::splat(if aa != 0.0 { 1.0 / aa } else { 0.0 });
+    let (p0x, p0y) = (f32x4::splat(sgt.p0.x), f32x4::splat(sgt.p0.y));
+    let (ax, ay) = (f32x4::splat(a.x), f32x4::splat(a.y));
+    let (zero, one) = (f32x4::splat(0.0), f32x4::splat(1.0));
+    let lanes = points.len() / 4 * 4;
+    let mut dist4 = [0f32; 4];
+    for i in (0 .. lanes / 4).map(|i| i * 4) {
+        let q = &points[i .. i + 4];
+        let px = f32x4::new(q[0].x, q[1].x, q[2].x, q[3].x);
+        let py = f32x4::new(q[0].y, q[1].y, q[2].y, q[3].y);
+        let (mx, my) = (px - p0x, py - p0y);
+        let t = ((mx * ax + my * ay) * inv).max(zero).min(one);
+        let (dx, dy) = (p0x + t * ax - px, p0y + t * ay - py);
+        (dx * dx + dy * dy).sqrt().store(&mut dist4, 0);
+        for k in 0 .. 4 {
+            out[i + k] = out[i + k].min(dist4[k]);
+        }
+    }
+    for (p, dist) in points[lanes ..].iter().zip(out[lanes ..].iter_mut()) {
+        *dist = dist.min(sgt.distance(*p));
+    }
+}
+
+// Bounding box of `points`: (xmin, ymin, xmax, ymax)
+fn control_box(points: &[Vec2]) -> (f32, f32, f32, f32) {
+    let (mut xmin, mut ymin) = (points[0].x, points[0].y);
+    let (mut xmax, mut ymax) = (xmin, ymin);
+    for pt in &points[1..] {
+        xmin = xmin.min(pt.x);
+        ymin = ymin.min(pt.y);
+        xmax = xmax.max(pt.x);
+        ymax = ymax.max(pt.y);
+    }
+    (xmin, ymin, xmax, ymax)
+}
+
+// Distance from `p` to the bounding box of `points` (zero when inside)
+fn box_distance(p: Vec2, points: &[Vec2]) -> f32 {
+    let (xmin, ymin, xmax, ymax) = control_box(points);
+    let dx = (xmin - p.x).max(p.x - xmax).max(0.0);
+    let dy = (ymin - p.y).max(p.y - ymax).max(0.0);
+    (dx * dx + dy * dy).sqrt()
+}
+
+
+// Tests
+// -----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn float_eq(a: f32, b: f32) -> bool {
+        let eps = 4e-5f32;
+        (a - b).abs() < eps
+    }
+
+    // Same samples as in curve tests, the reference distance is signed
+    #[test]
+    fn test_nearest_point() {
+        let mut outline = OutlineDistance::new();
+        outline.push_bezier3(Vec2::new(100.0, 200.0), Vec2::new(250.0, 400.0),
+                             Vec2::new(400.0, 200.0), Vec2::new(400.0, 400.0));
+
+        let p = Vec2::new(98.0, 314.0);
+        let nearest = outline.nearest_point(p);
+        assert!(float_eq(nearest.signed_distance(), -80.05094469021948));
+        assert!(float_eq(nearest.distance, outline.distance(p)));
+        // The distance vector is perpendicular to the tangent
+        let cos = (p - nearest.point).dot(nearest.tangent) /
+                  (nearest.distance * nearest.tangent.magnitude());
+        assert!(cos.abs() < 1e-3);
+
+        let p = Vec2::new(419.0, 291.0);
+        let nearest = outline.nearest_point(p);
+        assert!(float_eq(nearest.signed_distance(), 47.04632869336913));
+    }
+
+    #[test]
+    fn test_pseudo_distance() {
+        // Square 0..4
+        let mut outline = OutlineDistance::new();
+        let points = [(0.0, 0.0), (0.0, 4.0), (4.0, 4.0), (4.0, 0.0)];
+        for i in 0 .. 4 {
+            let ((x0, y0), (x1, y1)) = (points[i], points[(i + 1) % 4]);
+            outline.push_line(Vec2::new(x0, y0), Vec2::new(x1, y1));
+        }
+        // Facing an edge, both are the same
+        for &p in [Vec2::new(5.0, 2.0), Vec2::new(1.0, 2.0), Vec2::new(2.0, -3.0)].iter() {
+            assert!(float_eq(outline.pseudo_distance(p), outline.distance(p)));
+        }
+        // Beyond the corner, the distance is to the extension of the right edge
+        let p = Vec2::new(5.0, 4.5);
+        assert!(float_eq(outline.distance(p), 1.25f32.sqrt()));
+        assert!(float_eq(outline.pseudo_distance(p), 1.0));
+        let p = Vec2::new(4.5, 6.0);
+        assert!(float_eq(outline.pseudo_distance(p), 2.0));
+        assert!(outline.pseudo_distance(p) < outline.distance(p));
+    }
+
+    #[test]
+    fn test_distance_within() {
+        let mut outline = OutlineDistance::new();
+        outline.push_line(Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0));
+        outline.push_bezier2(Vec2::new(10.0, 0.0), Vec2::new(15.0, 5.0), Vec2::new(10.0, 10.0));
+        for &p in [Vec2::new(5.0, 3.0), Vec2::new(13.0, 5.0), Vec2::new(-2.0, -2.0)].iter() {
+            let dist = outline.distance(p);
+            assert_eq!(outline.distance_within(p, dist + 0.1), Some(dist));
+            assert_eq!(outline.distance_within(p, dist - 0.1), None);
+        }
+        assert_eq!(outline.distance_within(Vec2::new(100.0, 100.0), 10.0), None);
+    }
+
+    #[test]
+    fn test_distances() {
+        let mut outline = OutlineDistance::new();
+        outline.push_line(Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0));
+        outline.push_line(Vec2::new(10.0, 0.0), Vec2::new(10.0, 0.0));
+        outline.push_line(Vec2::new(10.0, 0.0), Vec2::new(5.0, 8.0));
+        outline.push_bezier2(Vec2::new(5.0, 8.0), Vec2::new(0.0, 8.0), Vec2::new(0.0, 0.0));
+        // Not a multiple of 4, the remainder goes through the scalar path
+        let points: Vec<Vec2> = (0 .. 23)
+            .map(|i| Vec2::new(i as f32 * 0.7 - 3.0, (i % 5) as f32 * 2.1 - 1.0))
+            .collect();
+        let mut out = vec![0.0; points.len()];
+        outline.distances(&points, &mut out);
+        for (p, dist) in points.iter().zip(out.iter()) {
+            assert!((dist - outline.distance(*p)).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_finalize() {
+        // Dense outline (like '@' glyph): wavy ring of lines, with curved inner part
+        let mut outline = OutlineDistance::new();
+        let point = |i: usize, r: f32| {
+            let a = i as f32 * 2.0 * ::std::f32::consts::PI / 360.0;
+            let r = r + (i % 7) as f32 * 0.1;
+            Vec2::new(50.0 + r * a.cos(), 50.0 + r * a.sin())
+        };
+        for i in 0 .. 360 {
+            outline.push_line(point(i, 40.0), point(i + 1, 40.0));
+        }
+        for i in 0 .. 90 {
+            outline.push_bezier2(point(4 * i, 20.0), point(4 * i + 2, 15.0), point(4 * i + 4, 20.0));
+        }
+        let points: Vec<Vec2> = (0 .. 400)
+            .map(|i| Vec2::new((i % 20) as f32 * 5.3 - 3.0, (i / 20) as f32 * 5.1 - 2.0))
+            .collect();
+        let brute: Vec<(f32, usize)> = points.iter().map(|&p| outline.distance_counted(p)).collect();
+        outline.finalize();
+        let mut indexed_count = 0;
+        for (&p, &(dist, count)) in points.iter().zip(brute.iter()) {
+            assert_eq!(count, 450);
+            let (indexed, n) = outline.distance_counted(p);
+            assert_eq!(indexed, dist);
+            assert_eq!(outline.distance_within(p, 3.0), if dist <= 3.0 { Some(dist) } else { None });
+            indexed_count += n;
+        }
+        assert!(indexed_count * 10 < 450 * points.len(), "{} evaluations", indexed_count);
+        // New segment drops the index
+        outline.push_line(Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0));
+        assert_eq!(outline.distance_counted(Vec2::new(0.0, 1.0)), (1.0, 451));
+    }
 }
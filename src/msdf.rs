@@ -0,0 +1,313 @@
+use std::f32;
+
+use curve::*;
+use outline::*;
+use font::*;
+
+// Default `angle_threshold` for `MsdfGenerator::new`, in radians
+pub const MSDF_ANGLE_THRESHOLD: f32 = 0.15;
+
+/// Set of MSDF channels an edge contributes to
+///
+/// Edges meeting at a corner share only one channel, so the median
+/// of the three channels reconstructs the corner sharp.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EdgeColor {
+    // all channels (smooth contours without corners)
+    White,
+    // green + blue
+    Cyan,
+    // red + blue
+    Magenta,
+    // red + green
+    Yellow,
+}
+
+impl EdgeColor {
+    // Does the edge contribute to `channel` (0 = red, 1 = green, 2 = blue)?
+    pub fn has_channel(self, channel: usize) -> bool {
+        match self {
+            EdgeColor::White => true,
+            EdgeColor::Cyan => channel != 0,
+            EdgeColor::Magenta => channel != 1,
+            EdgeColor::Yellow => channel != 2,
+        }
+    }
+}
+
+/// Generator of multi-channel signed distance field
+///
+/// Each channel holds the distance to a subset of the outline edges,
+/// the shape is reconstructed by the median of the three channels
+/// (see `median`). Consumes the same outline segments as `OutlineDistance`.
+
+#[derive(Clone, Debug)]
+pub struct MsdfGenerator {
+    pub edges: Vec<(PathSegment, EdgeColor)>,
+    // 1 for clockwise filled contours, -1 for `reverse_fill`
+    pub orientation: f32,
+}
+
+impl MsdfGenerator {
+    // Color the edges of `outline` (in face pixels). Corners are the joints
+    // where the direction of the contour changes by more than `angle_threshold`
+    // (in radians, see MSDF_ANGLE_THRESHOLD).
+    pub fn new(outline: &Outline, angle_threshold: f32) -> Self {
+        let mut edges = Vec::new();
+        for contour in outline.contours.iter() {
+            let colors = color_contour(contour, angle_threshold);
+            edges.extend(contour.iter().cloned().zip(colors.into_iter()));
+        }
+        MsdfGenerator {
+            edges: edges,
+            orientation: if outline.reverse_fill { -1.0 } else { 1.0 },
+        }
+    }
+
+    // Signed pseudo-distance from `p` to the edges of each channel
+    // (positive = outside, like in `SdfParams::encode`). Beyond the end
+    // of the nearest edge, the distance is measured to its tangent line,
+    // so the channels of the two edges at a corner extend across it.
+    pub fn distances(&self, p: Vec2) -> [f32; 3] {
+        // Nearest edge of each channel: (distance, orthogonality, edge, t)
+        let mut best = [(f32::INFINITY, 0.0, 0, 0.0); 3];
+        for (i, &(ref edge, color)) in self.edges.iter().enumerate() {
+            let (dist, t) = edge.nearest(p);
+            let ortho = orthogonality(edge, p, t);
+            for c in 0 .. 3 {
+                let (best_dist, best_ortho, _, _) = best[c];
+                // Equidistant edges meet at a corner, prefer the one
+                // which the point faces (not its endpoint)
+                let closer = dist < best_dist - 1e-4 ||
+                             (dist <= best_dist + 1e-4 && ortho > best_ortho);
+                if color.has_channel(c) && closer {
+                    best[c] = (dist, ortho, i, t);
+                }
+            }
+        }
+        let mut out = [f32::INFINITY; 3];
+        for c in 0 .. 3 {
+            let (dist, _, i, t) = best[c];
+            if dist.is_finite() {
+                out[c] = -self.orientation * pseudo_distance(&self.edges[i].0, p, t);
+            }
+        }
+        out
+    }
+
+    // Render MSDF of the glyph's tile into interleaved RGB `buffer`
+    // (3 bytes per pixel, `pitch` in pixels). Same pixel centers
+    // and padding as `Glyph::render_sdf_from`.
+    pub fn render(&self, glyph: &Glyph, face_size: usize, params: &SdfParams,
+                  buffer: &mut [u8], pitch: usize) {
+        for yr in 0 .. glyph.height {
+            let y = (glyph.ymin + (glyph.height - yr - 1) as isize) as f32 + 0.5;
+            for xr in 0 .. glyph.width {
+                let x = (glyph.xmin + xr as isize) as f32 + 0.5;
+                let i = ((glyph.y + yr) * pitch + glyph.x + xr) * 3;
+                // Saturated outside border (see `Glyph::padding`)
+                if glyph.padding > 0 && (xr == 0 || yr == 0 ||
+                                         xr + 1 == glyph.width || yr + 1 == glyph.height) {
+                    for c in 0 .. 3 {
+                        buffer[i + c] = params.outside_value();
+                    }
+                    continue;
+                }
+                let distances = self.distances(Vec2::new(x, y));
+                for c in 0 .. 3 {
+                    buffer[i + c] = params.encode(distances[c], face_size);
+                }
+            }
+        }
+    }
+}
+
+// Median of the channels gives the reconstructed value
+pub fn median(r: f32, g: f32, b: f32) -> f32 {
+    r.min(g).max(r.max(g).min(b))
+}
+
+// Assign colors to edges of a closed contour, switching the color at each corner
+fn color_contour(contour: &[PathSegment], angle_threshold: f32) -> Vec<EdgeColor> {
+    let n = contour.len();
+    // Degenerate (zero length) edges are skipped, they don't start corners
+    let degenerate = |i: usize| edge_direction(&contour[i], 0.0).magnitude2() == 0.0;
+    // Edges starting at a corner
+    let corners: Vec<usize> = (0 .. n).filter(|&i| {
+        if degenerate(i) {
+            return false;
+        }
+        let prev = match (1 .. n).map(|k| (i + n - k) % n).find(|&k| !degenerate(k)) {
+            Some(prev) => prev,
+            None => return false,
+        };
+        is_corner(edge_direction(&contour[prev], 1.0), edge_direction(&contour[i], 0.0),
+                  angle_threshold)
+    }).collect();
+    let mut colors = vec![EdgeColor::White; n];
+    match corners.len() {
+        0 => {}
+        1 => {
+            // Teardrop: split the edges into thirds, the first and last
+            // share one channel at the corner
+            let start = corners[0];
+            for k in 0 .. n {
+                colors[(start + k) % n] = if 3 * k < n {
+                    EdgeColor::Cyan
+                } else if 3 * (k + 1) <= 2 * n {
+                    EdgeColor::White
+                } else {
+                    EdgeColor::Yellow
+                };
+            }
+        }
+        m => {
+            let cycle = [EdgeColor::Cyan, EdgeColor::Magenta, EdgeColor::Yellow];
+            for (j, &start) in corners.iter().enumerate() {
+                // The last spline has to differ from the first one too
+                let color = if j + 1 == m && j % 3 == 0 { cycle[1] } else { cycle[j % 3] };
+                let end = corners[(j + 1) % m];
+                let mut i = start;
+                loop {
+                    colors[i] = color;
+                    i = (i + 1) % n;
+                    if i == end {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    colors
+}
+
+// Direction of the edge at its start (t = 0) or end (t = 1). When the control
+// point coincides with the endpoint, the tangent is zero, use the chord instead.
+fn edge_direction(edge: &PathSegment, t: f32) -> Vec2 {
+    let d = edge.eval_tangent(t);
+    if d.magnitude2() > 0.0 { d } else { edge.eval_point(1.0) - edge.eval_point(0.0) }
+}
+
+fn is_corner(incoming: Vec2, outgoing: Vec2, angle_threshold: f32) -> bool {
+    let (a, b) = (incoming.normalize(), outgoing.normalize());
+    let angle = a.cross(b).abs().atan2(a.dot(b));
+    angle > angle_threshold
+}
+
+// Sine of the angle between the edge's tangent and the direction to `p`
+fn orthogonality(edge: &PathSegment, p: Vec2, t: f32) -> f32 {
+    let (d, m) = (edge.eval_tangent(t).normalize(), (p - edge.eval_point(t)).normalize());
+    d.cross(m).abs()
+}
+
+// Signed distance to the edge, positive on the right side (inside
+// of clockwise contour). Beyond the ends, it's the distance to the tangent line.
+fn pseudo_distance(edge: &PathSegment, p: Vec2, t: f32) -> f32 {
+    let d = edge.eval_tangent(t).normalize();
+    let m = p - edge.eval_point(t);
+    let side = m.cross(d);
+    if (t <= 0.0 && m.dot(d) < 0.0) || (t >= 1.0 && m.dot(d) > 0.0) {
+        side
+    } else {
+        m.magnitude() * side.signum()
+    }
+}
+
+
+// Tests
+// -----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rasterizer::*;
+
+    // L-shaped outline, 4 x 5 texels
+    fn l_shape() -> Outline {
+        let mut builder = OutlineBuilder::new();
+        let points = [(0.0, 0.0), (0.0, 5.0), (1.5, 5.0), (1.5, 1.5), (4.0, 1.5), (4.0, 0.0)];
+        builder.move_to(Vec2::new(points[0].0, points[0].1));
+        for &(x, y) in points[1..].iter() {
+            builder.line_to(Vec2::new(x, y));
+        }
+        builder.finish()
+    }
+
+    #[test]
+    fn test_edge_coloring() {
+        let msdf = MsdfGenerator::new(&l_shape(), MSDF_ANGLE_THRESHOLD);
+        let n = msdf.edges.len();
+        assert_eq!(n, 6);
+        for i in 0 .. n {
+            let (a, b) = (msdf.edges[i].1, msdf.edges[(i + 1) % n].1);
+            assert!(a != b && a != EdgeColor::White, "{} {:?} {:?}", i, a, b);
+        }
+        // Smooth contour (circle) has a single color
+        let mut builder = OutlineBuilder::new();
+        let k = 0.5523;
+        builder.move_to(Vec2::new(1.0, 0.0));
+        builder.curve_to(Vec2::new(1.0, -k), Vec2::new(k, -1.0), Vec2::new(0.0, -1.0));
+        builder.curve_to(Vec2::new(-k, -1.0), Vec2::new(-1.0, -k), Vec2::new(-1.0, 0.0));
+        builder.curve_to(Vec2::new(-1.0, k), Vec2::new(-k, 1.0), Vec2::new(0.0, 1.0));
+        builder.curve_to(Vec2::new(k, 1.0), Vec2::new(1.0, k), Vec2::new(1.0, 0.0));
+        let circle = MsdfGenerator::new(&builder.finish(), MSDF_ANGLE_THRESHOLD);
+        assert!(circle.edges.iter().all(|e| e.1 == EdgeColor::White));
+    }
+
+    // Reconstruct the shape from bilinearly sampled texture, like GPU does.
+    // Returns the number of wrong samples near the corners.
+    fn corner_errors(outline: &Outline, glyph: &Glyph, buffer: &[u8], channels: usize) -> usize {
+        let texel = |x: usize, y: usize, c: usize| {
+            // Row 0 is the top
+            buffer[((glyph.height - 1 - y) * glyph.width + x) * channels + c] as f32
+        };
+        let sample = |p: Vec2, c: usize| {
+            let (fx, fy) = (p.x - glyph.xmin as f32 - 0.5, p.y - glyph.ymin as f32 - 0.5);
+            let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+            let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+            let lower = (1.0 - tx) * texel(x0, y0, c) + tx * texel(x0 + 1, y0, c);
+            let upper = (1.0 - tx) * texel(x0, y0 + 1, c) + tx * texel(x0 + 1, y0 + 1, c);
+            (1.0 - ty) * lower + ty * upper
+        };
+        let rasterizer = outline.rasterizer();
+        let mindist = outline.distance();
+        let mut errors = 0;
+        // 1.25 texels around each corner, at 1/8 texel steps
+        for sgt in outline.contours[0].iter() {
+            let corner = sgt.eval_point(0.0);
+            for iy in -10 .. 11 {
+                for ix in -10 .. 11 {
+                    let p = corner + Vec2::new(ix as f32 / 8.0 + 0.01, iy as f32 / 8.0 + 0.01);
+                    // Skip points too near the edges (the texels are quantized)
+                    if mindist.distance(p) < 0.2 {
+                        continue;
+                    }
+                    let value = if channels == 3 {
+                        median(sample(p, 0), sample(p, 1), sample(p, 2))
+                    } else {
+                        sample(p, 0)
+                    };
+                    if (value > 127.0) != rasterizer.contains(p, FillRule::NonZero) {
+                        errors += 1;
+                    }
+                }
+            }
+        }
+        errors
+    }
+
+    #[test]
+    fn test_sharp_corner() {
+        let outline = l_shape();
+        let glyph = Glyph::from_outline(&outline, 2, &FontConfig::default());
+        let params = SdfParams { spread: Some(2.0), .. SdfParams::default() };
+        let mut buffer = vec![0u8; glyph.width * glyph.height * 3];
+        MsdfGenerator::new(&outline, MSDF_ANGLE_THRESHOLD)
+            .render(&glyph, 16, &params, &mut buffer, glyph.width);
+        assert_eq!(corner_errors(&outline, &glyph, &buffer, 3), 0);
+        // Single channel SDF rounds the corners
+        let sdf = glyph.render_standalone(&outline, 16, &params);
+        assert!(corner_errors(&outline, &glyph, &sdf, 1) > 0);
+    }
+}
@@ -0,0 +1,537 @@
+use std::f32;
+use std::mem;
+
+use curve::*;
+use rasterizer::*;
+use mindist::*;
+
+/// Segment of an outline contour
+
+#[derive(Clone, Debug)]
+pub enum PathSegment {
+    Line(LinearSegment),
+    Quad(QuadraticSegment),
+    Cubic(CubicSegment),
+}
+
+impl PathSegment {
+    pub fn eval_point(&self, t: f32) -> Vec2 {
+        match *self {
+            PathSegment::Line(ref s) => s.eval_point(t),
+            PathSegment::Quad(ref s) => s.eval_point(t),
+            PathSegment::Cubic(ref s) => s.eval_point(t),
+        }
+    }
+
+    pub fn eval_tangent(&self, t: f32) -> Vec2 {
+        match *self {
+            PathSegment::Line(ref s) => s.eval_tangent(t),
+            PathSegment::Quad(ref s) => s.eval_tangent(t),
+            PathSegment::Cubic(ref s) => s.eval_tangent(t),
+        }
+    }
+
+    // Distance to the nearest point and its parameter t
+    pub fn nearest(&self, p: Vec2) -> (f32, f32) {
+        match *self {
+            PathSegment::Line(ref s) => s.nearest(p),
+            PathSegment::Quad(ref s) => s.nearest(p),
+            PathSegment::Cubic(ref s) => s.nearest(p),
+        }
+    }
+}
+
+/// Affine transformation: p' = (xx*x + xy*y + dx, yx*x + yy*y + dy)
+///
+/// Same layout as FreeType's FT_Matrix with delta vector.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub xx: f32,
+    pub xy: f32,
+    pub yx: f32,
+    pub yy: f32,
+    pub dx: f32,
+    pub dy: f32,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform::scale(1.0, 1.0)
+    }
+
+    pub fn scale(sx: f32, sy: f32) -> Self {
+        Transform { xx: sx, xy: 0.0, yx: 0.0, yy: sy, dx: 0.0, dy: 0.0 }
+    }
+
+    pub fn apply(&self, p: Vec2) -> Vec2 {
+        Vec2::new(self.xx * p.x + self.xy * p.y + self.dx,
+                  self.yx * p.x + self.yy * p.y + self.dy)
+    }
+
+    // Transformation applying `self` first, then `other`
+    pub fn then(&self, other: &Transform) -> Self {
+        let p = other.apply(Vec2::new(self.dx, self.dy));
+        Transform {
+            xx: other.xx * self.xx + other.xy * self.yx,
+            xy: other.xx * self.xy + other.xy * self.yy,
+            yx: other.yx * self.xx + other.yy * self.yx,
+            yy: other.yx * self.xy + other.yy * self.yy,
+            dx: p.x,
+            dy: p.y,
+        }
+    }
+
+    // Mirroring transforms (negative determinant) reverse the contour orientation
+    pub fn is_mirroring(&self) -> bool {
+        self.xx * self.yy - self.xy * self.yx < 0.0
+    }
+}
+
+/// Outline made of closed contours
+///
+/// Outer contours are oriented clockwise (with Y axis going up), holes
+/// counter-clockwise, like in TrueType fonts. Outlines with the opposite
+/// orientation have `reverse_fill` set, like FreeType's FT_OUTLINE_REVERSE_FILL
+/// flag. The flag only tells which side is outside for the operations which
+/// depend on it (emboldening). The filling itself uses the non-zero winding rule,
+/// like FreeType does, so any mix of orientations in one outline fills the same.
+
+#[derive(Clone, Debug)]
+pub struct Outline {
+    pub contours: Vec<Vec<PathSegment>>,
+    pub reverse_fill: bool,
+}
+
+impl Outline {
+    pub fn new() -> Self {
+        Outline {
+            contours: Vec::new(),
+            reverse_fill: false,
+        }
+    }
+
+    // Feed the segments into rasterizer (for scanline crossings)
+    pub fn rasterizer(&self) -> Rasterizer {
+        let mut rasterizer = Rasterizer::new();
+        for sgt in self.contours.iter().flat_map(|c| c.iter()) {
+            match *sgt {
+                PathSegment::Line(ref s) => rasterizer.push_line(s.p0, s.p1),
+                PathSegment::Quad(ref s) => rasterizer.push_bezier2(s.p0, s.p1, s.p2),
+                PathSegment::Cubic(ref s) => rasterizer.push_bezier3(s.p0, s.p1, s.p2, s.p3),
+            }
+        }
+        rasterizer.finalize();
+        rasterizer
+    }
+
+    // Feed the segments into OutlineDistance (for minimum distance from a point)
+    pub fn distance(&self) -> OutlineDistance {
+        let mut mindist = OutlineDistance::new();
+        for sgt in self.contours.iter().flat_map(|c| c.iter()) {
+            match *sgt {
+                PathSegment::Line(ref s) => mindist.push_line(s.p0, s.p1),
+                PathSegment::Quad(ref s) => mindist.push_bezier2(s.p0, s.p1, s.p2),
+                PathSegment::Cubic(ref s) => mindist.push_bezier3(s.p0, s.p1, s.p2, s.p3),
+            }
+        }
+        mindist.finalize();
+        mindist
+    }
+
+    // Like `distance`, but only with the parts of the outline on the boundary
+    // of the filled area (the union of the contours). Where contours overlap
+    // (e.g. merged shapes), the parts lying inside the other contours' fill
+    // would make false edges (seams) in the SDF. The segments are split into
+    // pieces of at most about `max_length` (in the outline units), the pieces
+    // with fill on both sides are dropped. A piece crossing other contour
+    // is kept whole, so the error is bounded by `max_length`.
+    pub fn union_distance(&self, max_length: f32) -> OutlineDistance {
+        let rasterizer = self.rasterizer();
+        let eps = max_length * 1e-3;
+        // Is there fill on only one side of the piece's midpoint?
+        let on_boundary = |mid: Vec2, tangent: Vec2| {
+            let n = eps * tangent.perp().normalize();
+            rasterizer.contains(mid + n, FillRule::NonZero) !=
+                rasterizer.contains(mid - n, FillRule::NonZero)
+        };
+        let mut mindist = OutlineDistance::new();
+        for sgt in self.contours.iter().flat_map(|c| c.iter()) {
+            let zero = Vec2::new(0.0, 0.0);
+            let (control, count) = match *sgt {
+                PathSegment::Line(ref s) => ([s.p0, s.p1, zero, zero], 2),
+                PathSegment::Quad(ref s) => ([s.p0, s.p1, s.p2, zero], 3),
+                PathSegment::Cubic(ref s) => ([s.p0, s.p1, s.p2, s.p3], 4),
+            };
+            let points = &control[.. count];
+            // The control polygon is not shorter than the curve
+            let length: f32 = points.windows(2).map(|w| (w[1] - w[0]).magnitude()).sum();
+            let n = (length / max_length).ceil().max(1.0) as usize;
+            for i in 0 .. n {
+                let (t0, t1) = (i as f32 / n as f32, (i + 1) as f32 / n as f32);
+                let tm = (t0 + t1) / 2.0;
+                match *sgt {
+                    PathSegment::Line(ref s) => {
+                        let (p0, p1) = (s.eval_point(t0), s.eval_point(t1));
+                        if on_boundary(s.eval_point(tm), s.eval_tangent(tm)) {
+                            mindist.push_line(p0, p1);
+                        }
+                    }
+                    PathSegment::Quad(ref s) => {
+                        if on_boundary(s.eval_point(tm), s.eval_tangent(tm)) {
+                            mindist.push_bezier2(blossom(points, &[t0, t0]),
+                                                 blossom(points, &[t0, t1]),
+                                                 blossom(points, &[t1, t1]));
+                        }
+                    }
+                    PathSegment::Cubic(ref s) => {
+                        if on_boundary(s.eval_point(tm), s.eval_tangent(tm)) {
+                            mindist.push_bezier3(blossom(points, &[t0, t0, t0]),
+                                                 blossom(points, &[t0, t0, t1]),
+                                                 blossom(points, &[t0, t1, t1]),
+                                                 blossom(points, &[t1, t1, t1]));
+                        }
+                    }
+                }
+            }
+        }
+        mindist.finalize();
+        mindist
+    }
+
+    // Bounding box of all control points: (xmin, ymin, xmax, ymax)
+    // The curves lie inside their control polygons, so this contains the whole outline.
+    pub fn control_box(&self) -> (f32, f32, f32, f32) {
+        let mut bbox = (f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+        {
+            let mut add = |p: Vec2| {
+                bbox.0 = bbox.0.min(p.x);
+                bbox.1 = bbox.1.min(p.y);
+                bbox.2 = bbox.2.max(p.x);
+                bbox.3 = bbox.3.max(p.y);
+            };
+            for sgt in self.contours.iter().flat_map(|c| c.iter()) {
+                match *sgt {
+                    PathSegment::Line(ref s) => { add(s.p0); add(s.p1); }
+                    PathSegment::Quad(ref s) => { add(s.p0); add(s.p1); add(s.p2); }
+                    PathSegment::Cubic(ref s) => { add(s.p0); add(s.p1); add(s.p2); add(s.p3); }
+                }
+            }
+        }
+        bbox
+    }
+
+    // Convert to SVG path data (the `d` attribute) with M, L, Q, C, Z commands.
+    // The coordinates are kept as they are (Y axis going up), flip them
+    // for display, e.g. with `transform="scale(1, -1)"`.
+    pub fn svg_path(&self) -> String {
+        let mut path = String::new();
+        for contour in self.contours.iter() {
+            let start = match contour.first() {
+                Some(&PathSegment::Line(ref s)) => s.p0,
+                Some(&PathSegment::Quad(ref s)) => s.p0,
+                Some(&PathSegment::Cubic(ref s)) => s.p0,
+                None => continue,
+            };
+            if !path.is_empty() {
+                path.push(' ');
+            }
+            path.push_str(&format!("M{} {}", start.x, start.y));
+            for (i, sgt) in contour.iter().enumerate() {
+                match *sgt {
+                    PathSegment::Line(ref s) => {
+                        // The closing line is implied by Z
+                        let closing = i + 1 == contour.len() &&
+                                      s.p1.x == start.x && s.p1.y == start.y;
+                        if !closing {
+                            path.push_str(&format!(" L{} {}", s.p1.x, s.p1.y));
+                        }
+                    }
+                    PathSegment::Quad(ref s) =>
+                        path.push_str(&format!(" Q{} {} {} {}", s.p1.x, s.p1.y, s.p2.x, s.p2.y)),
+                    PathSegment::Cubic(ref s) =>
+                        path.push_str(&format!(" C{} {} {} {} {} {}",
+                                               s.p1.x, s.p1.y, s.p2.x, s.p2.y, s.p3.x, s.p3.y)),
+                }
+            }
+            path.push_str(" Z");
+        }
+        path
+    }
+
+    // Apply affine transformation to all points
+    pub fn transform(&mut self, m: &Transform) {
+        for sgt in self.contours.iter_mut().flat_map(|c| c.iter_mut()) {
+            match *sgt {
+                PathSegment::Line(ref mut s) => {
+                    s.p0 = m.apply(s.p0);
+                    s.p1 = m.apply(s.p1);
+                }
+                PathSegment::Quad(ref mut s) => {
+                    s.p0 = m.apply(s.p0);
+                    s.p1 = m.apply(s.p1);
+                    s.p2 = m.apply(s.p2);
+                }
+                PathSegment::Cubic(ref mut s) => {
+                    s.p0 = m.apply(s.p0);
+                    s.p1 = m.apply(s.p1);
+                    s.p2 = m.apply(s.p2);
+                    s.p3 = m.apply(s.p3);
+                }
+            }
+        }
+        if m.is_mirroring() {
+            self.reverse_fill = !self.reverse_fill;
+        }
+    }
+
+    // Embolden the outline by `strength` (in the outline units, i.e. pixels):
+    // each side of a stroke is offset by strength/2 along the contour normals.
+    // Like FreeType's FT_Outline_Embolden, the points (including control points)
+    // are moved along the bisector of the adjacent control polygon edges,
+    // so the contours stay closed. Negative strength makes the outline thinner.
+    pub fn embolden(&mut self, strength: f32) {
+        if strength == 0.0 {
+            return;
+        }
+        // Outer side is on the left of the clockwise filled contours
+        let offset = if self.reverse_fill { -strength / 2.0 } else { strength / 2.0 };
+        for contour in self.contours.iter_mut() {
+            // Control polygon of the contour (without the closing point)
+            let mut points = Vec::with_capacity(contour.len() * 3);
+            for sgt in contour.iter() {
+                match *sgt {
+                    PathSegment::Line(ref s) => points.push(s.p0),
+                    PathSegment::Quad(ref s) => points.extend_from_slice(&[s.p0, s.p1]),
+                    PathSegment::Cubic(ref s) => points.extend_from_slice(&[s.p0, s.p1, s.p2]),
+                }
+            }
+            let moved = embolden_polygon(&points, offset);
+            let mut i = 0;
+            let mut next = || { let p = moved[i % moved.len()]; i += 1; p };
+            let first = next();
+            let mut p0 = first;
+            let count = contour.len();
+            for (k, sgt) in contour.iter_mut().enumerate() {
+                let last = k + 1 == count;
+                *sgt = match *sgt {
+                    PathSegment::Line(_) => {
+                        let p1 = if last { first } else { next() };
+                        PathSegment::Line(LinearSegment::new(p0, p1))
+                    }
+                    PathSegment::Quad(_) => {
+                        let p1 = next();
+                        let p2 = if last { first } else { next() };
+                        PathSegment::Quad(QuadraticSegment::new(p0, p1, p2))
+                    }
+                    PathSegment::Cubic(_) => {
+                        let (p1, p2) = (next(), next());
+                        let p3 = if last { first } else { next() };
+                        PathSegment::Cubic(CubicSegment::new(p0, p1, p2, p3))
+                    }
+                };
+                p0 = match *sgt {
+                    PathSegment::Line(ref s) => s.p1,
+                    PathSegment::Quad(ref s) => s.p2,
+                    PathSegment::Cubic(ref s) => s.p3,
+                };
+            }
+        }
+    }
+}
+
+// Blossom of Bezier curve with control `points` (up to cubic),
+// i.e. de Casteljau algorithm with different parameter `ts` at each level.
+// The control points of the curve restricted to t0..t1 are the blossoms
+// with `ts` made of t0 and t1 (e.g. [t0, t0, t1]).
+fn blossom(points: &[Vec2], ts: &[f32]) -> Vec2 {
+    let mut p = [Vec2::new(0.0, 0.0); 4];
+    p[.. points.len()].copy_from_slice(points);
+    for (level, &t) in ts.iter().enumerate() {
+        for i in 0 .. points.len() - level - 1 {
+            p[i] = p[i].lerp(p[i + 1], t);
+        }
+    }
+    p[0]
+}
+
+// Move each point of closed polygon along the bisector of its adjacent edges,
+// so the edges are offset by `offset` to their left side
+fn embolden_polygon(points: &[Vec2], offset: f32) -> Vec<Vec2> {
+    let n = points.len();
+    let same = |a: Vec2, b: Vec2| a.x == b.x && a.y == b.y;
+    (0 .. n).map(|i| {
+        let p = points[i];
+        // Nearest distinct neighbours (skip degenerate edges)
+        let prev = (1 .. n).map(|k| points[(i + n - k) % n]).find(|&q| !same(p, q));
+        let next = (1 .. n).map(|k| points[(i + k) % n]).find(|&q| !same(p, q));
+        let (prev, next) = match (prev, next) {
+            (Some(prev), Some(next)) => (prev, next),
+            _ => return p,
+        };
+        let d_in = (p - prev).normalize();
+        let d_out = (next - p).normalize();
+        // Miter: |shift| = offset / cos(half angle between the normals)
+        let d = 1.0 + d_in.dot(d_out);
+        if d < 1.0 / 16.0 {
+            // Nearly reversed direction, the miter would be too long
+            return p;
+        }
+        p + (offset / d) * (d_in.perp() + d_out.perp())
+    }).collect()
+}
+
+/// Builder of an `Outline` from drawing commands
+///
+/// Mirrors FreeType's outline decomposition callbacks
+/// (move_to, line_to, conic_to, cubic_to). Each `move_to` starts a new contour,
+/// contours are closed automatically.
+
+#[derive(Clone, Debug)]
+pub struct OutlineBuilder {
+    outline: Outline,
+    contour: Vec<PathSegment>,
+    start: Vec2,
+    current: Vec2,
+}
+
+impl OutlineBuilder {
+    pub fn new() -> Self {
+        OutlineBuilder {
+            outline: Outline::new(),
+            contour: Vec::new(),
+            start: Vec2::new(0.0, 0.0),
+            current: Vec2::new(0.0, 0.0),
+        }
+    }
+
+    pub fn move_to(&mut self, p: Vec2) {
+        self.close();
+        self.start = p;
+        self.current = p;
+    }
+
+    pub fn line_to(&mut self, p: Vec2) {
+        self.contour.push(PathSegment::Line(LinearSegment::new(self.current, p)));
+        self.current = p;
+    }
+
+    pub fn quad_to(&mut self, p1: Vec2, p2: Vec2) {
+        self.contour.push(PathSegment::Quad(QuadraticSegment::new(self.current, p1, p2)));
+        self.current = p2;
+    }
+
+    pub fn curve_to(&mut self, p1: Vec2, p2: Vec2, p3: Vec2) {
+        self.contour.push(PathSegment::Cubic(CubicSegment::new(self.current, p1, p2, p3)));
+        self.current = p3;
+    }
+
+    // Close current contour with a line back to its start point (if needed)
+    pub fn close(&mut self) {
+        if self.contour.is_empty() {
+            return;
+        }
+        if self.current.x != self.start.x || self.current.y != self.start.y {
+            let start = self.start;
+            self.line_to(start);
+        }
+        let contour = mem::replace(&mut self.contour, Vec::new());
+        self.outline.contours.push(contour);
+        self.current = self.start;
+    }
+
+    pub fn finish(mut self) -> Outline {
+        self.close();
+        self.outline
+    }
+}
+
+
+// Tests
+// -----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_closes_contours() {
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(0.0, 0.0));
+        builder.line_to(Vec2::new(0.0, 2.0));
+        builder.quad_to(Vec2::new(1.0, 3.0), Vec2::new(2.0, 2.0));
+        // Second contour is started without closing the first one
+        builder.move_to(Vec2::new(5.0, 0.0));
+        builder.line_to(Vec2::new(5.0, 1.0));
+        builder.line_to(Vec2::new(6.0, 1.0));
+        builder.close();
+        let outline = builder.finish();
+        assert_eq!(outline.contours.len(), 2);
+        assert_eq!(outline.contours[0].len(), 3);
+        assert_eq!(outline.contours[1].len(), 3);
+        match outline.contours[0][2] {
+            PathSegment::Line(ref s) => assert_eq!((s.p1.x, s.p1.y), (0.0, 0.0)),
+            _ => panic!("expected closing line"),
+        }
+        assert_eq!(outline.control_box(), (0.0, 0.0, 6.0, 3.0));
+    }
+
+    #[test]
+    fn test_svg_path() {
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(0.0, 0.0));
+        builder.line_to(Vec2::new(1.5, 3.0));
+        builder.line_to(Vec2::new(3.0, 0.0));
+        builder.move_to(Vec2::new(1.0, 1.0));
+        builder.quad_to(Vec2::new(1.5, 2.0), Vec2::new(2.0, 1.0));
+        builder.curve_to(Vec2::new(2.0, 0.5), Vec2::new(1.0, 0.5), Vec2::new(1.0, 1.0));
+        let outline = builder.finish();
+        assert_eq!(outline.svg_path(), "M0 0 L1.5 3 L3 0 Z M1 1 Q1.5 2 2 1 C2 0.5 1 0.5 1 1 Z");
+        assert_eq!(Outline::new().svg_path(), "");
+    }
+
+    #[test]
+    fn test_transform() {
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(0.0, 0.0));
+        builder.line_to(Vec2::new(0.0, 2.0));
+        builder.curve_to(Vec2::new(1.0, 3.0), Vec2::new(2.0, 3.0), Vec2::new(3.0, 2.0));
+        let mut outline = builder.finish();
+        outline.transform(&Transform::identity());
+        assert_eq!(outline.control_box(), (0.0, 0.0, 3.0, 3.0));
+        outline.transform(&Transform { dx: 1.0, .. Transform::scale(2.0, 0.5) });
+        assert_eq!(outline.control_box(), (1.0, 0.0, 7.0, 1.5));
+        assert!(!outline.reverse_fill);
+        outline.transform(&Transform::scale(-1.0, 1.0));
+        assert_eq!(outline.control_box(), (-7.0, 0.0, -1.0, 1.5));
+        assert!(outline.reverse_fill);
+        // Composition
+        let shift = Transform { dx: 1.0, .. Transform::identity() };
+        let m = shift.then(&Transform::scale(2.0, 0.5));
+        assert_eq!(m, Transform { dx: 2.0, .. Transform::scale(2.0, 0.5) });
+        assert_eq!(m.apply(Vec2::new(1.0, 2.0)), Vec2::new(4.0, 1.0));
+    }
+
+    #[test]
+    fn test_embolden() {
+        let mut builder = OutlineBuilder::new();
+        builder.move_to(Vec2::new(0.0, 0.0));
+        builder.line_to(Vec2::new(0.0, 4.0));
+        builder.quad_to(Vec2::new(2.0, 6.0), Vec2::new(4.0, 4.0));
+        builder.line_to(Vec2::new(4.0, 0.0));
+        let mut outline = builder.finish();
+        outline.embolden(1.0);
+        assert_eq!(outline.contours[0].len(), 4);
+        let (xmin, ymin, xmax, ymax) = outline.control_box();
+        assert!((xmin + 0.5).abs() < 1e-5);
+        assert!((ymin + 0.5).abs() < 1e-5);
+        assert!((xmax - 4.5).abs() < 1e-5);
+        assert!(ymax > 6.0);
+        // Still closed and connected
+        match (&outline.contours[0][0], &outline.contours[0][3]) {
+            (&PathSegment::Line(ref first), &PathSegment::Line(ref last)) =>
+                assert_eq!((first.p0.x, first.p0.y), (last.p1.x, last.p1.y)),
+            _ => panic!("expected lines"),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use roots;
 use curve::*;
 
@@ -13,13 +14,104 @@ impl OrientedCrossing {
     }
 }
 
+// Maximum recursion depth of `Rasterizer::push_bezier3`
+const MAX_BEZIER3_DEPTH: usize = 4;
+
+/// Rule deciding which areas are filled, from winding number (wn) at a point
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FillRule {
+    // inside where wn != 0
+    NonZero,
+    // inside where wn is odd
+    EvenOdd,
+}
+
+impl FillRule {
+    pub fn is_inside(self, wn: i32) -> bool {
+        match self {
+            FillRule::NonZero => wn != 0,
+            FillRule::EvenOdd => (wn & 1) != 0,
+        }
+    }
+}
+
+/// Which end of a profile's Y range is crossed by a scanline
+///
+/// A scanline at exactly the Y of a horizontal edge or a vertex
+/// crosses only the profiles whose interval includes it.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ScanlineInterval {
+    // y0 <= y < y1: a horizontal edge belongs to the area above it (default)
+    BottomInclusive,
+    // y0 < y <= y1: a horizontal edge belongs to the area below it
+    TopInclusive,
+}
+
+impl ScanlineInterval {
+    // Does scanline `y` cross profile spanning y0 < y1?
+    pub fn contains(self, y: f32, y0: f32, y1: f32) -> bool {
+        match self {
+            ScanlineInterval::BottomInclusive => y >= y0 && y < y1,
+            ScanlineInterval::TopInclusive => y > y0 && y <= y1,
+        }
+    }
+}
+
+// X of the profile's end point (`first` or `last`) at scanline `y`, if it's there.
+// The root finders could drift from the vertex, the neighboring profiles
+// then wouldn't meet.
+fn vertex_crossing(y: f32, first: Vec2, last: Vec2) -> Option<f32> {
+    if y == first.y {
+        Some(first.x)
+    } else if y == last.y {
+        Some(last.x)
+    } else {
+        None
+    }
+}
+
+// Y range of a profile (monotonic in Y, y0 < y1)
+trait YRange {
+    fn y_range(&self) -> (f32, f32);
+}
+
+// The profiles which may cross scanline `y`. With profiles sorted by y0
+// and the largest height of them (see `Rasterizer::finalize`), it's the run
+// with y - height <= y0 <= y, otherwise all of them.
+fn scanline_candidates<P: YRange>(profiles: &[P], y: f32, max_height: Option<f32>) -> &[P] {
+    match max_height {
+        Some(height) => {
+            let first = |bound: f32, inclusive: bool| {
+                profiles.binary_search_by(|prf| {
+                    let y0 = prf.y_range().0;
+                    if y0 < bound || (inclusive && y0 == bound) { Ordering::Less } else { Ordering::Greater }
+                }).unwrap_err()
+            };
+            // A few ulps of slack, so the profile ending exactly at `y` isn't lost
+            // to the rounding of y0 + height
+            let slack = (y.abs() + height) * 1e-6;
+            let (start, end) = (first(y - height - slack, false), first(y, true));
+            &profiles[start .. end.max(start)]
+        }
+        None => profiles,
+    }
+}
+
 #[derive(Clone, Debug)]
-pub struct LinearProfile {
+pub(crate) struct LinearProfile {
     dir: i8,
     p0: Vec2,
     p1: Vec2,
 }
 
+impl YRange for LinearProfile {
+    fn y_range(&self) -> (f32, f32) {
+        (self.p0.y, self.p1.y)
+    }
+}
+
 impl LinearProfile {
     pub fn new(dir: i8, p0: Vec2, p1: Vec2) -> Self {
         LinearProfile {
@@ -31,13 +123,19 @@ impl LinearProfile {
 }
 
 #[derive(Clone, Debug)]
-pub struct QuadraticProfile {
+pub(crate) struct QuadraticProfile {
     dir: i8,
     p0: Vec2,
     p1: Vec2,
     p2: Vec2,
 }
 
+impl YRange for QuadraticProfile {
+    fn y_range(&self) -> (f32, f32) {
+        (self.p0.y, self.p2.y)
+    }
+}
+
 impl QuadraticProfile {
     pub fn new(dir: i8, p0: Vec2, p1: Vec2, p2: Vec2) -> Self {
         QuadraticProfile {
@@ -50,7 +148,7 @@ impl QuadraticProfile {
 }
 
 #[derive(Clone, Debug)]
-pub struct CubicProfile {
+pub(crate) struct CubicProfile {
     dir: i8,
     p0: Vec2,
     p1: Vec2,
@@ -58,6 +156,12 @@ pub struct CubicProfile {
     p3: Vec2,
 }
 
+impl YRange for CubicProfile {
+    fn y_range(&self) -> (f32, f32) {
+        (self.p0.y, self.p3.y)
+    }
+}
+
 impl CubicProfile {
     pub fn new(dir: i8, p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2) -> Self {
         CubicProfile {
@@ -86,9 +190,14 @@ impl CubicProfile {
  */
 #[derive(Clone, Debug)]
 pub struct Rasterizer {
-    pub linear_profiles: Vec<LinearProfile>,
-    pub quadratic_profiles: Vec<QuadraticProfile>,
-    pub cubic_profiles: Vec<CubicProfile>,
+    pub(crate) linear_profiles: Vec<LinearProfile>,
+    pub(crate) quadratic_profiles: Vec<QuadraticProfile>,
+    pub(crate) cubic_profiles: Vec<CubicProfile>,
+    // interval convention for the scanlines
+    pub interval: ScanlineInterval,
+    // largest height of linear, quadratic and cubic profile, when the profiles
+    // are sorted by their bottom Y (see `finalize`)
+    max_heights: Option<[f32; 3]>,
 }
 
 impl Rasterizer {
@@ -97,35 +206,137 @@ impl Rasterizer {
             linear_profiles: Vec::new(),
             quadratic_profiles: Vec::new(),
             cubic_profiles: Vec::new(),
+            interval: ScanlineInterval::BottomInclusive,
+            max_heights: None,
+        }
+    }
+
+    // Sort the profiles by their bottom Y, so the scanlines skip the profiles
+    // out of their reach (the crossings stay the same). Call after all curves
+    // were pushed, pushing more curves drops the order.
+    pub fn finalize(&mut self) {
+        fn sort<P: YRange>(profiles: &mut Vec<P>) -> f32 {
+            profiles.sort_by(|a, b| a.y_range().0.partial_cmp(&b.y_range().0)
+                                     .unwrap_or(Ordering::Equal));
+            profiles.iter().map(|prf| { let (y0, y1) = prf.y_range(); y1 - y0 })
+                .fold(0.0, |a, b| a.max(b))
         }
+        self.max_heights = Some([sort(&mut self.linear_profiles),
+                                 sort(&mut self.quadratic_profiles),
+                                 sort(&mut self.cubic_profiles)]);
     }
 
+    // Crossings of scanline `y`, sorted by X.
+    //
+    // Each profile is monotonic in Y and its interval is half-open
+    // (see `ScanlineInterval`), so a scanline passing through a vertex shared
+    // by two consecutive profiles crosses exactly one of them (or none,
+    // if the vertex is a Y extremum). The horizontal segments have no profiles.
+    // A crossing at the profile's end point is placed exactly at the vertex.
     pub fn scanline_crossings(&self, y: f32) -> Vec<OrientedCrossing> {
         let mut crossings = Vec::<OrientedCrossing>::new();
-        for prf in &self.linear_profiles {
-            if y >= prf.p0.y && y < prf.p1.y {
-                let x = line_intersection(y, prf.p0, prf.p1);
+        self.scanline_crossings_into(y, &mut crossings);
+        crossings
+    }
+
+    // Like `scanline_crossings`, into a buffer reused between the scanlines
+    // (it's cleared first)
+    pub fn scanline_crossings_into(&self, y: f32, crossings: &mut Vec<OrientedCrossing>) {
+        crossings.clear();
+        let heights = self.max_heights;
+        for prf in scanline_candidates(&self.linear_profiles, y, heights.map(|h| h[0])) {
+            if self.interval.contains(y, prf.p0.y, prf.p1.y) {
+                let x = vertex_crossing(y, prf.p0, prf.p1)
+                    .unwrap_or_else(|| line_intersection(y, prf.p0, prf.p1));
                 crossings.push(OrientedCrossing::new(prf.dir, x));
             }
         }
-        for prf in &self.quadratic_profiles {
-            if y >= prf.p0.y && y < prf.p2.y {
-                let x = quadratic_intersection(y, prf.p0, prf.p1, prf.p2);
+        for prf in scanline_candidates(&self.quadratic_profiles, y, heights.map(|h| h[1])) {
+            if self.interval.contains(y, prf.p0.y, prf.p2.y) {
+                let x = vertex_crossing(y, prf.p0, prf.p2)
+                    .unwrap_or_else(|| quadratic_intersection(y, prf.p0, prf.p1, prf.p2));
                 crossings.push(OrientedCrossing::new(prf.dir, x));
             }
         }
-        for prf in &self.cubic_profiles {
-            if y >= prf.p0.y && y < prf.p3.y {
-                let x = cubic_intersection(y, prf.p0, prf.p1, prf.p2, prf.p3);
+        for prf in scanline_candidates(&self.cubic_profiles, y, heights.map(|h| h[2])) {
+            if self.interval.contains(y, prf.p0.y, prf.p3.y) {
+                let x = vertex_crossing(y, prf.p0, prf.p3)
+                    .unwrap_or_else(|| cubic_intersection(y, prf.p0, prf.p1, prf.p2, prf.p3));
                 crossings.push(OrientedCrossing::new(prf.dir, x));
             }
         }
-        crossings.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap());
+        // A degenerate curve could slip through the solvers as NaN,
+        // such crossing has no place on the scanline
+        let count = crossings.len();
+        crossings.retain(|c| !c.x.is_nan());
+        if crossings.len() != count {
+            warn!("dropped {} NaN crossings at scanline y = {}", count - crossings.len(), y);
+        }
+        // Ties are ordered by direction, the order doesn't depend on the profiles' order
+        crossings.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap().then(a.dir.cmp(&b.dir)));
         //println!("{} {:?}", y, crossings);
-        crossings
+    }
+
+    // Winding number at point `p`: sum of directions of the crossings
+    // at scanline `p.y` which are left of `p.x` (or at it)
+    pub fn winding_number(&self, p: Vec2) -> i32 {
+        self.scanline_crossings(p.y).iter()
+            .take_while(|c| c.x <= p.x)
+            .map(|c| c.dir as i32)
+            .sum()
+    }
+
+    // Filled spans at scanline `y`: (x0, x1) such that x0 <= x < x1 is inside
+    pub fn scanline_spans(&self, y: f32, rule: FillRule) -> Vec<(f32, f32)> {
+        let mut spans = Vec::new();
+        let mut wn = 0i32;
+        let mut start = 0.0;
+        for c in self.scanline_crossings(y) {
+            let was_inside = rule.is_inside(wn);
+            wn += c.dir as i32;
+            let inside = rule.is_inside(wn);
+            if inside && !was_inside {
+                start = c.x;
+            } else if was_inside && !inside && c.x > start {
+                spans.push((start, c.x));
+            }
+        }
+        spans
+    }
+
+    // Coverage of pixels along scanline `y` (a horizontal line, no height):
+    // add the covered length of [xmin + i, xmin + i + 1) to `coverage[i]`.
+    // The span ends are exact, so the boundary pixels get the fractional part.
+    pub fn scanline_coverage(&self, y: f32, rule: FillRule, xmin: f32, coverage: &mut [f32]) {
+        let width = coverage.len() as f32;
+        for (x0, x1) in self.scanline_spans(y, rule) {
+            let a = (x0 - xmin).max(0.0).min(width);
+            let b = (x1 - xmin).max(0.0).min(width);
+            if a >= b {
+                continue;
+            }
+            let (ia, ib) = (a.floor() as usize, b.floor() as usize);
+            if ia == ib {
+                coverage[ia] += b - a;
+                continue;
+            }
+            coverage[ia] += (ia + 1) as f32 - a;
+            for c in coverage[ia + 1 .. ib].iter_mut() {
+                *c += 1.0;
+            }
+            if ib < coverage.len() {
+                coverage[ib] += b - ib as f32;
+            }
+        }
+    }
+
+    // Is point `p` inside the filled area?
+    pub fn contains(&self, p: Vec2, rule: FillRule) -> bool {
+        rule.is_inside(self.winding_number(p))
     }
 
     pub fn push_line(&mut self, p0: Vec2, p1: Vec2) {
+        self.max_heights = None;
         if p0.y < p1.y {
             self.linear_profiles.push(LinearProfile::new(1, p0, p1));
         }
@@ -139,17 +350,16 @@ impl Rasterizer {
         let t = (p0.y - p1.y) / (p0.y - 2.0 * p1.y + p2.y);
         if t.is_finite() && 0.0 < t && t < 1.0 {
             // one extremum found, split the curve at `t`
-            let m0 = p0.lerp(p1, t);
-            let m1 = p1.lerp(p2, t);
-            let n0 = m0.lerp(m1, t);
-            self.push_bezier2_monotonic(p0, m0, n0);
-            self.push_bezier2_monotonic(n0, m1, p2);
+            let (a, b) = QuadraticSegment::new(p0, p1, p2).split(t);
+            self.push_bezier2_monotonic(a.p0, a.p1, a.p2);
+            self.push_bezier2_monotonic(b.p0, b.p1, b.p2);
         } else {
             self.push_bezier2_monotonic(p0, p1, p2);
         }
     }
 
     pub fn push_bezier2_monotonic(&mut self, p0: Vec2, p1: Vec2, p2: Vec2) {
+        self.max_heights = None;
         if p0.y < p2.y {
             self.quadratic_profiles.push(QuadraticProfile::new(1, p0, p1, p2));
         }
@@ -159,45 +369,62 @@ impl Rasterizer {
     }
 
     pub fn push_bezier3(&mut self, p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2) {
+        self.push_bezier3_split(p0, p1, p2, p3, 0)
+    }
+
+    // Split the curve at Y extrema. The recursion normally ends at depth 1
+    // (at most two extrema), the limit only guards against floating point
+    // cases which make no progress (an extremum found again at the split point).
+    fn push_bezier3_split(&mut self, p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, depth: usize) {
         // check the curve for Y extrema
         let a = p3.y - 3.0*p2.y + 3.0*p1.y - p0.y;
         let b = 2.0*(p2.y - 2.0*p1.y + p0.y);
         let c = p1.y - p0.y;
         let found_roots = roots::find_roots_quadratic(a, b, c);
-        let extrema: Vec<f32> = found_roots.as_ref().iter().cloned()
+        let mut extrema: Vec<f32> = found_roots.as_ref().iter().cloned()
             .filter(|&t| t.is_finite() && 0.0 < t && t < 1.0 ).collect();
+        if depth >= MAX_BEZIER3_DEPTH {
+            // Split at all the extrema without recursing further. Treating
+            // the curve as monotonic would drop it when its ends have equal Y.
+            extrema.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let mut rest = CubicSegment::new(p0, p1, p2, p3);
+            let mut start = 0.0;
+            for &t in extrema.iter() {
+                let (head, tail) = rest.split((t - start) / (1.0 - start));
+                self.push_bezier3_monotonic(head.p0, head.p1, head.p2, head.p3);
+                rest = tail;
+                start = t;
+            }
+            return self.push_bezier3_monotonic(rest.p0, rest.p1, rest.p2, rest.p3);
+        }
         if extrema.len() == 0 {
             // No extrema, the curve is monotonic
             self.push_bezier3_monotonic(p0, p1, p2, p3)
         } else {
             // one or more extrema found, split the curve at `t`
             let t1 = extrema[0];
-            let m0 = p0.lerp(p1, t1);
-            let m1 = p1.lerp(p2, t1);
-            let m2 = p2.lerp(p3, t1);
-            let n0 = m0.lerp(m1, t1);
-            let n1 = m1.lerp(m2, t1);
-            let o0 = n0.lerp(n1, t1);
+            let (a, b) = CubicSegment::new(p0, p1, p2, p3).split(t1);
             if extrema.len() > 1 {
                 // If there is second extremum, split the curve recursively
                 // (we could also do double split in one go as an optimization)
                 debug_assert!(extrema.len() == 2);
                 let t2 = extrema[1];
                 if t2 > t1 {
-                    self.push_bezier3_monotonic(p0, m0, n0, o0);
-                    self.push_bezier3(o0, n1, m2, p3);
+                    self.push_bezier3_monotonic(a.p0, a.p1, a.p2, a.p3);
+                    self.push_bezier3_split(b.p0, b.p1, b.p2, b.p3, depth + 1);
                 } else {
-                    self.push_bezier3(p0, m0, n0, o0);
-                    self.push_bezier3_monotonic(o0, n1, m2, p3);
+                    self.push_bezier3_split(a.p0, a.p1, a.p2, a.p3, depth + 1);
+                    self.push_bezier3_monotonic(b.p0, b.p1, b.p2, b.p3);
                 }
             } else {
-                self.push_bezier3_monotonic(p0, m0, n0, o0);
-                self.push_bezier3_monotonic(o0, n1, m2, p3);
+                self.push_bezier3_monotonic(a.p0, a.p1, a.p2, a.p3);
+                self.push_bezier3_monotonic(b.p0, b.p1, b.p2, b.p3);
             }
         }
     }
 
     pub fn push_bezier3_monotonic(&mut self, p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2) {
+        self.max_heights = None;
         if p0.y < p3.y {
             self.cubic_profiles.push(CubicProfile::new(1, p0, p1, p2, p3));
         }
@@ -206,3 +433,287 @@ impl Rasterizer {
         }
     }
 }
+
+
+// Tests
+// -----
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use super::*;
+
+    // Closed polygon from points
+    fn push_polygon(rasterizer: &mut Rasterizer, points: &[(f32, f32)]) {
+        for i in 0 .. points.len() {
+            let (x0, y0) = points[i];
+            let (x1, y1) = points[(i + 1) % points.len()];
+            rasterizer.push_line(Vec2::new(x0, y0), Vec2::new(x1, y1));
+        }
+    }
+
+    #[test]
+    fn test_contains() {
+        let mut rasterizer = Rasterizer::new();
+        // Two overlapping squares of same orientation
+        push_polygon(&mut rasterizer, &[(0.0, 0.0), (0.0, 4.0), (4.0, 4.0), (4.0, 0.0)]);
+        push_polygon(&mut rasterizer, &[(2.0, 2.0), (2.0, 6.0), (6.0, 6.0), (6.0, 2.0)]);
+        let p = |x, y| Vec2::new(x, y);
+        assert_eq!(rasterizer.winding_number(p(3.0, 3.0)), 2);
+        assert!(rasterizer.contains(p(1.0, 1.0), FillRule::NonZero));
+        assert!(rasterizer.contains(p(3.0, 3.0), FillRule::NonZero));
+        assert!(!rasterizer.contains(p(3.0, 3.0), FillRule::EvenOdd));
+        assert!(rasterizer.contains(p(5.0, 5.0), FillRule::EvenOdd));
+        assert!(!rasterizer.contains(p(5.0, 1.0), FillRule::NonZero));
+        assert!(!rasterizer.contains(p(-1.0, 1.0), FillRule::NonZero));
+        assert_eq!(rasterizer.scanline_spans(3.0, FillRule::NonZero), vec![(0.0, 6.0)]);
+        assert_eq!(rasterizer.scanline_spans(3.0, FillRule::EvenOdd), vec![(0.0, 2.0), (4.0, 6.0)]);
+        assert_eq!(rasterizer.scanline_spans(1.0, FillRule::EvenOdd), vec![(0.0, 4.0)]);
+    }
+
+    #[test]
+    fn test_scanline_interval() {
+        // Rectangles with horizontal edges at integer Y, sampled at integer Y
+        let rects = [(0.0, 0.0, 3.0, 2.0), (1.0, 2.0, 4.0, 5.0), (-2.0, -3.0, 0.5, 1.0)];
+        for &interval in [ScanlineInterval::BottomInclusive, ScanlineInterval::TopInclusive].iter() {
+            let mut rasterizer = Rasterizer::new();
+            rasterizer.interval = interval;
+            for &(x0, y0, x1, y1) in rects.iter() {
+                push_polygon(&mut rasterizer, &[(x0, y0), (x0, y1), (x1, y1), (x1, y0)]);
+            }
+            for y in -4 .. 7 {
+                for x in -3 .. 5 {
+                    let p = Vec2::new(x as f32 + 0.25, y as f32);
+                    // Reference: point in any rectangle under the same convention
+                    let expected = rects.iter().any(|&(x0, y0, x1, y1)| {
+                        p.x >= x0 && p.x < x1 && interval.contains(p.y, y0, y1)
+                    });
+                    assert_eq!(rasterizer.contains(p, FillRule::NonZero), expected,
+                               "{:?} at {:?}", interval, p);
+                }
+            }
+        }
+        // The edge at y = 2 switches sides
+        let mut rasterizer = Rasterizer::new();
+        push_polygon(&mut rasterizer, &[(0.0, 0.0), (0.0, 2.0), (3.0, 2.0), (3.0, 0.0)]);
+        assert!(!rasterizer.contains(Vec2::new(1.0, 2.0), FillRule::NonZero));
+        rasterizer.interval = ScanlineInterval::TopInclusive;
+        assert!(rasterizer.contains(Vec2::new(1.0, 2.0), FillRule::NonZero));
+        assert!(!rasterizer.contains(Vec2::new(1.0, 0.0), FillRule::NonZero));
+    }
+
+    #[test]
+    fn test_shared_vertices() {
+        // Rectangle with extra vertices on the vertical edges, a hexagon
+        // with vertices in the middle rows
+        let shapes: [&[(f32, f32)]; 2] = [
+            &[(0.0, 0.0), (0.0, 1.0), (0.0, 3.0), (4.0, 3.0), (4.0, 2.0), (4.0, 0.0)],
+            &[(2.0, -2.0), (0.0, 0.0), (0.0, 1.0), (2.0, 3.0), (4.0, 1.0), (4.0, 0.0)],
+        ];
+        for &interval in [ScanlineInterval::BottomInclusive, ScanlineInterval::TopInclusive].iter() {
+            for points in shapes.iter() {
+                let mut rasterizer = Rasterizer::new();
+                rasterizer.interval = interval;
+                push_polygon(&mut rasterizer, points);
+                // Interior scanlines through the vertex rows
+                for &y in [1.0, 2.0].iter() {
+                    let crossings = rasterizer.scanline_crossings(y);
+                    assert_eq!(crossings.len(), 2, "{:?} {:?} at y = {}", interval, points, y);
+                    for &x in [1.5, 2.0, 2.5].iter() {
+                        assert_eq!(rasterizer.winding_number(Vec2::new(x, y)), 1,
+                                   "{:?} {:?} at ({}, {})", interval, points, x, y);
+                    }
+                }
+            }
+        }
+
+        // Circle made of quadratic arcs, joined at the Y extrema and at y = 0
+        let mut rasterizer = Rasterizer::new();
+        let p = |x, y| Vec2::new(x, y);
+        rasterizer.push_bezier2(p(-2.0, 0.0), p(-2.0, 2.0), p(0.0, 2.0));
+        rasterizer.push_bezier2(p(0.0, 2.0), p(2.0, 2.0), p(2.0, 0.0));
+        rasterizer.push_bezier2(p(2.0, 0.0), p(2.0, -2.0), p(0.0, -2.0));
+        rasterizer.push_bezier2(p(0.0, -2.0), p(-2.0, -2.0), p(-2.0, 0.0));
+        let crossings = rasterizer.scanline_crossings(0.0);
+        assert_eq!(crossings.iter().map(|c| (c.dir, c.x)).collect::<Vec<_>>(),
+                   vec![(1, -2.0), (-1, 2.0)]);
+        assert_eq!(rasterizer.winding_number(p(0.0, 0.0)), 1);
+        // The top is a Y extremum, the scanline doesn't cross it.
+        // The bottom crosses both arcs at the same point, they cancel out.
+        assert!(rasterizer.scanline_crossings(2.0).is_empty());
+        assert_eq!(rasterizer.scanline_crossings(-2.0).len(), 2);
+        assert_eq!(rasterizer.winding_number(p(0.5, -2.0)), 0);
+    }
+
+    #[test]
+    fn test_scanline_crossings_into() {
+        let mut rasterizer = Rasterizer::new();
+        push_polygon(&mut rasterizer, &[(0.0, 0.0), (0.0, 4.0), (4.0, 4.0), (4.0, 0.0)]);
+        push_polygon(&mut rasterizer, &[(2.0, 2.0), (2.0, 6.0), (6.0, 6.0), (6.0, 2.0)]);
+        rasterizer.push_bezier2(Vec2::new(1.0, -1.0), Vec2::new(3.0, 7.0), Vec2::new(5.0, -1.0));
+        let key = |c: &OrientedCrossing| (c.dir, c.x);
+        // Stale content is replaced, the buffer shrinks and grows as needed
+        let mut buffer = vec![OrientedCrossing::new(1, 100.0); 10];
+        for &y in [3.0, 5.0, -2.0, 1.5, 3.0].iter() {
+            rasterizer.scanline_crossings_into(y, &mut buffer);
+            assert_eq!(buffer.iter().map(&key).collect::<Vec<_>>(),
+                       rasterizer.scanline_crossings(y).iter().map(&key).collect::<Vec<_>>());
+        }
+        rasterizer.scanline_crossings_into(3.0, &mut buffer);
+        assert_eq!(buffer.len(), 4);
+        rasterizer.scanline_crossings_into(-2.0, &mut buffer);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_finalize() {
+        // Wavy ring of lines, quadratic and cubic arcs (like a dense glyph)
+        let mut rasterizer = Rasterizer::new();
+        let n = 48;
+        let point = |i: f32, r: f32| {
+            let angle = i / n as f32 * 2.0 * ::std::f32::consts::PI;
+            let r = r + (i as usize % 3) as f32 * 0.5;
+            Vec2::new(10.0 + r * angle.cos(), 10.0 + r * angle.sin())
+        };
+        for i in 0 .. n {
+            let (p0, p1) = (point(i as f32, 8.0), point((i + 1) as f32, 8.0));
+            // Control points bulge along X only, the arcs stay monotonic in Y
+            let pm = |t: f32| p0.lerp(p1, t) + Vec2::new(0.4, 0.0);
+            match i % 3 {
+                0 => rasterizer.push_line(p0, p1),
+                1 => rasterizer.push_bezier2(p0, pm(0.5), p1),
+                _ => rasterizer.push_bezier3(p0, pm(0.2), pm(0.9), p1),
+            }
+            // Inner contour, opposite direction
+            rasterizer.push_line(point((n - i) as f32, 3.0), point((n - i - 1) as f32, 3.0));
+        }
+        let mut finalized = rasterizer.clone();
+        finalized.finalize();
+        let key = |c: &OrientedCrossing| (c.dir, c.x);
+        let mut scanlines: Vec<f32> = (0 .. 200).map(|k| k as f32 * 0.1 - 0.05).collect();
+        // Exactly at the vertices
+        scanlines.extend((0 .. n).map(|i| point(i as f32, 8.0).y));
+        scanlines.extend((0 .. n).map(|i| point(i as f32, 3.0).y));
+        for &interval in [ScanlineInterval::BottomInclusive, ScanlineInterval::TopInclusive].iter() {
+            rasterizer.interval = interval;
+            finalized.interval = interval;
+            for &y in &scanlines {
+                assert_eq!(finalized.scanline_crossings(y).iter().map(&key).collect::<Vec<_>>(),
+                           rasterizer.scanline_crossings(y).iter().map(&key).collect::<Vec<_>>(),
+                           "{:?} at y = {}", interval, y);
+            }
+        }
+        // Pushing more curves drops the order, the result is still right
+        let square = [(1.0, 1.0), (1.0, 2.0), (2.0, 2.0), (2.0, 1.0)];
+        push_polygon(&mut rasterizer, &square);
+        push_polygon(&mut finalized, &square);
+        for &y in &scanlines {
+            assert_eq!(finalized.scanline_crossings(y).len(), rasterizer.scanline_crossings(y).len());
+        }
+    }
+
+    #[test]
+    fn test_nan_crossings() {
+        let p = |x, y| Vec2::new(x, y);
+        let mut rasterizer = Rasterizer::new();
+        push_polygon(&mut rasterizer, &[(0.0, 0.0), (0.0, 4.0), (4.0, 4.0), (4.0, 0.0)]);
+        // Near-horizontal quadratic
+        rasterizer.push_bezier2(p(1.0, 2.0), p(2.0, 2.0 + 1e-7), p(3.0, 2.0 + 2e-7));
+        for &y in [2.0, 2.0 + 1e-7, 2.0 + 2e-7].iter() {
+            let crossings = rasterizer.scanline_crossings(y);
+            assert!(crossings.iter().all(|c| !c.x.is_nan()));
+        }
+        // Profile which evaluates to NaN is dropped
+        rasterizer.quadratic_profiles.push(
+            QuadraticProfile::new(1, p(2.0, 1.0), p(f32::NAN, 2.0), p(2.0, 3.0)));
+        let crossings = rasterizer.scanline_crossings(1.5);
+        assert_eq!(crossings.iter().map(|c| (c.dir, c.x)).collect::<Vec<_>>(),
+                   vec![(1, 0.0), (-1, 4.0)]);
+        assert_eq!(rasterizer.winding_number(p(2.0, 1.5)), 1);
+    }
+
+    #[test]
+    fn test_scanline_coverage() {
+        let mut rasterizer = Rasterizer::new();
+        // Right edge is diagonal: x = 4 + y
+        push_polygon(&mut rasterizer, &[(0.5, 0.0), (0.5, 4.0), (8.0, 4.0), (4.0, 0.0)]);
+        let mut coverage = [0.0; 8];
+        rasterizer.scanline_coverage(2.5, FillRule::NonZero, 0.0, &mut coverage);
+        assert_eq!(coverage, [0.5, 1.0, 1.0, 1.0, 1.0, 1.0, 0.5, 0.0]);
+        // Clipped by the pixel range
+        let mut coverage = [0.0; 4];
+        rasterizer.scanline_coverage(2.5, FillRule::NonZero, 4.0, &mut coverage);
+        assert_eq!(coverage, [1.0, 1.0, 0.5, 0.0]);
+        // Pixel crossed by the diagonal: the coverage ramps up with y
+        let mut last = 0.0;
+        for k in 0 .. 8 {
+            let mut coverage = [0.0; 8];
+            let y = 2.0 + (k as f32 + 0.5) / 8.0;
+            rasterizer.scanline_coverage(y, FillRule::NonZero, 0.0, &mut coverage);
+            assert!(coverage[6] > last);
+            last = coverage[6];
+        }
+    }
+
+    #[test]
+    fn test_bezier2_extremum() {
+        // y = 8t(1 - t), maximum y = 2 at t = 0.5; x = 4t
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.push_bezier2(Vec2::new(0.0, 0.0), Vec2::new(2.0, 4.0), Vec2::new(4.0, 0.0));
+        let dirs: Vec<i8> = rasterizer.quadratic_profiles.iter().map(|prf| prf.dir).collect();
+        assert_eq!(dirs, vec![1, -1]);
+        for prf in rasterizer.quadratic_profiles.iter() {
+            assert!(prf.p0.y <= prf.p1.y && prf.p1.y <= prf.p2.y);
+            assert_eq!(prf.p2, Vec2::new(2.0, 2.0));
+        }
+        // y = 1 at t = (1 -+ 1/sqrt(2)) / 2
+        let crossings = rasterizer.scanline_crossings(1.0);
+        assert_eq!(crossings.len(), 2);
+        let sqrt_half = 0.5f32.sqrt();
+        assert!((crossings[0].x - 2.0 * (1.0 - sqrt_half)).abs() < 1e-5);
+        assert!((crossings[1].x - 2.0 * (1.0 + sqrt_half)).abs() < 1e-5);
+        assert_eq!((crossings[0].dir, crossings[1].dir), (1, -1));
+        // Above the extremum
+        assert!(rasterizer.scanline_crossings(2.5).is_empty());
+
+        // Extremum at the end points, no split
+        for &(p1, p2) in [((2.0, 0.0), (4.0, 4.0)), ((2.0, 4.0), (4.0, 4.0))].iter() {
+            let mut rasterizer = Rasterizer::new();
+            rasterizer.push_bezier2(Vec2::new(0.0, 0.0), Vec2::new(p1.0, p1.1), Vec2::new(p2.0, p2.1));
+            assert_eq!(rasterizer.quadratic_profiles.len(), 1);
+            assert_eq!(rasterizer.quadratic_profiles[0].dir, 1);
+        }
+    }
+
+    #[test]
+    fn test_bezier3_two_extrema() {
+        // S-curve in Y: up, down, up again
+        let (p0, p1, p2, p3) = (Vec2::new(0.0, 0.0), Vec2::new(1.0, 10.0),
+                                Vec2::new(2.0, -10.0), Vec2::new(3.0, 0.0));
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.push_bezier3(p0, p1, p2, p3);
+        let mut dirs: Vec<i8> = rasterizer.cubic_profiles.iter().map(|prf| prf.dir).collect();
+        // (a tiny piece may be split off at an extremum due to rounding)
+        assert!(dirs.len() >= 3 && dirs.len() <= 3 + MAX_BEZIER3_DEPTH);
+        dirs.dedup();
+        assert_eq!(dirs, vec![1, -1, 1]);
+        for prf in rasterizer.cubic_profiles.iter() {
+            assert!(prf.p0.y <= prf.p1.y + 1e-4 && prf.p1.y <= prf.p2.y + 1e-4 &&
+                    prf.p2.y <= prf.p3.y + 1e-4);
+        }
+        // At the depth limit, the recursion stops, but the curve is still split
+        // (its ends have equal Y, the crossings of each scanline have to cancel out)
+        for &depth in [MAX_BEZIER3_DEPTH - 1, MAX_BEZIER3_DEPTH].iter() {
+            let mut rasterizer = Rasterizer::new();
+            rasterizer.push_bezier3_split(p0, p1, p2, p3, depth);
+            let mut dirs: Vec<i8> = rasterizer.cubic_profiles.iter().map(|prf| prf.dir).collect();
+            dirs.dedup();
+            assert_eq!(dirs, vec![1, -1, 1]);
+            for &y in [-2.0, -0.5, 0.5, 2.0].iter() {
+                let crossings = rasterizer.scanline_crossings(y);
+                assert_eq!(crossings.len(), 2);
+                assert_eq!(rasterizer.winding_number(Vec2::new(10.0, y)), 0);
+            }
+        }
+    }
+}
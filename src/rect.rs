@@ -0,0 +1,39 @@
+/// Rectangle in texture pixels
+///
+/// (x, y) is the top left corner, w and h are width and height.
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, w: usize, h: usize) -> Self {
+        Rect { x: x, y: y, w: w, h: h }
+    }
+
+    pub fn right(&self) -> usize {
+        self.x + self.w
+    }
+
+    pub fn bottom(&self) -> usize {
+        self.y + self.h
+    }
+
+    // Do the rectangles share any pixel?
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.right() && other.x < self.right() &&
+        self.y < other.bottom() && other.y < self.bottom()
+    }
+
+    // Smallest rectangle containing both
+    pub fn union(&self, other: &Rect) -> Rect {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Rect::new(x, y, right - x, bottom - y)
+    }
+}
@@ -0,0 +1,187 @@
+use freetype as ft;
+
+use font::*;
+use layout::*;
+use error::*;
+
+/// Handle of a font owned by `FontRegistry`
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FontId(usize);
+
+/// Collection of built fonts, referenced by `FontId`
+///
+/// Fonts are never removed, so the ids stay valid for the registry's lifetime.
+
+pub struct FontRegistry {
+    fonts: Vec<Font>,
+}
+
+impl FontRegistry {
+    pub fn new() -> Self {
+        FontRegistry {
+            fonts: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, font: Font) -> FontId {
+        self.fonts.push(font);
+        FontId(self.fonts.len() - 1)
+    }
+
+    pub fn get(&self, id: FontId) -> &Font {
+        &self.fonts[id.0]
+    }
+
+    pub fn get_mut(&mut self, id: FontId) -> &mut Font {
+        &mut self.fonts[id.0]
+    }
+
+    pub fn ids(&self) -> Vec<FontId> {
+        (0 .. self.fonts.len()).map(FontId).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fonts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fonts.is_empty()
+    }
+
+    // Lay out `text` with font `id` (see `Layout::new`)
+    pub fn layout(&self, id: FontId, text: &str, target_px: f32) -> Layout {
+        Layout::new(self.get(id), text, target_px)
+    }
+}
+
+/// Fonts of the same texture size, one per layer of a texture array
+///
+/// Each layer is a whole font (unlike channels, see `Font::build_channel_from_face`).
+/// Upload `layers_buffer` as the array texture, the font `FontId(i)` is layer `i`.
+
+pub struct FontArray {
+    layers: Vec<Font>,
+    width: usize,
+    height: usize,
+    // configuration and parameters of the fonts built by `add_face`
+    pub config: FontConfig,
+    pub params: SdfParams,
+}
+
+impl FontArray {
+    pub fn new(square_size: usize) -> Self {
+        FontArray {
+            layers: Vec::new(),
+            width: square_size,
+            height: square_size,
+            config: FontConfig::default(),
+            params: SdfParams::default(),
+        }
+    }
+
+    // Build a font from `face` (see `Font::build_from_faces`) into new layer
+    pub fn add_face(&mut self, face: &ft::Face, face_size: usize, padding: usize,
+                    chars: &str) -> Result<FontId, FontError> {
+        let mut font = Font::new(self.width);
+        font.config = self.config.clone();
+        font.params = self.params.clone();
+        font.build_from_faces(&[face], face_size, padding, chars)?;
+        Ok(self.add(font))
+    }
+
+    // Add already built font as new layer, it has to be of the array's size
+    pub fn add(&mut self, font: Font) -> FontId {
+        assert!(font.width == self.width && font.height == self.height,
+                "font {}x{} doesn't match the array layers {}x{}",
+                font.width, font.height, self.width, self.height);
+        if let Some(first) = self.layers.first() {
+            assert!(font.pitch == first.pitch && font.channels == first.channels,
+                    "font texture layout doesn't match the other layers");
+        }
+        self.layers.push(font);
+        FontId(self.layers.len() - 1)
+    }
+
+    pub fn get(&self, id: FontId) -> &Font {
+        &self.layers[id.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    // Array layer and texture coordinates of `ch` in font `id`
+    pub fn lookup(&self, id: FontId, ch: char) -> Option<(usize, (f32, f32, f32, f32))> {
+        self.glyph_info(id, ch).map(|info| (info.page, info.uv_rect))
+    }
+
+    // Like `Font::glyph_info`, the page is the array layer
+    pub fn glyph_info(&self, id: FontId, ch: char) -> Option<GlyphInfo> {
+        self.get(id).glyph_info(ch).map(|info| GlyphInfo { page: id.0, .. info })
+    }
+
+    // Texture data of all layers, one after another (empty layers are zeroed)
+    pub fn layers_buffer(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        for font in &self.layers {
+            let size = font.pitch * font.height * font.channels;
+            let start = buffer.len();
+            buffer.extend_from_slice(&font.buffer);
+            buffer.resize(start + size, 0);
+        }
+        buffer
+    }
+}
+
+
+// Tests
+// -----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font_with_glyph(ch: char, advance: f32) -> Font {
+        let mut font = Font::new(8);
+        font.face_size = 4;
+        font.glyphs.insert(ch, Glyph { width: 4, height: 4, advance_x: advance, .. Default::default() });
+        font
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = FontRegistry::new();
+        let regular = registry.add(font_with_glyph('x', 2.0));
+        let bold = registry.add(font_with_glyph('x', 3.0));
+        assert!(regular != bold);
+        assert_eq!(registry.ids(), vec![regular, bold]);
+        assert_eq!(registry.layout(regular, "xx", 4.0).advance, 4.0);
+        assert_eq!(registry.layout(bold, "xx", 4.0).advance, 6.0);
+        registry.get_mut(bold).config.snap_to_pixel = true;
+        assert!(registry.get(bold).config.snap_to_pixel);
+    }
+
+    #[test]
+    fn test_font_array() {
+        let mut array = FontArray::new(8);
+        let regular = array.add(font_with_glyph('x', 2.0));
+        let mut bold = font_with_glyph('x', 3.0);
+        bold.glyphs.get_mut(&'x').unwrap().x = 4;
+        bold.buffer = vec![7; 64];
+        let bold = array.add(bold);
+        assert_eq!(array.len(), 2);
+        assert_eq!(array.lookup(regular, 'x'), Some((0, (0.0, 0.0, 0.5, 0.5))));
+        assert_eq!(array.lookup(bold, 'x'), Some((1, (0.5, 0.0, 1.0, 0.5))));
+        assert_eq!(array.lookup(bold, 'y'), None);
+        assert_eq!(array.glyph_info(bold, 'x').unwrap().advance, 3.0);
+        let buffer = array.layers_buffer();
+        assert_eq!(buffer.len(), 2 * 64);
+        assert!(buffer[.. 64].iter().all(|&v| v == 0));
+        assert!(buffer[64 ..].iter().all(|&v| v == 7));
+    }
+}